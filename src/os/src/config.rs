@@ -1,7 +1,18 @@
+use crate::mm::address;
+
 pub const USER_STACK_SIZE: usize = 4096 * 2;
+// user stack 可以通过 page fault 向下增长，USER_STACK_LIMIT 是默认的最大长度
+pub const USER_STACK_LIMIT: usize = 0x80_0000;
 pub const KERNEL_STACK_SIZE: usize = 4096 * 2;
-pub const KERNEL_HEAP_SIZE: usize = 0x30_0000;
+// 内核堆最开始使用 .bss 中 KERNEL_HEAP_INIT_SIZE 大小的空间，不够时从页框分配器申请
+// 页框并映射到从 kernel_heap_base() 开始的区域，这个区域最大为 KERNEL_HEAP_MAX_SIZE。
+// 页框分配器的元数据不在内核堆中，所以初始空间不需要随物理内存增大。
+pub const KERNEL_HEAP_INIT_SIZE: usize = 0x4_0000;
+pub const KERNEL_HEAP_MAX_SIZE: usize = 0x200_0000;
+// 用作交换区的 RAM disk 的大小
+pub const RAM_DISK_SIZE: usize = 0x40_0000;
 
+// 物理内存的范围从设备树中获取，MEMORY_END 只在没有设备树的时候使用
 pub const MEMORY_END: usize = 0x80800000;
 
 // address space
@@ -9,9 +20,52 @@ pub const PAGE_SIZE: usize = 0x1000;
 pub const PAGE_SIZE_BITS: usize = 0xc;
 
 // high kernel/application address space
+// 虚拟地址空间的大小由分页模式决定 (Sv39 为 2^39，Sv48 为 2^48)，所以下面的地址都按照
+// paging_levels() 在运行时计算，只能在 page_table::init_paging_mode 之后使用。
+// 高半部分从 kernel_space_start() 开始，内核堆位于最低处；TRAMPOLINE 和 TRAP_CONTEXT
+// 位于最高处，kernel stack 紧挨着 TRAMPOLINE 向下排列。
 // Ref: https://rcore-os.github.io/rCore-Tutorial-Book-v3/chapter4/5kernel-app-spaces.html#id6
-pub const TRAMPOLINE: usize = usize::MAX - PAGE_SIZE + 1;
-pub const TRAP_CONTEXT: usize = TRAMPOLINE - PAGE_SIZE;
+pub fn kernel_space_start() -> usize {
+    !(half_space_size() - 1)
+}
+
+pub fn kernel_heap_base() -> usize {
+    kernel_space_start()
+}
+
+pub fn trampoline() -> usize {
+    kernel_space_start() + (half_space_size() - PAGE_SIZE)
+}
+
+pub fn trap_context() -> usize {
+    trampoline() - PAGE_SIZE
+}
+
+// user address space
+// 用户程序使用虚拟地址空间的低半部分
+pub fn user_space_end() -> usize {
+    half_space_size()
+}
+
+// mmap 没有指定地址时从 user_mmap_base() 开始查找空闲区域，程序镜像必须位于它之下
+pub fn user_mmap_base() -> usize {
+    user_space_end() / 4
+}
+
+// user stack 位于用户地址空间的顶部，heap 紧跟在程序镜像之后，两者相向增长
+pub fn user_stack_top() -> usize {
+    user_space_end()
+}
+
+// PIE 程序的最低加载地址，实际的加载地址由 ASLR 在它之上随机选择
+pub const USER_PIE_BASE: usize = 0x4000_0000;
+// 动态链接器的最低加载地址，位于 PIE 程序的随机范围之上，Sv39 下也低于 user_mmap_base()
+pub const USER_INTERP_BASE: usize = 0x8_0000_0000;
+
+// half_space_size 是虚拟地址空间低半部分 (或者高半部分) 的大小
+fn half_space_size() -> usize {
+    1 << (address::va_width() - 1)
+}
 
 // CLOCK_FREQ is clock frequency, in this case, the value is for qemu.
 pub const CLOCK_FREQ: usize = 12500000;
@@ -0,0 +1,22 @@
+mod ram_disk;
+
+use alloc::sync::Arc;
+use lazy_static::*;
+
+use ram_disk::RamDisk;
+
+// 块设备中每个块的大小 (字节)
+pub const BLOCK_SZ: usize = 512;
+
+// BlockDevice 是块设备的抽象，以块为单位读写数据
+pub trait BlockDevice: Send + Sync {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]);
+    fn write_block(&self, block_id: usize, buf: &[u8]);
+    // num_blocks 返回块设备包含的块的数量
+    fn num_blocks(&self) -> usize;
+}
+
+lazy_static! {
+    // 目前还没有块设备驱动，所以使用一块内存模拟的 RAM disk 作为交换区
+    pub static ref SWAP_DEVICE: Arc<dyn BlockDevice> = Arc::new(RamDisk::new());
+}
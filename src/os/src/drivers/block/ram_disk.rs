@@ -0,0 +1,40 @@
+use crate::{config::RAM_DISK_SIZE, sync::UPSafeCell};
+
+use super::{BlockDevice, BLOCK_SZ};
+
+// RAM disk 的存储空间位于 .bss 段中
+static mut RAM_DISK_SPACE: [u8; RAM_DISK_SIZE] = [0; RAM_DISK_SIZE];
+
+// RamDisk 使用一段内存模拟块设备
+pub struct RamDisk {
+    space: UPSafeCell<&'static mut [u8]>,
+}
+
+impl RamDisk {
+    pub fn new() -> Self {
+        Self {
+            space: unsafe {
+                UPSafeCell::new(core::slice::from_raw_parts_mut(
+                    RAM_DISK_SPACE.as_mut_ptr(),
+                    RAM_DISK_SIZE,
+                ))
+            },
+        }
+    }
+}
+
+impl BlockDevice for RamDisk {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+        let start = block_id * BLOCK_SZ;
+        buf.copy_from_slice(&self.space.exclusive_access()[start..start + BLOCK_SZ]);
+    }
+
+    fn write_block(&self, block_id: usize, buf: &[u8]) {
+        let start = block_id * BLOCK_SZ;
+        self.space.exclusive_access()[start..start + BLOCK_SZ].copy_from_slice(buf);
+    }
+
+    fn num_blocks(&self) -> usize {
+        RAM_DISK_SIZE / BLOCK_SZ
+    }
+}
@@ -3,6 +3,7 @@
     .globl _start
 _start:
     la sp, boot_stack_top # 在 OS 启动时候 sp 指向 boot_stack 的高地址，也就是 boot_stack_top
+    # a0 = hart id, a1 = 设备树的物理地址，直接传递给 rust_main
     call rust_main
 
     .section .bss.stack
@@ -0,0 +1,268 @@
+// fdt 解析 bootloader 传递过来的 flattened device tree (FDT)，获取物理内存、
+// 保留内存以及启动参数等信息。
+// Ref: https://devicetree-specification.readthedocs.io/en/stable/flattened-format.html
+
+use alloc::{string::String, vec::Vec};
+use lazy_static::*;
+
+use crate::{config, sync::UPSafeCell};
+
+const FDT_MAGIC: u32 = 0xd00d_feed;
+
+const FDT_BEGIN_NODE: u32 = 0x1;
+const FDT_END_NODE: u32 = 0x2;
+const FDT_PROP: u32 = 0x3;
+const FDT_NOP: u32 = 0x4;
+const FDT_END: u32 = 0x9;
+
+// 设备树的最大嵌套深度
+const MAX_DEPTH: usize = 16;
+// header 的大小 (version 17)，以及接受的设备树的最大长度
+const FDT_HEADER_SIZE: usize = 40;
+const MAX_FDT_SIZE: usize = 0x10_0000;
+
+// 没有设备树时使用的物理内存范围
+const FALLBACK_MEMORY_START: usize = 0x8000_0000;
+
+pub struct MachineInfo {
+    // 物理内存区域 [start, end)
+    pub memory: Vec<(usize, usize)>,
+    // 保留内存区域 [start, end)，包括 /memreserve/、/reserved-memory 和设备树本身
+    pub reserved: Vec<(usize, usize)>,
+    // /chosen 节点中的 bootargs
+    pub bootargs: String,
+    // /chosen 节点中 bootloader 提供的随机数种子，没有时为 0
+    pub rng_seed: u64,
+}
+
+impl MachineInfo {
+    fn empty() -> Self {
+        Self {
+            memory: Vec::new(),
+            reserved: Vec::new(),
+            bootargs: String::new(),
+            rng_seed: 0,
+        }
+    }
+}
+
+lazy_static! {
+    pub static ref MACHINE_INFO: UPSafeCell<MachineInfo> =
+        unsafe { UPSafeCell::new(MachineInfo::empty()) };
+}
+
+fn be32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset.checked_add(4)?)?;
+    Some(u32::from_be_bytes(bytes.try_into().unwrap()))
+}
+
+fn be64(data: &[u8], offset: usize) -> Option<u64> {
+    let bytes = data.get(offset..offset.checked_add(8)?)?;
+    Some(u64::from_be_bytes(bytes.try_into().unwrap()))
+}
+
+fn align4(offset: usize) -> usize {
+    (offset + 3) & !3
+}
+
+// c_str 读取从 offset 开始以 '\0' 结尾的字符串
+fn c_str(data: &[u8], offset: usize) -> Option<&str> {
+    let bytes = data.get(offset..)?;
+    let len = bytes.iter().position(|&c| c == 0)?;
+    core::str::from_utf8(&bytes[..len]).ok()
+}
+
+// read_cells 读取 cells 个 u32 组成的大端整数
+fn read_cells(data: &[u8], offset: usize, cells: usize) -> Option<usize> {
+    (0..cells).try_fold(0, |v, i| {
+        Some((v << 32) | be32(data, offset + i * 4)? as usize)
+    })
+}
+
+// parse_reg 解析 reg 属性中的 (address, size) 对，返回 [start, end) 区域
+fn parse_reg(value: &[u8], cells: (usize, usize)) -> Option<Vec<(usize, usize)>> {
+    let (address_cells, size_cells) = cells;
+    let entry_size = (address_cells + size_cells) * 4;
+    if entry_size == 0 {
+        return Some(Vec::new());
+    }
+    let mut regions = Vec::new();
+    for i in 0..value.len() / entry_size {
+        let start = read_cells(value, i * entry_size, address_cells)?;
+        let size = read_cells(value, i * entry_size + address_cells * 4, size_cells)?;
+        if let Some(end) = start.checked_add(size).filter(|&end| start < end) {
+            regions.push((start, end));
+        }
+    }
+    Some(regions)
+}
+
+// parse 解析设备树，data 的长度是 header 中的 totalsize。设备树不完整或者不合法时返回 None，
+// 所有的读取都会检查边界，不会因为下标越界 panic。
+fn parse(data: &[u8]) -> Option<MachineInfo> {
+    let mut info = MachineInfo::empty();
+    if data.len() < FDT_HEADER_SIZE {
+        return None;
+    }
+    let off_dt_struct = be32(data, 8)? as usize;
+    let off_dt_strings = be32(data, 12)? as usize;
+    let off_mem_rsvmap = be32(data, 16)? as usize;
+    let size_dt_strings = be32(data, 32)? as usize;
+    let size_dt_struct = be32(data, 36)? as usize;
+    let structs = data.get(off_dt_struct..off_dt_struct.checked_add(size_dt_struct)?)?;
+    let strings = data.get(off_dt_strings..off_dt_strings.checked_add(size_dt_strings)?)?;
+
+    // memory reservation block 以 (0, 0) 结尾
+    let mut offset = off_mem_rsvmap;
+    loop {
+        let address = be64(data, offset)? as usize;
+        let size = be64(data, offset + 8)? as usize;
+        if address == 0 && size == 0 {
+            break;
+        }
+        info.reserved.push((address, address.checked_add(size)?));
+        offset += 16;
+    }
+
+    // names[i] 是第 i 层节点的名字，cells[i] 是第 i 层节点为子节点定义的
+    // (#address-cells, #size-cells)，根节点为第 0 层。
+    let mut names = [""; MAX_DEPTH];
+    let mut cells = [(2usize, 1usize); MAX_DEPTH + 1];
+    let mut depth = 0;
+    let mut offset = 0;
+    loop {
+        let token = be32(structs, offset)?;
+        offset += 4;
+        match token {
+            FDT_BEGIN_NODE => {
+                let name = c_str(structs, offset)?;
+                offset = align4(offset + name.len() + 1);
+                if depth == MAX_DEPTH {
+                    println!("[kernel] device tree is nested too deep");
+                    return None;
+                }
+                names[depth] = name;
+                depth += 1;
+                cells[depth] = (2, 1);
+            }
+            FDT_END_NODE => depth = depth.checked_sub(1)?,
+            FDT_PROP => {
+                let len = be32(structs, offset)? as usize;
+                let name = c_str(strings, be32(structs, offset + 4)? as usize)?;
+                let value = structs.get(offset + 8..(offset + 8).checked_add(len)?)?;
+                offset = align4(offset + 8 + len);
+                let node = names[depth.checked_sub(1)?];
+                match name {
+                    "#address-cells" | "#size-cells" => {
+                        // 地址和长度最多使用两个 cell 表示
+                        let value = be32(value, 0)? as usize;
+                        if value > 2 {
+                            return None;
+                        }
+                        if name == "#address-cells" {
+                            cells[depth].0 = value;
+                        } else {
+                            cells[depth].1 = value;
+                        }
+                    }
+                    "reg" if depth == 2 && (node == "memory" || node.starts_with("memory@")) => {
+                        info.memory.extend(parse_reg(value, cells[depth - 1])?);
+                    }
+                    "reg" if depth == 3 && names[1] == "reserved-memory" => {
+                        info.reserved.extend(parse_reg(value, cells[depth - 1])?);
+                    }
+                    "bootargs" if depth == 2 && node == "chosen" => {
+                        info.bootargs = String::from(c_str(value, 0)?);
+                    }
+                    "rng-seed" if depth == 2 && node == "chosen" => {
+                        info.rng_seed = value.chunks(8).fold(0, |seed, chunk| {
+                            let mut bytes = [0u8; 8];
+                            bytes[..chunk.len()].copy_from_slice(chunk);
+                            seed ^ u64::from_le_bytes(bytes)
+                        });
+                    }
+                    _ => {}
+                }
+            }
+            FDT_NOP => {}
+            FDT_END => return Some(info),
+            _ => {
+                println!("[kernel] invalid device tree token {:#x}", token);
+                return None;
+            }
+        }
+    }
+}
+
+// init 解析 dtb_pa 指向的设备树，必须在启用分页之前调用。
+// 如果设备树不合法，则退化为使用 [FALLBACK_MEMORY_START, MEMORY_END) 作为物理内存。
+pub fn init(dtb_pa: usize) {
+    let mut info = MACHINE_INFO.exclusive_access();
+    let magic = if dtb_pa == 0 {
+        0
+    } else {
+        u32::from_be(unsafe { (dtb_pa as *const u32).read_volatile() })
+    };
+    let total_size = if magic == FDT_MAGIC {
+        u32::from_be(unsafe { ((dtb_pa + 4) as *const u32).read_volatile() }) as usize
+    } else {
+        0
+    };
+    let parsed = if (FDT_HEADER_SIZE..=MAX_FDT_SIZE).contains(&total_size) {
+        let data = unsafe { core::slice::from_raw_parts(dtb_pa as *const u8, total_size) };
+        parse(data)
+    } else {
+        None
+    };
+    match parsed {
+        Some(parsed) => {
+            *info = parsed;
+            info.reserved.push((dtb_pa, dtb_pa + total_size));
+        }
+        None => println!("[kernel] invalid device tree at {:#x}", dtb_pa),
+    }
+    if info.memory.is_empty() {
+        info.memory
+            .push((FALLBACK_MEMORY_START, config::MEMORY_END));
+    }
+
+    for &(start, end) in info.memory.iter() {
+        println!("[kernel] memory [{:#x}, {:#x})", start, end);
+    }
+    for &(start, end) in info.reserved.iter() {
+        println!("[kernel] reserved memory [{:#x}, {:#x})", start, end);
+    }
+}
+
+// has_bootarg 判断启动参数中是否包含 arg，比如 norandmaps
+pub fn has_bootarg(arg: &str) -> bool {
+    MACHINE_INFO
+        .exclusive_access()
+        .bootargs
+        .split_whitespace()
+        .any(|bootarg| bootarg == arg)
+}
+
+// usable_memory 返回 [start, ∞) 中可以被内核自由使用的物理内存区域，
+// 也就是物理内存区域中去除保留内存区域后的部分。
+pub fn usable_memory(start: usize) -> Vec<(usize, usize)> {
+    let info = MACHINE_INFO.exclusive_access();
+    let mut regions: Vec<(usize, usize)> = info
+        .memory
+        .iter()
+        .map(|&(l, r)| (l.max(start), r))
+        .filter(|&(l, r)| l < r)
+        .collect();
+    for &(reserved_l, reserved_r) in info.reserved.iter() {
+        regions = regions
+            .into_iter()
+            .flat_map(|(l, r)| {
+                [(l, r.min(reserved_l)), (l.max(reserved_r), r)]
+                    .into_iter()
+                    .filter(|&(l, r)| l < r)
+            })
+            .collect();
+    }
+    regions.sort();
+    regions
+}
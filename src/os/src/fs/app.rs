@@ -0,0 +1,53 @@
+use alloc::sync::Arc;
+
+use crate::loader;
+
+use super::File;
+
+// AppFile 是链接在内核镜像中的用户程序，只读
+pub struct AppFile {
+    app_id: usize,
+    data: &'static [u8],
+}
+
+impl AppFile {
+    // open 根据应用名打开用户程序
+    pub fn open(name: &str) -> Option<Arc<dyn File>> {
+        let app_id = loader::get_app_id_by_name(name)?;
+        Some(Arc::new(Self {
+            app_id,
+            data: loader::get_app_data(app_id),
+        }))
+    }
+}
+
+impl File for AppFile {
+    fn ino(&self) -> usize {
+        self.app_id
+    }
+
+    fn size(&self) -> usize {
+        self.data.len()
+    }
+
+    fn writable(&self) -> bool {
+        false
+    }
+
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
+        if offset >= self.data.len() {
+            return 0;
+        }
+        let len = buf.len().min(self.data.len() - offset);
+        buf[..len].copy_from_slice(&self.data[offset..offset + len]);
+        len
+    }
+
+    fn write_at(&self, _offset: usize, _buf: &[u8]) -> usize {
+        0
+    }
+
+    fn set_size(&self, _size: usize) -> bool {
+        false
+    }
+}
@@ -0,0 +1,70 @@
+use alloc::{sync::Arc, vec::Vec};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::{loader, sync::UPSafeCell};
+
+use super::File;
+
+// MemFile 的 ino 排在所有用户程序之后，并且不会被重复使用
+static NEXT_INO: AtomicUsize = AtomicUsize::new(0);
+
+// MemFile 是只存在于内存中的可读写文件，由 memfd_create 创建，长度为 0，
+// 需要通过 ftruncate 设置长度。
+pub struct MemFile {
+    ino: usize,
+    data: UPSafeCell<Vec<u8>>,
+}
+
+impl MemFile {
+    pub fn new() -> Arc<dyn File> {
+        Arc::new(Self {
+            ino: loader::get_num_app() + NEXT_INO.fetch_add(1, Ordering::Relaxed),
+            data: unsafe { UPSafeCell::new(Vec::new()) },
+        })
+    }
+}
+
+impl File for MemFile {
+    fn ino(&self) -> usize {
+        self.ino
+    }
+
+    fn size(&self) -> usize {
+        self.data.exclusive_access().len()
+    }
+
+    fn writable(&self) -> bool {
+        true
+    }
+
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
+        let data = self.data.exclusive_access();
+        if offset >= data.len() {
+            return 0;
+        }
+        let len = buf.len().min(data.len() - offset);
+        buf[..len].copy_from_slice(&data[offset..offset + len]);
+        len
+    }
+
+    fn write_at(&self, offset: usize, buf: &[u8]) -> usize {
+        let mut data = self.data.exclusive_access();
+        if offset >= data.len() {
+            return 0;
+        }
+        let len = buf.len().min(data.len() - offset);
+        data[offset..offset + len].copy_from_slice(&buf[..len]);
+        len
+    }
+
+    // 变长时新的部分为 0，申请不到内存时返回 false
+    fn set_size(&self, size: usize) -> bool {
+        let mut data = self.data.exclusive_access();
+        let len = data.len();
+        if size > len && data.try_reserve_exact(size - len).is_err() {
+            return false;
+        }
+        data.resize(size, 0);
+        true
+    }
+}
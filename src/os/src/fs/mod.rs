@@ -0,0 +1,49 @@
+// 文件的抽象。目前还没有文件系统，链接在内核镜像中的用户程序可以作为只读文件被打开，
+// exec 通过 page cache 将这些文件中的 ELF 段映射到用户地址空间。memfd_create 创建的
+// MemFile 是唯一可以写入的文件，可以通过 MAP_SHARED 映射并在 msync/munmap 时写回。
+mod app;
+mod mem;
+
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+pub use app::AppFile;
+pub use mem::MemFile;
+
+pub trait File: Send + Sync {
+    // ino 在所有文件中唯一，作为 page cache 的索引
+    fn ino(&self) -> usize;
+    // size 返回文件的长度 (字节)
+    fn size(&self) -> usize;
+    fn writable(&self) -> bool;
+    // read_at 从 offset 开始读取数据到 buf 中，返回读取的字节数
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize;
+    // write_at 将 buf 写入到 offset 开始的位置，不会改变文件的长度，返回写入的字节数
+    fn write_at(&self, offset: usize, buf: &[u8]) -> usize;
+    // set_size 将文件的长度修改为 size，返回 false 表示修改失败
+    fn set_size(&self, size: usize) -> bool;
+}
+
+// OpenFile 是 fd_table 中的一项，offset 是 read/write 使用的文件位置。
+// fork 之后父子进程共享同一个 OpenFile，所以也共享文件位置。
+pub struct OpenFile {
+    pub file: Arc<dyn File>,
+    offset: AtomicUsize,
+}
+
+impl OpenFile {
+    pub fn new(file: Arc<dyn File>) -> Self {
+        Self {
+            file,
+            offset: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn offset(&self) -> usize {
+        self.offset.load(Ordering::Relaxed)
+    }
+
+    pub fn set_offset(&self, offset: usize) {
+        self.offset.store(offset, Ordering::Relaxed);
+    }
+}
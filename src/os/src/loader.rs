@@ -48,12 +48,16 @@ lazy_static! {
     };
 }
 
+// 根据应用名 (name) 获取应用的编号
+pub fn get_app_id_by_name(name: &str) -> Option<usize> {
+    let num_app = get_num_app();
+    (0..num_app).find(|&i| APP_NAMES[i] == name)
+}
+
 // 根据应用名 (name) 获取 elf 数据
+#[allow(unused)]
 pub fn get_app_data_by_name(name: &str) -> Option<&'static [u8]> {
-    let num_app = get_num_app();
-    (0..num_app)
-        .find(|&i| APP_NAMES[i] == name)
-        .map(|i| get_app_data(i))
+    get_app_id_by_name(name).map(|i| get_app_data(i))
 }
 
 #[allow(unused)]
@@ -2,6 +2,8 @@
 #![no_main]
 #![feature(panic_info_message)]
 #![feature(alloc_error_handler)]
+#![feature(allocator_api)]
+#![feature(btreemap_alloc)]
 
 // #[macro_use] 的作用是在 mod 作用域结束时依然可以使用 macro，
 // 或者引入其他 crate 的 marcos。
@@ -15,7 +17,11 @@ pub mod syscall;
 pub mod trap;
 
 mod config;
+mod drivers;
+mod fdt;
+mod fs;
 mod loader;
+mod random;
 mod task;
 mod timer;
 
@@ -31,12 +37,14 @@ global_asm!(include_str!("entry.asm"));
 // 将用户程序链接到操作系统中
 global_asm!(include_str!("link_app.S"));
 
+// hart_id 和 dtb_pa 由 bootloader 通过 a0 和 a1 寄存器传递，
+// 分别表示当前 hart 的编号以及设备树的物理地址。
 #[no_mangle]
-fn rust_main() -> ! {
+fn rust_main(_hart_id: usize, dtb_pa: usize) -> ! {
     clear_bss();
 
     println!("[kernel] Welcome to rCore!");
-    mm::init();
+    mm::init(dtb_pa);
     task::add_initproc(); 
     trap::init();
     trap::enable_timer_interrupt();
@@ -1,16 +1,41 @@
 use crate::config;
 use core::fmt::{self, Debug, Formatter};
+use core::sync::atomic::{AtomicUsize, Ordering};
 
 use super::page_table::PageTableEntry;
 
-// PA = Page Address
-const PA_WIDTH_SV39: usize = 56;
-// VA = Virtual Address
-const VA_WIDTH_SV39: usize = 39;
+// PA = Page Address，Sv39 和 Sv48 的物理地址宽度都是 56 位
+const PA_WIDTH: usize = 56;
 // PPN = Physical Page Number
-const PPN_WIDTH_SV39: usize = PA_WIDTH_SV39 - config::PAGE_SIZE_BITS;
+const PPN_WIDTH: usize = PA_WIDTH - config::PAGE_SIZE_BITS;
+// 每一级页表的索引宽度，一个页表有 512 个页表项
+const VPN_INDEX_WIDTH: usize = 9;
+// 支持的最大页表级数 (Sv48)
+pub const MAX_PAGING_LEVELS: usize = 4;
+
+// 页表级数，Sv39 为 3 级，Sv48 为 4 级，在开启分页之前由
+// page_table::init_paging_mode 确定，之后不再改变
+static PAGING_LEVELS: AtomicUsize = AtomicUsize::new(3);
+
+pub fn paging_levels() -> usize {
+    PAGING_LEVELS.load(Ordering::Relaxed)
+}
+
+#[allow(unused)]
+pub fn set_paging_levels(levels: usize) {
+    assert!(levels == 3 || levels == MAX_PAGING_LEVELS);
+    PAGING_LEVELS.store(levels, Ordering::Relaxed);
+}
+
 // VPN = Virtual Page Number
-const VPN_WIDTH_SV39: usize = VA_WIDTH_SV39 - config::PAGE_SIZE_BITS;
+fn vpn_width() -> usize {
+    VPN_INDEX_WIDTH * paging_levels()
+}
+
+// VA = Virtual Address，Sv39 为 39 位，Sv48 为 48 位
+pub fn va_width() -> usize {
+    vpn_width() + config::PAGE_SIZE_BITS
+}
 
 #[derive(Clone, Copy, PartialEq, PartialOrd, Ord, Eq)]
 pub struct PhysAddr(pub usize);
@@ -109,13 +134,13 @@ impl VirtAddr {
 }
 
 impl VirtPageNum {
-    pub fn indexes(&self) -> [usize; 3] {
+    // indexes 返回从根页表到叶子页表的各级索引，只有前 paging_levels() 项有效
+    pub fn indexes(&self) -> [usize; MAX_PAGING_LEVELS] {
         let mut vpn = self.0;
-        let mut idxs = [0usize; 3];
-        let idxlen = VPN_WIDTH_SV39 / 3;
-        for i in (0..3).rev() {
-            idxs[i] = vpn & ((1usize << idxlen) - 1);
-            vpn >>= idxlen;
+        let mut idxs = [0usize; MAX_PAGING_LEVELS];
+        for i in (0..paging_levels()).rev() {
+            idxs[i] = vpn & ((1usize << VPN_INDEX_WIDTH) - 1);
+            vpn >>= VPN_INDEX_WIDTH;
         }
         idxs
     }
@@ -123,13 +148,13 @@ impl VirtPageNum {
 
 impl From<usize> for PhysAddr {
     fn from(v: usize) -> Self {
-        Self(v & (1 << PA_WIDTH_SV39) - 1)
+        Self(v & (1 << PA_WIDTH) - 1)
     }
 }
 
 impl From<usize> for PhysPageNum {
     fn from(v: usize) -> Self {
-        Self(v & ((1 << PPN_WIDTH_SV39) - 1))
+        Self(v & ((1 << PPN_WIDTH) - 1))
     }
 }
 
@@ -147,13 +172,13 @@ impl From<PhysPageNum> for usize {
 
 impl From<usize> for VirtAddr {
     fn from(v: usize) -> Self {
-        Self(v & ((1 << VA_WIDTH_SV39) - 1))
+        Self(v & ((1 << va_width()) - 1))
     }
 }
 
 impl From<usize> for VirtPageNum {
     fn from(v: usize) -> Self {
-        Self(v & ((1 << VPN_WIDTH_SV39) - 1))
+        Self(v & ((1 << vpn_width()) - 1))
     }
 }
 
@@ -172,8 +197,9 @@ impl From<PhysPageNum> for PhysAddr {
 
 impl From<VirtAddr> for usize {
     fn from(v: VirtAddr) -> Self {
-        if v.0 >= (1 << (VA_WIDTH_SV39 - 1)) {
-            v.0 | (!((1 << VA_WIDTH_SV39) - 1))
+        let va_width = va_width();
+        if v.0 >= (1 << (va_width - 1)) {
+            v.0 | (!((1 << va_width) - 1))
         } else {
             v.0
         }
@@ -233,6 +259,10 @@ where
     pub fn get_end(&self) -> T {
         self.r
     }
+
+    pub fn contains(&self, v: T) -> bool {
+        self.l <= v && v < self.r
+    }
 }
 
 impl<T> IntoIterator for SimpleRange<T>
@@ -0,0 +1,88 @@
+// ASID (address space identifier) 用于区分不同地址空间的 TLB 表项，切换 satp 时
+// 只要新旧地址空间的 ASID 不同就不需要刷新整个 TLB。
+// ASID 0 保留给内核地址空间，同时也是硬件不支持 ASID 或者 ASID 用完时的回退值：
+// 使用 ASID 0 的用户地址空间在 trap.S 中切换 satp 时仍然会刷新整个 TLB。
+use alloc::vec::Vec;
+use lazy_static::*;
+use riscv::register::satp;
+
+use crate::sync::UPSafeCell;
+
+pub(crate) const ASID_OFFSET: usize = 44;
+const ASID_MASK: usize = 0xffff;
+
+pub struct AsidTracker(pub usize);
+
+pub struct AsidAllocator {
+    current: usize,
+    // 硬件支持的 ASID 数量，init 之前为 1，也就是只有 ASID 0
+    max: usize,
+    recycled: Vec<usize>,
+}
+
+impl AsidAllocator {
+    pub fn new() -> Self {
+        Self {
+            current: 1,
+            max: 1,
+            recycled: Vec::new(),
+        }
+    }
+
+    pub fn alloc(&mut self) -> AsidTracker {
+        if let Some(asid) = self.recycled.pop() {
+            AsidTracker(asid)
+        } else if self.current < self.max {
+            self.current += 1;
+            AsidTracker(self.current - 1)
+        } else {
+            AsidTracker(0)
+        }
+    }
+
+    pub fn dealloc(&mut self, asid: usize) {
+        assert!(asid < self.current);
+        assert!(
+            !self.recycled.iter().any(|&i| i == asid),
+            "asid {} has been deallocated",
+            asid
+        );
+        self.recycled.push(asid);
+    }
+}
+
+lazy_static! {
+    pub static ref ASID_ALLOCATOR: UPSafeCell<AsidAllocator> =
+        unsafe { UPSafeCell::new(AsidAllocator::new()) };
+}
+
+impl Drop for AsidTracker {
+    fn drop(&mut self) {
+        if self.0 != 0 {
+            ASID_ALLOCATOR.exclusive_access().dealloc(self.0);
+        }
+    }
+}
+
+// init 探测硬件支持的 ASID 位数：ASID 字段是 WARL 的，向其中写入全 1 之后
+// 读回的值就是最大的 ASID。需要在开启分页之后调用。
+pub fn init() {
+    let token = satp::read().bits();
+    let max_asid = unsafe {
+        satp::write(token | ASID_MASK << ASID_OFFSET);
+        let max_asid = satp::read().bits() >> ASID_OFFSET & ASID_MASK;
+        satp::write(token);
+        core::arch::asm!("sfence.vma");
+        max_asid
+    };
+    println!(
+        "[kernel] {} ASID bits supported",
+        (max_asid + 1).trailing_zeros()
+    );
+    ASID_ALLOCATOR.exclusive_access().max = max_asid + 1;
+}
+
+// asid_alloc 申请一个 ASID，没有空闲的 ASID 时返回 ASID 0
+pub fn asid_alloc() -> AsidTracker {
+    ASID_ALLOCATOR.exclusive_access().alloc()
+}
@@ -0,0 +1,52 @@
+// 地址空间随机化 (ASLR)：每次 exec 时随机选择 PIE 程序的加载地址和 user stack 的栈顶。
+// 启动参数中包含 norandmaps 时关闭随机化，方便调试。
+use lazy_static::*;
+
+use crate::{
+    config::{self, PAGE_SIZE, USER_INTERP_BASE, USER_PIE_BASE},
+    fdt, random,
+};
+
+// 加载地址在 USER_PIE_BASE (动态链接器为 USER_INTERP_BASE) 之上 2^PIE_RANDOM_BITS
+// 个页的范围内随机选择
+const PIE_RANDOM_BITS: usize = 16;
+// 栈顶在 user_stack_top() 之下 2^STACK_RANDOM_BITS 个页的范围内随机选择
+const STACK_RANDOM_BITS: usize = 10;
+
+lazy_static! {
+    static ref ENABLED: bool = {
+        let enabled = !fdt::has_bootarg("norandmaps");
+        if !enabled {
+            println!("[kernel] address space randomization is disabled");
+        }
+        enabled
+    };
+}
+
+// enabled 返回是否开启了地址空间随机化
+pub fn enabled() -> bool {
+    *ENABLED
+}
+
+fn random_pages(bits: usize) -> usize {
+    if *ENABLED {
+        random::rand_u64() as usize & ((1 << bits) - 1)
+    } else {
+        0
+    }
+}
+
+// pie_load_bias 返回 PIE 程序的加载偏移，也就是链接地址 0 被加载到的位置
+pub fn pie_load_bias() -> usize {
+    USER_PIE_BASE + random_pages(PIE_RANDOM_BITS) * PAGE_SIZE
+}
+
+// interp_load_bias 返回动态链接器的加载偏移
+pub fn interp_load_bias() -> usize {
+    USER_INTERP_BASE + random_pages(PIE_RANDOM_BITS) * PAGE_SIZE
+}
+
+// stack_top 返回 user stack 的栈顶
+pub fn stack_top() -> usize {
+    config::user_stack_top() - random_pages(STACK_RANDOM_BITS) * PAGE_SIZE
+}
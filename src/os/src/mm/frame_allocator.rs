@@ -1,9 +1,13 @@
-use core::fmt::{self, Debug, Formatter};
+use core::{
+    fmt::{self, Debug, Formatter},
+    mem::size_of,
+    slice,
+};
 
 use alloc::vec::Vec;
 use lazy_static::*;
 
-use crate::{config, sync::UPSafeCell};
+use crate::{config::PAGE_SIZE, fdt, sync::UPSafeCell};
 
 use super::address::{PhysAddr, PhysPageNum};
 
@@ -36,14 +40,66 @@ impl Drop for FrameTracker {
 
 trait FrameAllocator {
     fn new() -> Self;
+    // init 设置可分配的物理页框范围，ranges 中的每一项为 [l, r) 且按照地址从小到大排列。
+    // 分配器可以通过 carve_metadata 从 ranges 中取出页框存放自己的元数据。
+    fn init(&mut self, ranges: &mut [(PhysPageNum, PhysPageNum)]);
     fn alloc(&mut self) -> Option<PhysPageNum>;
+    // alloc_contiguous 分配 count 个物理地址连续的页框，第一个页框的 ppn 按照
+    // align (页框个数，必须是 2 的幂) 对齐。
+    fn alloc_contiguous(&mut self, count: usize, align: usize) -> Option<PhysPageNum>;
     fn dealloc(&mut self, ppn: PhysPageNum);
+    // free_count 返回空闲页框的数量
+    fn free_count(&self) -> usize;
+    // total_count 返回全部可分配页框的数量
+    fn total_count(&self) -> usize;
+}
+
+// 通过 cargo feature 选择物理页框分配器，默认使用 BitmapFrameAllocator，
+// 启用 stack-frame-allocator 后使用原来的 StackFrameAllocator。
+#[cfg(feature = "stack-frame-allocator")]
+type FrameAllocatorImpl = StackFrameAllocator;
+#[cfg(not(feature = "stack-frame-allocator"))]
+type FrameAllocatorImpl = BitmapFrameAllocator;
+
+// carve_metadata 从 range 的开头取出足够存放 count 个 T 的页框，初始化为 value 之后返回。
+// 元数据的大小随物理内存增长，而内核堆在内核地址空间创建之前不能扩展，所以元数据不放在
+// 内核堆中；这样持有 FRAME_ALLOCATOR 时也不会因为申请堆内存而需要再次分配页框。
+fn carve_metadata<T: Copy>(
+    range: &mut (PhysPageNum, PhysPageNum),
+    count: usize,
+    value: T,
+) -> &'static mut [T] {
+    let pages = (count * size_of::<T>() + PAGE_SIZE - 1) / PAGE_SIZE;
+    assert!(
+        range.0 .0 + pages <= range.1 .0,
+        "no memory for frame allocator metadata"
+    );
+    let ptr = PhysAddr::from(range.0).0 as *mut T;
+    range.0 .0 += pages;
+    let metadata = unsafe { slice::from_raw_parts_mut(ptr, count) };
+    metadata.fill(value);
+    metadata
+}
+
+// largest_range 返回 ranges 中页框最多的一段
+fn largest_range(ranges: &mut [(PhysPageNum, PhysPageNum)]) -> &mut (PhysPageNum, PhysPageNum) {
+    ranges.iter_mut().max_by_key(|(l, r)| r.0 - l.0).unwrap()
 }
 
 pub struct StackFrameAllocator {
     current: usize,
     end: usize,
-    recycled: Vec<usize>,
+    // recycled 的容量是全部页框的数量，所以回收页框时不需要扩容
+    recycled: &'static mut [usize],
+    recycled_count: usize,
+    total: usize,
+}
+
+impl StackFrameAllocator {
+    fn recycle(&mut self, ppn: usize) {
+        self.recycled[self.recycled_count] = ppn;
+        self.recycled_count += 1;
+    }
 }
 
 impl FrameAllocator for StackFrameAllocator {
@@ -51,13 +107,27 @@ impl FrameAllocator for StackFrameAllocator {
         Self {
             current: 0,
             end: 0,
-            recycled: Vec::new(),
+            recycled: &mut [],
+            recycled_count: 0,
+            total: 0,
         }
     }
 
+    // StackFrameAllocator 只能管理一段连续的页框，所以只使用最大的一段
+    fn init(&mut self, ranges: &mut [(PhysPageNum, PhysPageNum)]) {
+        let range = largest_range(ranges);
+        let count = range.1 .0 - range.0 .0;
+        self.recycled = carve_metadata(range, count, 0);
+        let (l, r) = *range;
+        self.current = l.0;
+        self.end = r.0;
+        self.total = r.0 - l.0;
+    }
+
     fn alloc(&mut self) -> Option<PhysPageNum> {
-        if let Some(ppn) = self.recycled.pop() {
-            Some(ppn.into())
+        if self.recycled_count > 0 {
+            self.recycled_count -= 1;
+            Some(self.recycled[self.recycled_count].into())
         } else if self.current == self.end {
             None
         } else {
@@ -66,36 +136,187 @@ impl FrameAllocator for StackFrameAllocator {
         }
     }
 
+    // 只能从还没有被分配过的 [current, end) 中分配连续的页框，
+    // 为了对齐而跳过的页框会被放入 recycled 中。
+    fn alloc_contiguous(&mut self, count: usize, align: usize) -> Option<PhysPageNum> {
+        let start = (self.current + align - 1) & !(align - 1);
+        if start + count > self.end {
+            return None;
+        }
+        for ppn in self.current..start {
+            self.recycle(ppn);
+        }
+        self.current = start + count;
+        Some(start.into())
+    }
+
     fn dealloc(&mut self, ppn: PhysPageNum) {
         let ppn = ppn.0;
         // validity check
-        if ppn >= self.current || self.recycled.iter().any(|&v| v == ppn) {
+        if ppn >= self.current || self.recycled[..self.recycled_count].contains(&ppn) {
             panic!("Frame ppn={:#x} has not been allocated!", ppn);
         }
-        self.recycled.push(ppn);
+        self.recycle(ppn);
+    }
+
+    fn free_count(&self) -> usize {
+        self.end - self.current + self.recycled_count
+    }
+
+    fn total_count(&self) -> usize {
+        self.total
     }
 }
 
-impl StackFrameAllocator {
-    fn init(&mut self, l: PhysPageNum, r: PhysPageNum) {
-        self.current = l.0;
-        self.end = r.0;
+// BitmapFrameAllocator 使用一个 bit 记录一个页框是否已经被分配 (1 表示已分配)，
+// 释放的时间复杂度为 O(1)，同时可以检测重复释放。
+pub struct BitmapFrameAllocator {
+    base: usize,
+    end: usize,
+    bitmap: &'static mut [u64],
+    free: usize,
+    total: usize,
+    // 下一次分配开始查找的位置，避免每次都从头开始扫描
+    next: usize,
+}
+
+impl BitmapFrameAllocator {
+    fn is_allocated(&self, idx: usize) -> bool {
+        self.bitmap[idx / 64] & (1 << (idx % 64)) != 0
+    }
+
+    fn set_allocated(&mut self, idx: usize, allocated: bool) {
+        if allocated {
+            self.bitmap[idx / 64] |= 1 << (idx % 64);
+        } else {
+            self.bitmap[idx / 64] &= !(1 << (idx % 64));
+        }
+    }
+}
+
+impl FrameAllocator for BitmapFrameAllocator {
+    fn new() -> Self {
+        Self {
+            base: 0,
+            end: 0,
+            bitmap: &mut [],
+            free: 0,
+            total: 0,
+            next: 0,
+        }
+    }
+
+    // bitmap 覆盖从第一段开始到最后一段结束的全部页框，段与段之间的空洞视为已分配。
+    // bitmap 本身放在最大一段的开头，这些页框同样视为已分配。
+    fn init(&mut self, ranges: &mut [(PhysPageNum, PhysPageNum)]) {
+        self.base = ranges.first().unwrap().0 .0;
+        self.end = ranges.last().unwrap().1 .0;
+        let count = self.end - self.base;
+        self.bitmap = carve_metadata(largest_range(ranges), (count + 63) / 64, u64::MAX);
+        for &(l, r) in ranges.iter() {
+            for ppn in l.0..r.0 {
+                self.set_allocated(ppn - self.base, false);
+            }
+            self.free += r.0 - l.0;
+        }
+        self.total = self.free;
+        self.next = 0;
+    }
+
+    fn alloc(&mut self) -> Option<PhysPageNum> {
+        let words = self.bitmap.len();
+        for i in 0..words {
+            let word_idx = (self.next + i) % words;
+            let word = self.bitmap[word_idx];
+            if word != u64::MAX {
+                let idx = word_idx * 64 + (!word).trailing_zeros() as usize;
+                self.set_allocated(idx, true);
+                self.free -= 1;
+                self.next = word_idx;
+                return Some((self.base + idx).into());
+            }
+        }
+        None
+    }
+
+    fn alloc_contiguous(&mut self, count: usize, align: usize) -> Option<PhysPageNum> {
+        let mut start = (self.base + align - 1) & !(align - 1);
+        while start + count <= self.end {
+            let first = start - self.base;
+            match (first..first + count).find(|&idx| self.is_allocated(idx)) {
+                // 从冲突页框之后的下一个对齐位置继续查找
+                Some(idx) => {
+                    start = (self.base + idx + 1 + align - 1) & !(align - 1);
+                }
+                None => {
+                    for idx in first..first + count {
+                        self.set_allocated(idx, true);
+                    }
+                    self.free -= count;
+                    return Some(start.into());
+                }
+            }
+        }
+        None
+    }
+
+    fn dealloc(&mut self, ppn: PhysPageNum) {
+        let ppn = ppn.0;
+        if ppn < self.base || ppn >= self.end {
+            panic!("Frame ppn={:#x} is out of range!", ppn);
+        }
+        let idx = ppn - self.base;
+        if !self.is_allocated(idx) {
+            panic!("Frame ppn={:#x} has not been allocated!", ppn);
+        }
+        self.set_allocated(idx, false);
+        self.free += 1;
+    }
+
+    fn free_count(&self) -> usize {
+        self.free
+    }
+
+    fn total_count(&self) -> usize {
+        self.total
     }
 }
 
+// SlabFrames 记录哪些页框是 slab 页 (1 表示是)，释放堆内存时据此判断对象是否由 slab
+// 分配器分配。它覆盖页框分配器管理的全部页框，范围之外的地址都不是 slab 页。
+struct SlabFrames {
+    base: usize,
+    bitmap: &'static mut [u64],
+}
+
 lazy_static! {
-    pub static ref FRAME_ALLOCATOR: UPSafeCell<StackFrameAllocator> =
-        unsafe { UPSafeCell::new(StackFrameAllocator::new()) };
+    pub static ref FRAME_ALLOCATOR: UPSafeCell<FrameAllocatorImpl> =
+        unsafe { UPSafeCell::new(FrameAllocatorImpl::new()) };
+    static ref SLAB_FRAMES: UPSafeCell<SlabFrames> = unsafe {
+        UPSafeCell::new(SlabFrames {
+            base: 0,
+            bitmap: &mut [],
+        })
+    };
 }
 
+// init_frame_allocator 将内核镜像之后的可用物理内存交给页框分配器管理
 pub fn init_frame_allocator() {
     extern "C" {
         fn ekernel();
     }
-    FRAME_ALLOCATOR.exclusive_access().init(
-        PhysAddr::from(ekernel as usize).ceil(),
-        PhysAddr::from(config::MEMORY_END).floor(),
-    )
+    let mut ranges: Vec<(PhysPageNum, PhysPageNum)> = fdt::usable_memory(ekernel as usize)
+        .into_iter()
+        .map(|(l, r)| (PhysAddr::from(l).ceil(), PhysAddr::from(r).floor()))
+        .filter(|(l, r)| l < r)
+        .collect();
+    let base = ranges.first().unwrap().0 .0;
+    let count = ranges.last().unwrap().1 .0 - base;
+    *SLAB_FRAMES.exclusive_access() = SlabFrames {
+        base,
+        bitmap: carve_metadata(largest_range(&mut ranges), (count + 63) / 64, 0),
+    };
+    FRAME_ALLOCATOR.exclusive_access().init(&mut ranges)
 }
 
 pub fn frame_alloc() -> Option<FrameTracker> {
@@ -105,6 +326,129 @@ pub fn frame_alloc() -> Option<FrameTracker> {
         .map(FrameTracker::new)
 }
 
+// frame_alloc_contiguous 分配 count 个物理地址连续且按照 align 个页框对齐的页框，
+// 比如 DMA 缓冲区和大页，返回的页框按照 ppn 从小到大排列。
+#[allow(unused)]
+pub fn frame_alloc_contiguous(count: usize, align: usize) -> Option<Vec<FrameTracker>> {
+    assert!(count > 0 && align.is_power_of_two());
+    // 创建 Vec 时可能需要扩展内核堆，所以先释放 FRAME_ALLOCATOR
+    let start = FRAME_ALLOCATOR
+        .exclusive_access()
+        .alloc_contiguous(count, align)?;
+    Some(
+        (start.0..start.0 + count)
+            .map(|ppn| FrameTracker::new(ppn.into()))
+            .collect(),
+    )
+}
+
+// alloc_heap_frames 为内核堆和 slab 分配器分配 count 个页框，全部分配成功时对每个页框调用
+// f 并返回 true，否则不分配任何页框。这些页框由调用者管理，所以不使用 FrameTracker。
+// 持有 FRAME_ALLOCATOR 的代码不会申请堆内存，如果仍然发生了重入，直接返回 false 而不是 panic。
+pub fn alloc_heap_frames(count: usize, mut f: impl FnMut(PhysPageNum)) -> bool {
+    let mut allocator = match FRAME_ALLOCATOR.try_exclusive_access() {
+        Some(allocator) => allocator,
+        None => return false,
+    };
+    if allocator.free_count() < count {
+        return false;
+    }
+    for _ in 0..count {
+        let ppn = allocator.alloc().unwrap();
+        for byte in ppn.get_bytes_array() {
+            *byte = 0;
+        }
+        f(ppn);
+    }
+    true
+}
+
+// free_heap_frame 释放 alloc_heap_frames 分配的页框，页框分配器正在被使用时返回 false
+pub fn free_heap_frame(ppn: PhysPageNum) -> bool {
+    match FRAME_ALLOCATOR.try_exclusive_access() {
+        Some(mut allocator) => {
+            allocator.dealloc(ppn);
+            true
+        }
+        None => false,
+    }
+}
+
+// set_slab_frame 标记 alloc_heap_frames 分配的页框是否被用作 slab 页
+pub fn set_slab_frame(ppn: PhysPageNum, slab: bool) {
+    let mut frames = SLAB_FRAMES.exclusive_access();
+    let idx = ppn.0 - frames.base;
+    if slab {
+        frames.bitmap[idx / 64] |= 1 << (idx % 64);
+    } else {
+        frames.bitmap[idx / 64] &= !(1 << (idx % 64));
+    }
+}
+
+// is_slab_frame 判断 ppn 是否是 slab 页，ppn 可以是任意值，比如内核堆中的地址右移得到的值
+pub fn is_slab_frame(ppn: usize) -> bool {
+    let frames = SLAB_FRAMES.exclusive_access();
+    match ppn.checked_sub(frames.base) {
+        Some(idx) if idx / 64 < frames.bitmap.len() => {
+            frames.bitmap[idx / 64] & (1 << (idx % 64)) != 0
+        }
+        _ => false,
+    }
+}
+
+pub fn frame_free_count() -> usize {
+    FRAME_ALLOCATOR.exclusive_access().free_count()
+}
+
+pub fn frame_total_count() -> usize {
+    FRAME_ALLOCATOR.exclusive_access().total_count()
+}
+
 fn frame_dealloc(ppn: PhysPageNum) {
     FRAME_ALLOCATOR.exclusive_access().dealloc(ppn)
 }
+
+// TEST_FRAMES 是自检时交给每个分配器管理的页框数量
+#[cfg(feature = "frame-allocator-selftest")]
+const TEST_FRAMES: usize = 64;
+
+// frame_allocator_test 在启用 frame-allocator-selftest 时于启动阶段检查两种分配器，不论编译时
+// 选择了哪一种。从全局分配器中借出一段页框交给新建的分配器管理，测试结束后归还。
+#[cfg(feature = "frame-allocator-selftest")]
+pub fn frame_allocator_test() {
+    let frames = match frame_alloc_contiguous(TEST_FRAMES, TEST_FRAMES) {
+        Some(frames) => frames,
+        None => {
+            println!("frame_allocator_test skipped: not enough contiguous frames");
+            return;
+        }
+    };
+    let start = frames[0].ppn;
+    let end = PhysPageNum(start.0 + TEST_FRAMES);
+    test_allocator::<StackFrameAllocator>("StackFrameAllocator", start, end);
+    test_allocator::<BitmapFrameAllocator>("BitmapFrameAllocator", start, end);
+    println!("frame_allocator_test passed!");
+}
+
+// test_allocator 检查连续分配：先分配一个页框打乱对齐，再分配连续的页框，检查对齐和
+// 连续性，全部释放之后空闲页框的数量应该恢复
+#[cfg(feature = "frame-allocator-selftest")]
+fn test_allocator<A: FrameAllocator>(name: &str, start: PhysPageNum, end: PhysPageNum) {
+    let mut allocator = A::new();
+    allocator.init(&mut [(start, end)]);
+    let free = allocator.free_count();
+    let single = allocator.alloc().unwrap();
+    let mut allocated = Vec::new();
+    for &(count, align) in &[(3, 4), (8, 8), (1, 16)] {
+        let first = allocator.alloc_contiguous(count, align).unwrap().0;
+        assert_eq!(first % align, 0, "{}", name);
+        assert!(first >= start.0 && first + count <= end.0, "{}", name);
+        assert!(single.0 < first || single.0 >= first + count, "{}", name);
+        allocated.extend(first..first + count);
+    }
+    allocator.dealloc(single);
+    for ppn in allocated {
+        allocator.dealloc(ppn.into());
+    }
+    assert_eq!(allocator.free_count(), free, "{}", name);
+}
@@ -1,20 +1,147 @@
-use crate::config;
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    arch::asm,
+    ptr::{self, NonNull},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::config::{self, KERNEL_HEAP_MAX_SIZE, PAGE_SIZE};
 use buddy_system_allocator as sysalloc;
 
+use super::{
+    address::{PhysPageNum, VirtAddr},
+    frame_allocator::alloc_heap_frames,
+    page_table::{prepared_pte, PTEFlags, PageTableEntry},
+    slab_allocator,
+};
+
+// KernelHeap 优先从 slab 分配器分配小对象，其它的申请由 buddy 分配器处理，
+// buddy 分配器的空间不够时从页框分配器申请页框扩展内核堆
+struct KernelHeap {
+    heap: sysalloc::LockedHeap,
+}
+
 #[global_allocator]
-static HEAP_ALLOCATOR: sysalloc::LockedHeap = sysalloc::LockedHeap::empty();
+static HEAP_ALLOCATOR: KernelHeap = KernelHeap {
+    heap: sysalloc::LockedHeap::empty(),
+};
+
+// 内核根页表的 ppn，为 0 表示内核地址空间还没有创建，此时堆不能扩展
+static KERNEL_ROOT_PPN: AtomicUsize = AtomicUsize::new(0);
+// 已经映射到 [kernel_heap_base(), kernel_heap_base() + KERNEL_HEAP_MAX_SIZE) 的字节数
+static GROWN_SIZE: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for KernelHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if let Some(ptr) = slab_allocator::alloc(layout) {
+            return ptr;
+        }
+        // 扩展堆的过程中不会申请堆内存，所以可以一直持有锁
+        let mut heap = self.heap.lock();
+        if let Ok(ptr) = heap.alloc(layout) {
+            return ptr.as_ptr();
+        }
+        if !grow(&mut heap, layout) {
+            return ptr::null_mut();
+        }
+        heap.alloc(layout)
+            .map_or(ptr::null_mut(), |ptr| ptr.as_ptr())
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        if slab_allocator::owns(ptr) {
+            return slab_allocator::dealloc(ptr);
+        }
+        self.heap
+            .lock()
+            .dealloc(NonNull::new_unchecked(ptr), layout)
+    }
+}
 
 #[alloc_error_handler]
 pub fn handle_alloc_error(layout: core::alloc::Layout) -> ! {
     panic!("Failed to allocate on heap, layout = {:?}", layout);
 }
 
-static mut HEAP_SPACE: [u8; config::KERNEL_HEAP_SIZE] = [0; config::KERNEL_HEAP_SIZE];
+static mut HEAP_SPACE: [u8; config::KERNEL_HEAP_INIT_SIZE] = [0; config::KERNEL_HEAP_INIT_SIZE];
 
 pub fn init_heap() {
     unsafe {
         HEAP_ALLOCATOR
+            .heap
             .lock()
-            .init(HEAP_SPACE.as_ptr() as usize, config::KERNEL_HEAP_SIZE)
+            .init(HEAP_SPACE.as_ptr() as usize, config::KERNEL_HEAP_INIT_SIZE)
+    }
+}
+
+// enable_growth 在内核地址空间创建之后调用，root_ppn 的页表中必须已经通过
+// PageTable::prepare 创建了内核堆区域的各级页表
+pub fn enable_growth(root_ppn: PhysPageNum) {
+    KERNEL_ROOT_PPN.store(root_ppn.0, Ordering::Relaxed);
+}
+
+// grow 扩展内核堆使其能够满足 layout 的申请。buddy 分配器只会合并地址对齐的块，所以
+// 已经映射的区域大小总是 2 的幂，从 size 扩展到 new_size 之后 [new_size / 2, new_size)
+// 是一个完整的块。
+fn grow(heap: &mut sysalloc::Heap, layout: Layout) -> bool {
+    let root_ppn = KERNEL_ROOT_PPN.load(Ordering::Relaxed);
+    if root_ppn == 0 {
+        return false;
+    }
+    // 与 buddy 分配器一样计算实际需要的块大小
+    let need = layout
+        .size()
+        .next_power_of_two()
+        .max(layout.align())
+        .max(PAGE_SIZE);
+    let size = GROWN_SIZE.load(Ordering::Relaxed);
+    let new_size = if size == 0 {
+        need
+    } else {
+        (size * 2).max(need * 2)
+    };
+    if new_size > KERNEL_HEAP_MAX_SIZE {
+        return false;
+    }
+
+    let start = config::kernel_heap_base() + size;
+    let end = config::kernel_heap_base() + new_size;
+    let mut va = start;
+    let allocated = alloc_heap_frames((new_size - size) / PAGE_SIZE, |ppn| {
+        let pte = prepared_pte(PhysPageNum(root_ppn), VirtAddr::from(va).floor());
+        *pte = PageTableEntry::new(ppn, PTEFlags::V | PTEFlags::R | PTEFlags::W);
+        va += PAGE_SIZE;
+    });
+    if !allocated {
+        return false;
+    }
+    unsafe {
+        asm!("sfence.vma");
+        heap.add_to_heap(start, end);
+    }
+    GROWN_SIZE.store(new_size, Ordering::Relaxed);
+    true
+}
+
+// HeapStat 是内核堆的使用情况，单位是字节
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct HeapStat {
+    // total 是堆的总大小，包括初始空间和扩展出来的空间
+    pub total: usize,
+    // grown 是从页框分配器扩展出来的空间
+    pub grown: usize,
+    // allocated 是 buddy 分配器实际分配出去的空间，requested 是申请者要求的空间
+    pub allocated: usize,
+    pub requested: usize,
+}
+
+pub fn heap_stat() -> HeapStat {
+    let heap = HEAP_ALLOCATOR.heap.lock();
+    HeapStat {
+        total: heap.stats_total_bytes(),
+        grown: GROWN_SIZE.load(Ordering::Relaxed),
+        allocated: heap.stats_alloc_actual(),
+        requested: heap.stats_alloc_user(),
     }
 }
@@ -1,18 +1,26 @@
 use alloc::{collections::BTreeMap, sync::Arc, vec::Vec};
 use bitflags::*;
-use core::arch::asm;
+use core::{arch::asm, fmt};
 use lazy_static::*;
 
 use crate::{
-    config::{self, MEMORY_END, PAGE_SIZE, TRAMPOLINE},
+    config::{self, PAGE_SIZE},
+    fdt,
+    fs::{AppFile, File},
     mm::address::StepByOne,
     sync::UPSafeCell,
+    task,
 };
 
 use super::{
-    address::{PhysAddr, PhysPageNum, VPNRange, VirtAddr, VirtPageNum},
-    frame_allocator::{frame_alloc, FrameTracker},
-    page_table::{PTEFlags, PageTable, PageTableEntry},
+    address::{paging_levels, PhysAddr, PhysPageNum, VPNRange, VirtAddr, VirtPageNum},
+    aslr,
+    frame_allocator::{frame_alloc, frame_free_count, FrameTracker},
+    page_cache,
+    page_table::{level_pages, PTEFlags, PageTable, PageTableEntry, MAX_HUGE_PAGE_LEVEL},
+    shm::ShmSegment,
+    slab_allocator::{SlabCache, MAP_AREA_CACHE},
+    swap::{self, SwapTracker},
 };
 
 extern "C" {
@@ -37,6 +45,17 @@ lazy_static! {
 pub enum MapType {
     Identical, // 一个 VPN 唯一的映射一个 PPN，比如内核就需要访问物理内存中的某个 PPN
     Framed,    // 一个 VPN 随机的映射一个 PPN
+    File,      // 一个 VPN 映射到文件在 page cache 中的页
+}
+
+// FileMapping 描述 File 逻辑段映射的文件
+#[derive(Clone)]
+pub struct FileMapping {
+    file: Arc<dyn File>,
+    // 逻辑段起始位置对应的文件偏移，按页对齐
+    offset: usize,
+    // shared 表示修改对其他映射可见并且会写回文件，否则修改时写时复制
+    shared: bool,
 }
 
 bitflags! {
@@ -49,11 +68,27 @@ bitflags! {
     }
 }
 
+// MapArea 中 BTreeMap 的节点从专用的 slab 缓存 MAP_AREA_CACHE 中分配
+type MapAreaAlloc = &'static SlabCache<MapArea>;
+
 pub struct MapArea {
     vpn_range: VPNRange,
-    data_frames: BTreeMap<VirtPageNum, FrameTracker>,
+    // 页框使用 Arc 做引用计数，fork 之后父子进程的逻辑段可以共享同一个页框
+    data_frames: BTreeMap<VirtPageNum, Arc<FrameTracker>, MapAreaAlloc>,
+    // 被换出到交换区的页，fork 之后父子进程共享同一个 slot，换入时各自读入新的页框
+    swapped: BTreeMap<VirtPageNum, Arc<SwapTracker>, MapAreaAlloc>,
     map_type: MapType,
     map_perm: MapPermission,
+    // lazy 表示逻辑段在 map 时不申请页框，等到第一次访问触发 page fault 时才分配
+    lazy: bool,
+    // 映射共享内存段的逻辑段持有该共享内存段，页框在进程之间共享且不会写时复制
+    shm: Option<Arc<ShmSegment>>,
+    // write_denied 表示逻辑段不能通过 mprotect 加上写权限，比如以 SHM_RDONLY 方式映射的共享内存
+    write_denied: bool,
+    // Some(top) 表示这是一个可以向下增长的 user stack 逻辑段，top 是栈顶
+    stack_top: Option<VirtPageNum>,
+    // File 逻辑段映射的文件
+    file: Option<FileMapping>,
 }
 
 impl MapArea {
@@ -65,70 +100,381 @@ impl MapArea {
     ) -> Self {
         Self {
             vpn_range: VPNRange::new(start_va.floor(), end_va.ceil()),
-            data_frames: BTreeMap::new(),
+            data_frames: BTreeMap::new_in(&*MAP_AREA_CACHE),
+            swapped: BTreeMap::new_in(&*MAP_AREA_CACHE),
             map_type: map_type,
             map_perm: map_perm,
+            lazy: false,
+            shm: None,
+            write_denied: false,
+            stack_top: None,
+            file: None,
         }
     }
 
+    // new_lazy 创建一个按需分配 (demand-zero) 的 Framed 逻辑段
+    pub fn new_lazy(start_va: VirtAddr, end_va: VirtAddr, map_perm: MapPermission) -> Self {
+        let mut map_area = Self::new(start_va, end_va, MapType::Framed, map_perm);
+        map_area.lazy = true;
+        map_area
+    }
+
+    // new_shared 创建一个从 start_va 开始映射整个共享内存段 segment 的逻辑段
+    pub fn new_shared(
+        start_va: VirtAddr,
+        segment: Arc<ShmSegment>,
+        map_perm: MapPermission,
+    ) -> Self {
+        let start_vpn = start_va.floor();
+        let end_vpn = VirtPageNum(start_vpn.0 + segment.frames.len());
+        let mut map_area = Self::new(start_va, end_vpn.into(), MapType::Framed, map_perm);
+        for (i, frame) in segment.frames.iter().enumerate() {
+            map_area
+                .data_frames
+                .insert(VirtPageNum(start_vpn.0 + i), frame.clone());
+        }
+        map_area.shm = Some(segment);
+        map_area.write_denied = !map_perm.contains(MapPermission::W);
+        map_area
+    }
+
+    // new_file 创建一个映射文件的逻辑段，页在第一次访问时从 page cache 中获取，
+    // offset 是 start_va 所在页对应的文件偏移。
+    pub fn new_file(
+        start_va: VirtAddr,
+        end_va: VirtAddr,
+        map_perm: MapPermission,
+        file: Arc<dyn File>,
+        offset: usize,
+        shared: bool,
+    ) -> Self {
+        let mut map_area = Self::new(start_va, end_va, MapType::File, map_perm);
+        map_area.lazy = true;
+        map_area.file = Some(FileMapping {
+            file,
+            offset,
+            shared,
+        });
+        map_area
+    }
+
     // 拷贝一个与 `map_area` 一样长度和位置的虚拟地址空间，
     // 但是不拷贝页框数据。
     pub fn from_another(map_area: &MapArea) -> Self {
         Self {
             vpn_range: VPNRange::new(map_area.vpn_range.get_start(), map_area.vpn_range.get_end()),
-            data_frames: BTreeMap::new(),
+            data_frames: BTreeMap::new_in(&*MAP_AREA_CACHE),
+            swapped: BTreeMap::new_in(&*MAP_AREA_CACHE),
             map_type: map_area.map_type,
             map_perm: map_area.map_perm,
+            lazy: map_area.lazy,
+            shm: map_area.shm.clone(),
+            write_denied: map_area.write_denied,
+            stack_top: map_area.stack_top,
+            file: map_area.file.clone(),
+        }
+    }
+
+    // is_shared 判断逻辑段的页框是否在多个逻辑段之间共享写入，比如共享内存和共享文件映射
+    fn is_shared(&self) -> bool {
+        self.shm.is_some() || self.file.as_ref().map_or(false, |file| file.shared)
+    }
+
+    // new_frame 为 vpn 申请一个页框，File 逻辑段的页框来自 page cache，
+    // 没有空闲页框时返回 None
+    fn new_frame(&self, vpn: VirtPageNum) -> Option<Arc<FrameTracker>> {
+        match &self.file {
+            Some(mapping) => {
+                let index = mapping.offset / PAGE_SIZE + (vpn.0 - self.vpn_range.get_start().0);
+                page_cache::get_page(&mapping.file, index)
+            }
+            None => Some(Arc::new(frame_alloc()?)),
         }
     }
 
-    // map_one 为一个 vpn 申请一个物理页框，
-    // 将 vpn 和 ppn 的映射关系保存到 page table 中。
-    pub fn map_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) {
+    // map_one 为一个 vpn 申请一个物理页框 (已经有页框的 vpn 直接使用该页框，
+    // 比如共享内存)，将 vpn 和 ppn 的映射关系保存到 page table 中。
+    // 私有逻辑段中被共享的页框 (比如 page cache 中的页) 映射为只读，写入时再复制。
+    // 没有空闲页框时返回 None，此时逻辑段保持原来的状态。
+    pub fn map_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) -> Option<()> {
         let ppn: PhysPageNum;
+        let mut map_perm = self.map_perm;
+        let mut new_frame = false;
         match self.map_type {
             MapType::Identical => ppn = PhysPageNum(vpn.0),
-            MapType::Framed => {
-                let frame = frame_alloc().unwrap();
+            MapType::Framed | MapType::File => {
+                if !self.data_frames.contains_key(&vpn) {
+                    let frame = self.new_frame(vpn)?;
+                    self.data_frames.insert(vpn, frame);
+                    new_frame = true;
+                }
+                let frame = self.data_frames.get(&vpn).unwrap();
                 ppn = frame.ppn;
-                self.data_frames.insert(vpn, frame);
+                if !self.is_shared() && Arc::strong_count(frame) > 1 {
+                    map_perm.remove(MapPermission::W);
+                }
             }
         }
 
-        let pte_flags = PTEFlags::from_bits(self.map_perm.bits).unwrap();
-        page_table.map(vpn, ppn, pte_flags);
+        let pte_flags = PTEFlags::from_bits(map_perm.bits).unwrap();
+        if page_table.map(vpn, ppn, pte_flags).is_none() {
+            if new_frame {
+                self.data_frames.remove(&vpn);
+            }
+            return None;
+        }
+        Some(())
     }
 
     #[allow(unused)]
     pub fn unmap_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) {
-        if self.map_type == MapType::Framed {
+        if self.map_type != MapType::Identical {
             self.data_frames.remove(&vpn);
+            self.swapped.remove(&vpn);
+        }
+        // 没有任何访问权限 (PROT_NONE) 的页保留页框，但是没有页表项
+        if page_table
+            .translate(vpn)
+            .map_or(false, |pte| pte.is_valid())
+        {
+            page_table.unmap(vpn);
         }
-        page_table.unmap(vpn);
     }
 
-    // map 将逻辑段包含的所有 vpn 与 ppn 的映射关系保存到 page table 中
-    pub fn map(&mut self, page_table: &mut PageTable) {
+    // map 将逻辑段包含的所有 vpn 与 ppn 的映射关系保存到 page table 中，
+    // lazy 逻辑段的映射推迟到 page fault 时再建立。没有空闲页框时返回 None，
+    // 已经建立的映射需要调用者通过 unmap 释放。
+    pub fn map(&mut self, page_table: &mut PageTable) -> Option<()> {
+        if self.lazy {
+            return Some(());
+        }
+        if self.map_type == MapType::Identical {
+            return self.map_identical(page_table);
+        }
         for vpn in self.vpn_range {
-            self.map_one(page_table, vpn);
+            self.map_one(page_table, vpn)?;
+        }
+        Some(())
+    }
+
+    // map_identical 恒等映射整个逻辑段，对齐允许时使用 2 MiB/1 GiB 的大页，
+    // 以减少页表占用的页框和 TLB 表项
+    fn map_identical(&mut self, page_table: &mut PageTable) -> Option<()> {
+        let pte_flags = PTEFlags::from_bits(self.map_perm.bits).unwrap();
+        let end = self.vpn_range.get_end().0;
+        let mut vpn = self.vpn_range.get_start().0;
+        while vpn < end {
+            let level = (0..=MAX_HUGE_PAGE_LEVEL.min(paging_levels() - 1))
+                .rev()
+                .find(|&level| vpn % level_pages(level) == 0 && vpn + level_pages(level) <= end)
+                .unwrap();
+            page_table.map_huge(VirtPageNum(vpn), PhysPageNum(vpn), pte_flags, level)?;
+            vpn += level_pages(level);
         }
+        Some(())
+    }
+
+    // mapped_pages 返回逻辑段的大小 (页数)
+    fn mapped_pages(&self) -> usize {
+        self.vpn_range.get_end().0 - self.vpn_range.get_start().0
+    }
+
+    // resident_pages 返回逻辑段中位于物理内存中的页数，与其他逻辑段共享的页框也会被计算在内
+    fn resident_pages(&self) -> usize {
+        match self.map_type {
+            MapType::Identical => self.mapped_pages(),
+            MapType::Framed | MapType::File => self.data_frames.len(),
+        }
+    }
+
+    // is_mapped 判断 vpn 是否已经映射到了一个页框或者已经被换出
+    fn is_mapped(&self, vpn: VirtPageNum) -> bool {
+        self.map_type == MapType::Identical
+            || self.data_frames.contains_key(&vpn)
+            || self.swapped.contains_key(&vpn)
     }
 
     #[allow(unused)]
     pub fn unmap(&mut self, page_table: &mut PageTable) {
         for vpn in self.vpn_range {
-            self.unmap_one(page_table, vpn)
+            if self.is_mapped(vpn) {
+                self.unmap_one(page_table, vpn)
+            }
+        }
+    }
+
+    // shrink_to 将逻辑段的结束位置缩小到 new_end，释放被移除部分的页框
+    pub fn shrink_to(&mut self, page_table: &mut PageTable, new_end: VirtPageNum) {
+        for vpn in VPNRange::new(new_end, self.vpn_range.get_end()) {
+            if self.is_mapped(vpn) {
+                self.unmap_one(page_table, vpn);
+            }
+        }
+        self.vpn_range = VPNRange::new(self.vpn_range.get_start(), new_end);
+    }
+
+    // append_to 将逻辑段的结束位置扩大到 new_end，没有空闲页框时恢复原来的结束位置
+    // 并返回 None
+    pub fn append_to(&mut self, page_table: &mut PageTable, new_end: VirtPageNum) -> Option<()> {
+        let old_end = self.vpn_range.get_end();
+        self.vpn_range = VPNRange::new(self.vpn_range.get_start(), new_end);
+        if self.lazy {
+            return Some(());
         }
+        for vpn in VPNRange::new(old_end, new_end) {
+            if self.map_one(page_table, vpn).is_none() {
+                self.shrink_to(page_table, old_end);
+                return None;
+            }
+        }
+        Some(())
+    }
+
+    // extend_down_to 将逻辑段的起始位置向下扩展到 new_start，只用于 lazy 逻辑段
+    fn extend_down_to(&mut self, new_start: VirtPageNum) {
+        assert!(self.lazy);
+        self.vpn_range = VPNRange::new(new_start, self.vpn_range.get_end());
+    }
+
+    // split_off 将逻辑段从 vpn 处一分为二，当前逻辑段保留 [start, vpn)，
+    // 返回的新逻辑段为 [vpn, end)，已经分配的页框跟随 vpn 一起移动。
+    // 只有下半部分的 user stack 可以继续向下增长。
+    pub fn split_off(&mut self, vpn: VirtPageNum) -> Self {
+        let end = self.vpn_range.get_end();
+        let file = self.file.clone().map(|mut mapping| {
+            mapping.offset += (vpn.0 - self.vpn_range.get_start().0) * PAGE_SIZE;
+            mapping
+        });
+        self.vpn_range = VPNRange::new(self.vpn_range.get_start(), vpn);
+        Self {
+            vpn_range: VPNRange::new(vpn, end),
+            data_frames: self.data_frames.split_off(&vpn),
+            swapped: self.swapped.split_off(&vpn),
+            map_type: self.map_type,
+            map_perm: self.map_perm,
+            lazy: self.lazy,
+            shm: self.shm.clone(),
+            write_denied: self.write_denied,
+            stack_top: None,
+            file,
+        }
+    }
+
+    // set_permission 修改逻辑段的访问权限，同时更新已经映射的页表项。
+    // 仍然被多个逻辑段共享的页框保持只读，以便继续写时复制 (共享内存除外)。
+    // 重新映射 PROT_NONE 的页时可能需要创建页表，没有空闲页框时返回 None。
+    pub fn set_permission(
+        &mut self,
+        page_table: &mut PageTable,
+        map_perm: MapPermission,
+    ) -> Option<()> {
+        self.map_perm = map_perm;
+        for (&vpn, frame) in self.data_frames.iter() {
+            let mut perm = map_perm;
+            if !self.is_shared() && Arc::strong_count(frame) > 1 {
+                perm.remove(MapPermission::W);
+            }
+            let valid = page_table
+                .translate(vpn)
+                .map_or(false, |pte| pte.is_valid());
+            if perm.intersects(MapPermission::R | MapPermission::W | MapPermission::X) {
+                let pte_flags = PTEFlags::from_bits(perm.bits).unwrap();
+                if valid {
+                    page_table.remap(vpn, frame.ppn, pte_flags);
+                } else {
+                    page_table.map(vpn, frame.ppn, pte_flags)?;
+                }
+            } else if valid {
+                // 页表项不允许 V 有效但是 R/W/X 全为 0 (表示指向下一级页表)
+                page_table.unmap(vpn);
+            }
+        }
+        Some(())
+    }
+
+    // cow 处理写时复制 (copy on write)：如果页框只被当前逻辑段引用，
+    // 则直接恢复写权限，否则申请一个新的页框并拷贝原页框的数据，没有空闲页框时返回 None。
+    pub fn cow(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) -> Option<()> {
+        let pte_flags = PTEFlags::from_bits(self.map_perm.bits).unwrap();
+        let frame = self.data_frames.get(&vpn).unwrap();
+        if Arc::strong_count(frame) == 1 {
+            page_table.remap(vpn, frame.ppn, pte_flags);
+            return Some(());
+        }
+
+        let new_frame = frame_alloc()?;
+        new_frame
+            .ppn
+            .get_bytes_array()
+            .copy_from_slice(frame.ppn.get_bytes_array());
+        page_table.remap(vpn, new_frame.ppn, pte_flags);
+        self.data_frames.insert(vpn, Arc::new(new_frame));
+        Some(())
+    }
+
+    // is_swappable 判断逻辑段中的页是否可以被换出，只有用户的私有逻辑段可以换出，
+    // 私有文件映射中只有已经写时复制过的页可以换出。
+    fn is_swappable(&self) -> bool {
+        self.map_type != MapType::Identical
+            && self.map_perm.contains(MapPermission::U)
+            && !self.is_shared()
+    }
+
+    // sync 将共享文件映射中被修改过 (D 位为 1) 的页写回文件
+    fn sync(&self, page_table: &mut PageTable) {
+        let mapping = match &self.file {
+            Some(mapping) if mapping.shared => mapping,
+            _ => return,
+        };
+        for (&vpn, frame) in self.data_frames.iter() {
+            if page_table
+                .translate(vpn)
+                .map_or(false, |pte| pte.is_valid() && pte.dirty())
+            {
+                let offset = mapping.offset + (vpn.0 - self.vpn_range.get_start().0) * PAGE_SIZE;
+                mapping.file.write_at(offset, frame.ppn.get_bytes_array());
+                page_table.clear_dirty(vpn);
+            }
+        }
+    }
+
+    // swap_out 将 vpn 对应的页写入交换区并释放页框，返回 false 表示交换区已满
+    fn swap_out(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) -> bool {
+        let frame = self.data_frames.get(&vpn).unwrap();
+        let swap_tracker = match swap::swap_out(frame.ppn) {
+            Some(swap_tracker) => swap_tracker,
+            None => return false,
+        };
+        self.data_frames.remove(&vpn);
+        page_table.unmap(vpn);
+        self.swapped.insert(vpn, Arc::new(swap_tracker));
+        true
+    }
+
+    // swap_in 申请一个新的页框并从交换区读回 vpn 的数据，换入的页框只属于当前逻辑段。
+    // 没有空闲页框时返回 None，数据仍然保留在交换区中。
+    fn swap_in(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) -> Option<()> {
+        let frame = frame_alloc()?;
+        let pte_flags = PTEFlags::from_bits(self.map_perm.bits).unwrap();
+        page_table.map(vpn, frame.ppn, pte_flags)?;
+        let swap_tracker = self.swapped.remove(&vpn).unwrap();
+        swap_tracker.swap_in(frame.ppn);
+        self.data_frames.insert(vpn, Arc::new(frame));
+        Some(())
     }
 
     // copy_data 将 data 的数据拷贝到当前逻辑段中对应的物理内存中。
     // 需要注意的是 data 长度不能超过当前逻辑段的长度，按页为单位拷贝。
-    pub fn copy_data(&mut self, page_table: &mut PageTable, data: &[u8]) {
+    // 对于 lazy 逻辑段，只有被 data 覆盖的页会被分配页框。没有空闲页框时返回 None。
+    pub fn copy_data(&mut self, page_table: &mut PageTable, data: &[u8]) -> Option<()> {
         assert_eq!(self.map_type, MapType::Framed);
         let mut start = 0;
         let mut current_vpn = self.vpn_range.get_start();
         let len = data.len();
         loop {
+            if !self.is_mapped(current_vpn) {
+                self.map_one(page_table, current_vpn)?;
+            }
             let src = &data[start..len.min(start + PAGE_SIZE)];
             let dst = &mut page_table
                 .translate(current_vpn)
@@ -142,19 +488,93 @@ impl MapArea {
             }
             current_vpn.step();
         }
+        Some(())
     }
 }
 
+// PageFaultError 是 page fault 无法被处理的原因
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PageFaultError {
+    // 访问了没有映射的地址或者没有访问权限
+    InvalidAccess,
+    // 换出页之后仍然没有空闲页框
+    OutOfMemory,
+}
+
+// MemoryStat 是一个地址空间的内存使用情况，单位都是页
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct MemoryStat {
+    // 全部逻辑段的大小 (VSZ)
+    pub vsz: usize,
+    // 位于物理内存中的页数 (RSS)
+    pub rss: usize,
+    // 被换出到交换区的页数
+    pub swap: usize,
+    // 页表自身占用的页框数量
+    pub page_table: usize,
+    // rss 的峰值
+    pub peak_rss: usize,
+}
+
 pub struct MemorySet {
     page_table: PageTable,
     areas: Vec<MapArea>,
+    // clock 算法的指针，下一次从这个 vpn 开始查找换出的页
+    clock_hand: VirtPageNum,
+    // user stack 的最大长度 (字节)
+    stack_limit: usize,
+    // 地址空间的最大大小 (字节)，新建或者扩展逻辑段之后 VSZ 不能超过它
+    as_limit: usize,
+    // brk 管理的 heap 逻辑段的起始位置，内核地址空间没有 heap
+    heap_start: Option<VirtPageNum>,
+    // rss 的峰值，在可能分配页框的操作之后更新
+    peak_rss: usize,
 }
 
 impl MemorySet {
-    fn new_bare() -> Self {
-        Self {
-            page_table: PageTable::new(),
+    fn new_bare() -> Option<Self> {
+        Some(Self {
+            page_table: PageTable::new()?,
             areas: Vec::new(),
+            clock_hand: VirtPageNum(0),
+            stack_limit: config::USER_STACK_LIMIT,
+            as_limit: usize::MAX,
+            heap_start: None,
+            peak_rss: 0,
+        })
+    }
+
+    fn rss(&self) -> usize {
+        self.areas.iter().map(|area| area.resident_pages()).sum()
+    }
+
+    fn update_peak_rss(&mut self) {
+        self.peak_rss = self.peak_rss.max(self.rss());
+    }
+
+    fn vsz(&self) -> usize {
+        self.areas.iter().map(|area| area.mapped_pages()).sum()
+    }
+
+    // within_as_limit 判断再映射 pages 个页之后 VSZ 是否仍然不超过 as_limit
+    pub fn within_as_limit(&self, pages: usize) -> bool {
+        self.vsz() + pages <= self.as_limit / PAGE_SIZE
+    }
+
+    // set_as_limit 设置地址空间的最大大小，已经映射的部分不会被解除
+    pub fn set_as_limit(&mut self, limit: usize) {
+        self.as_limit = limit;
+    }
+
+    // stat 返回地址空间的内存使用情况
+    pub fn stat(&self) -> MemoryStat {
+        MemoryStat {
+            vsz: self.vsz(),
+            rss: self.rss(),
+            swap: self.areas.iter().map(|area| area.swapped.len()).sum(),
+            page_table: self.page_table.frame_count(),
+            peak_rss: self.peak_rss,
         }
     }
 
@@ -162,17 +582,21 @@ impl MemorySet {
         self.page_table.token()
     }
 
-    // insert_framed_area 将逻辑地址映射到 memory set 中。
+    pub fn root_ppn(&self) -> PhysPageNum {
+        self.page_table.root_ppn()
+    }
+
+    // insert_framed_area 将逻辑地址映射到 memory set 中，没有空闲页框时返回 None。
     pub fn insert_framed_area(
         &mut self,
         start_va: VirtAddr,
         end_va: VirtAddr,
         permission: MapPermission,
-    ) {
+    ) -> Option<()> {
         self.push(
             MapArea::new(start_va, end_va, MapType::Framed, permission),
             None,
-        );
+        )
     }
 
     // 从 memory_set 中移除一个指定的 map_area
@@ -188,25 +612,289 @@ impl MemorySet {
         }
     }
 
+    // overlaps_heap 判断 [start, end) 是否与 brk 管理的 heap 重叠，长度为 0 的 heap 看作
+    // 占用 heap_start 所在的一页。heap 逻辑段被拆分或者移除之后 brk 无法再调整它，
+    // 所以 munmap 和 mprotect 不能修改 heap。
+    pub fn overlaps_heap(&self, start: VirtPageNum, end: VirtPageNum) -> bool {
+        let heap_start = match self.heap_start {
+            Some(heap_start) => heap_start,
+            None => return false,
+        };
+        let heap_end = self
+            .areas
+            .iter()
+            .find(|area| area.vpn_range.get_start() == heap_start)
+            .map_or(heap_start, |area| area.vpn_range.get_end());
+        heap_start < end && heap_end.0.max(heap_start.0 + 1) > start.0
+    }
+
+    // is_range_free 判断 [start, end) 是否与已有逻辑段重叠
+    pub fn is_range_free(&self, start: VirtPageNum, end: VirtPageNum) -> bool {
+        self.areas
+            .iter()
+            .all(|area| area.vpn_range.get_end() <= start || area.vpn_range.get_start() >= end)
+    }
+
+    // is_range_covered 判断 [start, end) 是否完全被用户逻辑段覆盖
+    pub fn is_range_covered(&self, start: VirtPageNum, end: VirtPageNum) -> bool {
+        let mut vpn = start;
+        while vpn < end {
+            match self.areas.iter().find(|area| {
+                area.map_perm.contains(MapPermission::U) && area.vpn_range.contains(vpn)
+            }) {
+                Some(area) => vpn = area.vpn_range.get_end(),
+                None => return false,
+            }
+        }
+        true
+    }
+
+    // is_write_denied 判断 [start, end) 中是否有不能加上写权限的逻辑段
+    pub fn is_write_denied(&self, start: VirtPageNum, end: VirtPageNum) -> bool {
+        self.areas.iter().any(|area| {
+            area.write_denied
+                && area.vpn_range.get_start() < end
+                && area.vpn_range.get_end() > start
+        })
+    }
+
+    // find_free_area 从 hint 开始查找一段长度为 page_count 且没有被映射的虚拟地址，
+    // 查找范围不超过 limit。
+    pub fn find_free_area(
+        &self,
+        hint: VirtPageNum,
+        page_count: usize,
+        limit: VirtPageNum,
+    ) -> Option<VirtPageNum> {
+        let mut start = hint;
+        while start.0 + page_count <= limit.0 {
+            let end = VirtPageNum(start.0 + page_count);
+            match self
+                .areas
+                .iter()
+                .filter(|area| area.vpn_range.get_end() > start && area.vpn_range.get_start() < end)
+                .map(|area| area.vpn_range.get_end())
+                .max()
+            {
+                Some(area_end) => start = area_end,
+                None => return Some(start),
+            }
+        }
+        None
+    }
+
+    // mmap 在 [start, end) 插入一个按需分配的匿名逻辑段，调用者需要保证该区域空闲。
+    // 返回 false 表示映射之后会超过 as_limit。
+    pub fn mmap(
+        &mut self,
+        start: VirtPageNum,
+        end: VirtPageNum,
+        permission: MapPermission,
+    ) -> bool {
+        if !self.within_as_limit(end.0 - start.0) {
+            return false;
+        }
+        // lazy 逻辑段在 push 时不会分配页框
+        self.push(
+            MapArea::new_lazy(start.into(), end.into(), permission),
+            None,
+        )
+        .unwrap();
+        true
+    }
+
+    // mmap_file 将 file 从 offset 开始的内容映射到 [start, end)，调用者需要保证该区域空闲。
+    // 不可写文件的共享映射不能通过 mprotect 加上写权限。返回 false 表示超过了 as_limit。
+    pub fn mmap_file(
+        &mut self,
+        start: VirtPageNum,
+        end: VirtPageNum,
+        permission: MapPermission,
+        file: Arc<dyn File>,
+        offset: usize,
+        shared: bool,
+    ) -> bool {
+        if !self.within_as_limit(end.0 - start.0) {
+            return false;
+        }
+        let writable = file.writable();
+        let mut area =
+            MapArea::new_file(start.into(), end.into(), permission, file, offset, shared);
+        area.write_denied = shared && !writable;
+        self.push(area, None).unwrap();
+        true
+    }
+
+    // attach_shm 从 start 开始映射共享内存段 segment，调用者需要保证该区域空闲。
+    // 映射之后会超过 as_limit 或者没有空闲页框创建页表时返回 None。
+    pub fn attach_shm(
+        &mut self,
+        start: VirtPageNum,
+        segment: Arc<ShmSegment>,
+        permission: MapPermission,
+    ) -> Option<()> {
+        let area = MapArea::new_shared(start.into(), segment, permission);
+        if !self.within_as_limit(area.mapped_pages()) {
+            return None;
+        }
+        self.push(area, None)
+    }
+
+    // detach_shm 解除起始位置为 start 的共享内存逻辑段的映射，
+    // 返回 false 表示 start 处没有映射共享内存。
+    pub fn detach_shm(&mut self, start: VirtPageNum) -> bool {
+        match self
+            .areas
+            .iter()
+            .position(|area| area.shm.is_some() && area.vpn_range.get_start() == start)
+        {
+            Some(idx) => {
+                let mut area = self.areas.remove(idx);
+                area.unmap(&mut self.page_table);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // resize_area 将起始位置为 start 的逻辑段的结束位置调整为 new_end，
+    // 增长的部分不能与其他逻辑段重叠。返回 false 表示调整失败 (包括没有空闲页框和
+    // 超过 as_limit)。
+    pub fn resize_area(&mut self, start: VirtPageNum, new_end: VirtPageNum) -> bool {
+        let idx = match self
+            .areas
+            .iter()
+            .position(|area| area.vpn_range.get_start() == start)
+        {
+            Some(idx) => idx,
+            None => return false,
+        };
+        let old_end = self.areas[idx].vpn_range.get_end();
+        if new_end < start {
+            return false;
+        }
+        if new_end < old_end {
+            self.areas[idx].shrink_to(&mut self.page_table, new_end);
+        } else if new_end > old_end {
+            if !self.is_range_free(old_end, new_end) || !self.within_as_limit(new_end.0 - old_end.0)
+            {
+                return false;
+            }
+            if self.areas[idx]
+                .append_to(&mut self.page_table, new_end)
+                .is_none()
+            {
+                return false;
+            }
+            self.update_peak_rss();
+        }
+        true
+    }
+
+    // munmap 解除 [start, end) 中全部用户逻辑段的映射，部分重叠的逻辑段会被拆分，
+    // 只有重叠的部分会被移除。
+    pub fn munmap(&mut self, start: VirtPageNum, end: VirtPageNum) {
+        let mut idx = 0;
+        while idx < self.areas.len() {
+            if !self.split_area(idx, start, end) {
+                idx += 1;
+                continue;
+            }
+            let mut area = self.areas.remove(idx);
+            area.sync(&mut self.page_table);
+            area.unmap(&mut self.page_table);
+        }
+    }
+
+    // msync 将 [start, end) 中的共享文件映射写回文件，以逻辑段为单位写回。
+    // 返回 false 表示该区域没有被完全映射。
+    pub fn msync(&mut self, start: VirtPageNum, end: VirtPageNum) -> bool {
+        if !self.is_range_covered(start, end) {
+            return false;
+        }
+        for area in self.areas.iter() {
+            if area.vpn_range.get_end() > start && area.vpn_range.get_start() < end {
+                area.sync(&mut self.page_table);
+            }
+        }
+        true
+    }
+
+    // mprotect 修改 [start, end) 的访问权限，返回 false 表示该区域没有被完全映射
+    // 或者没有空闲页框
+    pub fn mprotect(
+        &mut self,
+        start: VirtPageNum,
+        end: VirtPageNum,
+        permission: MapPermission,
+    ) -> bool {
+        if !self.is_range_covered(start, end) {
+            return false;
+        }
+        let mut idx = 0;
+        while idx < self.areas.len() {
+            if self.split_area(idx, start, end)
+                && self.areas[idx]
+                    .set_permission(&mut self.page_table, permission)
+                    .is_none()
+            {
+                return false;
+            }
+            idx += 1;
+        }
+        true
+    }
+
+    // split_area 将第 idx 个用户逻辑段中与 [start, end) 重叠的部分拆分出来并保存在
+    // 第 idx 个位置，其余部分插入到它的后面。返回 false 表示没有重叠的部分。
+    fn split_area(&mut self, idx: usize, start: VirtPageNum, end: VirtPageNum) -> bool {
+        let area = &mut self.areas[idx];
+        if !area.map_perm.contains(MapPermission::U)
+            || area.vpn_range.get_end() <= start
+            || area.vpn_range.get_start() >= end
+        {
+            return false;
+        }
+        if area.vpn_range.get_end() > end {
+            let tail = area.split_off(end);
+            self.areas.insert(idx + 1, tail);
+        }
+        let area = &mut self.areas[idx];
+        if area.vpn_range.get_start() < start {
+            let middle = area.split_off(start);
+            self.areas.insert(idx + 1, middle);
+            self.areas.swap(idx, idx + 1);
+        }
+        true
+    }
+
     // push 将逻辑段内容映射到物理内存中，如果有数据则深拷贝数据，最后将 map_area 保存到 mmset 中。
-    fn push(&mut self, mut map_area: MapArea, data: Option<&[u8]>) {
-        map_area.map(&mut self.page_table);
-        if let Some(data) = data {
-            map_area.copy_data(&mut self.page_table, data);
+    // 没有空闲页框时释放已经建立的映射并返回 None。
+    fn push(&mut self, mut map_area: MapArea, data: Option<&[u8]>) -> Option<()> {
+        let mapped = map_area.map(&mut self.page_table).and_then(|_| match data {
+            Some(data) => map_area.copy_data(&mut self.page_table, data),
+            None => Some(()),
+        });
+        if mapped.is_none() {
+            map_area.unmap(&mut self.page_table);
+            return None;
         }
         self.areas.push(map_area);
+        self.update_peak_rss();
+        Some(())
     }
 
-    fn map_trampoline(&mut self) {
-        let vpn: VirtPageNum = VirtAddr::from(TRAMPOLINE).into();
+    fn map_trampoline(&mut self) -> Option<()> {
+        let vpn: VirtPageNum = VirtAddr::from(config::trampoline()).into();
         let ppn: PhysPageNum = PhysAddr::from(strampoline as usize).into();
-        self.page_table.map(vpn, ppn, PTEFlags::R | PTEFlags::X);
+        self.page_table.map(vpn, ppn, PTEFlags::R | PTEFlags::X)
     }
 
     pub fn new_kernel() -> Self {
-        let mut memory_set = Self::new_bare();
+        // 内核地址空间在启动时创建，此时页框一定足够
+        let mut memory_set = Self::new_bare().unwrap();
         // high kernel address space
-        memory_set.map_trampoline();
+        memory_set.map_trampoline().unwrap();
 
         // low kernel address space
         println!(".text [{:#x}, {:#x})", stext as usize, etext as usize);
@@ -224,7 +912,7 @@ impl MemorySet {
             MapType::Identical,
             MapPermission::R | MapPermission::X,
         );
-        memory_set.push(text_map_area, None);
+        memory_set.push(text_map_area, None).unwrap();
 
         println!("mapping .rodata section");
         let rodata_map_area = MapArea::new(
@@ -233,7 +921,7 @@ impl MemorySet {
             MapType::Identical,
             MapPermission::R,
         );
-        memory_set.push(rodata_map_area, None);
+        memory_set.push(rodata_map_area, None).unwrap();
 
         println!("mapping .data section");
         let data_map_area = MapArea::new(
@@ -242,7 +930,7 @@ impl MemorySet {
             MapType::Identical,
             MapPermission::R | MapPermission::W,
         );
-        memory_set.push(data_map_area, None);
+        memory_set.push(data_map_area, None).unwrap();
 
         println!("mapping .bss section");
         let bss_map_area = MapArea::new(
@@ -251,16 +939,30 @@ impl MemorySet {
             MapType::Identical,
             MapPermission::R | MapPermission::W,
         );
-        memory_set.push(bss_map_area, None);
+        memory_set.push(bss_map_area, None).unwrap();
 
-        println!("mapping physical memory");
-        let phy_mem_map_area = MapArea::new(
-            (ekernel as usize).into(),
-            MEMORY_END.into(),
-            MapType::Identical,
-            MapPermission::R | MapPermission::W,
-        );
-        memory_set.push(phy_mem_map_area, None);
+        for (start, end) in fdt::usable_memory(ekernel as usize) {
+            println!("mapping physical memory [{:#x}, {:#x})", start, end);
+            let phy_mem_map_area = MapArea::new(
+                start.into(),
+                end.into(),
+                MapType::Identical,
+                MapPermission::R | MapPermission::W,
+            );
+            memory_set.push(phy_mem_map_area, None).unwrap();
+        }
+
+        // 内核堆区域的页表项在扩展内核堆时才会被填写
+        let heap_start = config::kernel_heap_base();
+        let heap_end = heap_start + config::KERNEL_HEAP_MAX_SIZE;
+        println!("reserving kernel heap [{:#x}, {:#x})", heap_start, heap_end);
+        memory_set
+            .page_table
+            .prepare(
+                VirtAddr::from(heap_start).floor(),
+                VirtAddr::from(heap_end).floor(),
+            )
+            .unwrap();
 
         println!("kernel's memory set was loaded");
 
@@ -269,103 +971,698 @@ impl MemorySet {
 
     // from_elf 根据 elf 文件创建一个 mmset，
     // 完成的事情包括验证 elf 文件是否合法，根据 program headers 加载数据的逻辑段，
-    // 设置 user stack，以及设置 trap context 地址。
+    // 设置 heap 和 user stack，以及设置 trap context 地址。
     // returns:
     //  - memory_set
     //  - user stack 栈顶虚拟地址
-    //  - app 入口地址
-    pub fn from_elf(elf_data: &[u8]) -> (Self, usize, usize) {
-        let mut memory_set = Self::new_bare();
-        memory_set.map_trampoline();
-
-        // read elf header
-        let elf = xmas_elf::ElfFile::new(elf_data).unwrap();
-        let elf_header = elf.header;
-        let magic = elf_header.pt1.magic;
-        assert_eq!(magic, [0x7f, 0x45, 0x4c, 0x46], "invalid elf!");
-
-        // load program from program headers
-        let ph_count = elf_header.pt2.ph_count();
-        let mut max_end_vpn = VirtPageNum(0);
-        for i in 0..ph_count {
-            let ph = elf.program_header(i).unwrap();
-            if ph.get_type().unwrap() == xmas_elf::program::Type::Load {
-                let start_va: VirtAddr = (ph.virtual_addr() as usize).into();
-                let end_va: VirtAddr = ((ph.virtual_addr() + ph.mem_size()) as usize).into();
-                let mut map_perm = MapPermission::U;
-                let ph_flags = ph.flags();
-                if ph_flags.is_read() {
-                    map_perm |= MapPermission::R;
-                }
-                if ph_flags.is_write() {
-                    map_perm |= MapPermission::W;
-                }
-                if ph_flags.is_execute() {
-                    map_perm |= MapPermission::X;
-                }
-                let map_area = MapArea::new(start_va, end_va, MapType::Framed, map_perm);
-                max_end_vpn = map_area.vpn_range.get_end();
-                memory_set.push(
-                    map_area,
-                    Some(&elf.input[ph.offset() as usize..(ph.offset() + ph.file_size()) as usize]),
-                );
-            }
+    //  - heap 起始虚拟地址，也就是程序镜像的结束位置
+    //  - elf 文件的信息，比如 app 入口地址
+    // elf 文件不合法或者没有空闲页框时返回错误
+    pub fn from_elf(file: &Arc<dyn File>) -> Result<(Self, usize, usize, ElfInfo), ElfLoadError> {
+        let elf_data = read_elf_headers(file);
+        let (segments, mut elf_info) = parse_elf(&elf_data, file.size(), aslr::pie_load_bias())?;
+
+        let mut memory_set = Self::new_bare().ok_or(ElfLoadError::OutOfMemory)?;
+        memory_set
+            .map_trampoline()
+            .ok_or(ElfLoadError::OutOfMemory)?;
+        memory_set
+            .load_segments(file, &segments)
+            .ok_or(ElfLoadError::OutOfMemory)?;
+        if elf_info.interp.is_some() {
+            // 有动态链接器的程序由动态链接器负责重定位和符号解析
+            memory_set.load_interp(file, &segments, &mut elf_info)?;
+        } else {
+            memory_set.apply_relocations(file, &segments, &elf_info)?;
         }
+        let max_end_vpn = segments
+            .iter()
+            .map(|segment| VirtAddr::from(segment.end()).ceil())
+            .max()
+            .unwrap();
 
         let max_end_va: VirtAddr = max_end_vpn.into();
-        let mut user_stack_bottom: usize = max_end_va.into();
-        // guard page
-        user_stack_bottom += config::PAGE_SIZE;
+        let mut heap_bottom: usize = max_end_va.into();
+        // 初始线程的 TLS 块紧跟在程序镜像之后
+        if let Some(tls) = elf_info.tls {
+            let (tls_pointer, tls_end) = memory_set
+                .load_tls(file, &tls, heap_bottom)
+                .ok_or(ElfLoadError::OutOfMemory)?;
+            elf_info.tls_pointer = tls_pointer;
+            heap_bottom = VirtAddr::from(tls_end).ceil().into();
+        }
+
+        // heap 紧跟在程序镜像 (以及 TLS 块) 之后，初始长度为 0，通过 brk 系统调用增长或者收缩。
+        // heap 和 user stack 都是 lazy 逻辑段，push 时不会分配页框。
+        memory_set
+            .push(
+                MapArea::new_lazy(
+                    heap_bottom.into(),
+                    heap_bottom.into(),
+                    MapPermission::R | MapPermission::W | MapPermission::U,
+                ),
+                None,
+            )
+            .unwrap();
+        memory_set.heap_start = Some(VirtAddr::from(heap_bottom).floor());
 
         // user stack
-        let user_stack_top = user_stack_bottom + config::USER_STACK_SIZE;
+        let user_stack_top = aslr::stack_top();
+        let user_stack_bottom = user_stack_top - config::USER_STACK_SIZE;
         let user_stack_start_va = user_stack_bottom.into();
         let user_stack_end_va = user_stack_top.into();
-        let user_stack_map_area = MapArea::new(
+        let mut user_stack_map_area = MapArea::new_lazy(
             user_stack_start_va,
             user_stack_end_va,
-            MapType::Framed,
             MapPermission::R | MapPermission::W | MapPermission::U,
         );
-        memory_set.push(user_stack_map_area, None);
+        user_stack_map_area.stack_top = Some(VirtAddr::from(user_stack_top).floor());
+        memory_set.push(user_stack_map_area, None).unwrap();
 
         // trap context
         let trap_ctx_map_area = MapArea::new(
-            config::TRAP_CONTEXT.into(),
-            config::TRAMPOLINE.into(),
+            config::trap_context().into(),
+            config::trampoline().into(),
             MapType::Framed,
             MapPermission::R | MapPermission::W,
         );
-        memory_set.push(trap_ctx_map_area, None);
+        memory_set
+            .push(trap_ctx_map_area, None)
+            .ok_or(ElfLoadError::OutOfMemory)?;
 
-        (
-            memory_set,
-            user_stack_top,
-            elf.header.pt2.entry_point() as usize,
-        )
+        Ok((memory_set, user_stack_top, heap_bottom, elf_info))
+    }
+
+    // load_segments 加载全部 PT_LOAD 段，segments 按照虚拟地址排列且互不重叠。
+    // 相邻段的首尾可能位于同一页中，这样的页只能属于一个逻辑段：它被单独映射为一个
+    // 私有页，权限为所有相关段权限的并集，内容从各个段中拷贝。没有空闲页框时返回 None。
+    fn load_segments(&mut self, file: &Arc<dyn File>, segments: &[ElfSegment]) -> Option<()> {
+        let mut shared_vpns: Vec<VirtPageNum> = segments
+            .windows(2)
+            .filter(|pair| pair[0].end_vpn() > pair[1].start_vpn())
+            .map(|pair| pair[1].start_vpn())
+            .collect();
+        shared_vpns.dedup();
+
+        for segment in segments {
+            let mut start_vpn = segment.start_vpn();
+            let mut end_vpn = segment.end_vpn();
+            if shared_vpns.contains(&start_vpn) {
+                start_vpn.step();
+            }
+            if end_vpn > start_vpn && shared_vpns.contains(&VirtPageNum(end_vpn.0 - 1)) {
+                end_vpn = VirtPageNum(end_vpn.0 - 1);
+            }
+            let start = VirtAddr::from(start_vpn).0.max(segment.vaddr);
+            let end = VirtAddr::from(end_vpn).0.min(segment.end());
+            if start >= end {
+                continue;
+            }
+            let skip = start - segment.vaddr;
+            self.load_segment(
+                file,
+                start,
+                end - start,
+                segment.offset + skip,
+                segment.file_size.saturating_sub(skip).min(end - start),
+                segment.map_perm,
+            )?;
+        }
+
+        for &vpn in shared_vpns.iter() {
+            let page_start = VirtAddr::from(vpn).0;
+            let page_end = page_start + PAGE_SIZE;
+            let owners: Vec<&ElfSegment> = segments
+                .iter()
+                .filter(|segment| segment.start_vpn() <= vpn && vpn < segment.end_vpn())
+                .collect();
+            let map_perm = owners
+                .iter()
+                .fold(MapPermission::U, |perm, segment| perm | segment.map_perm);
+            self.push(
+                MapArea::new(
+                    page_start.into(),
+                    page_end.into(),
+                    MapType::Framed,
+                    map_perm,
+                ),
+                None,
+            )?;
+            // 新的页框已经被清零，只需要拷贝文件中的部分
+            let bytes = self.translate(vpn).unwrap().ppn().get_bytes_array();
+            for segment in owners {
+                let start = segment.vaddr.max(page_start);
+                let end = (segment.vaddr + segment.file_size).min(page_end);
+                if start < end {
+                    file.read_at(
+                        segment.offset + start - segment.vaddr,
+                        &mut bytes[start - page_start..end - page_start],
+                    );
+                }
+            }
+        }
+        Some(())
+    }
+
+    // load_segment 映射一个 PT_LOAD 段：文件中的部分通过 page cache 私有映射，
+    // 超出文件的部分 (.bss) 使用按需分配的匿名页。没有空闲页框时返回 None。
+    fn load_segment(
+        &mut self,
+        file: &Arc<dyn File>,
+        vaddr: usize,
+        mem_size: usize,
+        offset: usize,
+        file_size: usize,
+        map_perm: MapPermission,
+    ) -> Option<()> {
+        let file_end = vaddr + file_size;
+        let mem_end = vaddr + mem_size;
+        if vaddr % PAGE_SIZE != offset % PAGE_SIZE {
+            // 虚拟地址与文件偏移在页内的位置不同，无法直接映射，只能拷贝。
+            // copy_data 从逻辑段的第一页开头开始拷贝，所以数据前面要补上页内偏移。
+            let page_offset = vaddr % PAGE_SIZE;
+            let mut data = vec![0u8; page_offset + file_size];
+            file.read_at(offset, &mut data[page_offset..]);
+            return self.push(
+                MapArea::new_lazy(vaddr.into(), mem_end.into(), map_perm),
+                Some(&data),
+            );
+        }
+
+        let mut anonymous_start = VirtAddr::from(vaddr).floor();
+        if file_size > 0 {
+            self.push(
+                MapArea::new_file(
+                    vaddr.into(),
+                    file_end.into(),
+                    map_perm,
+                    file.clone(),
+                    offset - vaddr % PAGE_SIZE,
+                    false,
+                ),
+                None,
+            )?;
+            anonymous_start = VirtAddr::from(file_end).ceil();
+            // 文件部分最后一页中 file_size 之后的内容属于 .bss，必须清零
+            let page_end = VirtAddr::from(anonymous_start).0;
+            if mem_end > file_end && file_end < page_end {
+                self.zero_fill(file_end, mem_end.min(page_end))?;
+            }
+        }
+        if VirtAddr::from(mem_end).ceil() > anonymous_start {
+            self.push(
+                MapArea::new_lazy(anonymous_start.into(), mem_end.into(), map_perm),
+                None,
+            )?;
+        }
+        Some(())
+    }
+
+    // load_interp 加载 PT_INTERP 指定的动态链接器。动态链接器必须是位置无关的，
+    // 被加载到 USER_INTERP_BASE 之上，由内核完成它自身的重定位 (见 user/src/bin/ld.rs)。
+    // 内核只有一个扁平的应用列表，因此按照路径的最后一部分查找动态链接器。
+    fn load_interp(
+        &mut self,
+        file: &Arc<dyn File>,
+        segments: &[ElfSegment],
+        elf_info: &mut ElfInfo,
+    ) -> Result<(), ElfLoadError> {
+        let (offset, size) = elf_info.interp.unwrap();
+        let mut path = vec![0u8; size];
+        file.read_at(offset, &mut path);
+        let path = core::str::from_utf8(&path)
+            .map_err(|_| "invalid interpreter path")?
+            .trim_end_matches('\0');
+        let name = path.rsplit('/').next().unwrap();
+        let interp_file = AppFile::open(name).ok_or("interpreter not found")?;
+
+        let interp_data = read_elf_headers(&interp_file);
+        let (interp_segments, interp_info) =
+            parse_elf(&interp_data, interp_file.size(), aslr::interp_load_bias())?;
+        if interp_info.load_bias == 0 || interp_info.interp.is_some() {
+            return Err("invalid interpreter".into());
+        }
+        if interp_segments.iter().any(|interp_segment| {
+            segments.iter().any(|segment| {
+                interp_segment.start_vpn() < segment.end_vpn()
+                    && segment.start_vpn() < interp_segment.end_vpn()
+            })
+        }) {
+            return Err("interpreter overlaps with program".into());
+        }
+        self.load_segments(&interp_file, &interp_segments)
+            .ok_or(ElfLoadError::OutOfMemory)?;
+        self.apply_relocations(&interp_file, &interp_segments, &interp_info)?;
+        elf_info.interp_base = interp_info.load_bias;
+        elf_info.interp_entry = interp_info.entry_point;
+        Ok(())
+    }
+
+    // load_tls 在 start 之后分配一个 TLS 块并用 PT_TLS 的初始化镜像填充，返回
+    // (TLS 块的地址, TLS 块的结束地址)。RISC-V 使用 TLS variant I 且 TCB 的大小为 0，
+    // 因此 tp 直接指向 TLS 块的开头。没有空闲页框时返回 None。
+    fn load_tls(
+        &mut self,
+        file: &Arc<dyn File>,
+        tls: &ElfTls,
+        start: usize,
+    ) -> Option<(usize, usize)> {
+        let tls_pointer = (start + tls.align - 1) & !(tls.align - 1);
+        let tls_end = tls_pointer + tls.mem_size.max(1);
+        self.push(
+            MapArea::new_lazy(
+                start.into(),
+                tls_end.into(),
+                MapPermission::R | MapPermission::W | MapPermission::U,
+            ),
+            None,
+        )?;
+        if tls.file_size > 0 {
+            let mut data = vec![0u8; tls.file_size];
+            file.read_at(tls.offset, &mut data);
+            self.write_image(tls_pointer, &data)?;
+        }
+        Some((tls_pointer, tls_end))
+    }
+
+    // apply_relocations 处理 PIE 程序的 R_RISCV_RELATIVE 重定位：在 load_bias + r_offset
+    // 处写入 load_bias + r_addend。链接器会为部分本地函数生成不引用符号的
+    // R_RISCV_JUMP_SLOT (位于 DT_JMPREL 表中)，它们的值同样是 load_bias + r_addend。
+    // 引用符号的重定位需要动态链接器，返回错误。
+    fn apply_relocations(
+        &mut self,
+        file: &Arc<dyn File>,
+        segments: &[ElfSegment],
+        elf_info: &ElfInfo,
+    ) -> Result<(), ElfLoadError> {
+        let (dynamic_offset, dynamic_size) = match elf_info.dynamic {
+            Some(dynamic) => dynamic,
+            None => return Ok(()),
+        };
+        let mut dynamic = vec![0u8; dynamic_size];
+        file.read_at(dynamic_offset, &mut dynamic);
+        let (mut rela, mut rela_size, mut rela_entry_size) = (None, 0, ELF64_RELA_SIZE);
+        let (mut jmprel, mut jmprel_size, mut pltrel) = (None, 0, DT_RELA);
+        for entry in dynamic.chunks_exact(ELF64_DYN_SIZE) {
+            let tag = u64::from_le_bytes(entry[..8].try_into().unwrap());
+            let value = u64::from_le_bytes(entry[8..].try_into().unwrap()) as usize;
+            match tag {
+                DT_NULL => break,
+                DT_RELA => rela = Some(value),
+                DT_RELASZ => rela_size = value,
+                DT_RELAENT => rela_entry_size = value,
+                DT_JMPREL => jmprel = Some(value),
+                DT_PLTRELSZ => jmprel_size = value,
+                DT_PLTREL => pltrel = value as u64,
+                _ => {}
+            }
+        }
+        if rela_entry_size != ELF64_RELA_SIZE {
+            return Err("invalid relocation entry size".into());
+        }
+        if pltrel != DT_RELA {
+            return Err("unsupported relocation type".into());
+        }
+        for (table, size) in [(rela, rela_size), (jmprel, jmprel_size)] {
+            if let Some(table) = table {
+                let table = table.wrapping_add(elf_info.load_bias);
+                self.apply_relocation_table(file, segments, elf_info, table, size)?;
+            }
+        }
+        Ok(())
+    }
+
+    // apply_relocation_table 处理虚拟地址 table 处长度为 size 的 RELA 重定位表
+    fn apply_relocation_table(
+        &mut self,
+        file: &Arc<dyn File>,
+        segments: &[ElfSegment],
+        elf_info: &ElfInfo,
+        table: usize,
+        size: usize,
+    ) -> Result<(), ElfLoadError> {
+        // 重定位表的地址是虚拟地址，需要转换为文件偏移再读取
+        let table_offset = segments
+            .iter()
+            .find(|segment| {
+                segment.vaddr <= table
+                    && table
+                        .checked_add(size)
+                        .map_or(false, |end| end <= segment.vaddr + segment.file_size)
+            })
+            .map(|segment| segment.offset + table - segment.vaddr)
+            .ok_or("relocation table is out of file")?;
+        let mut data = vec![0u8; size];
+        file.read_at(table_offset, &mut data);
+        for entry in data.chunks_exact(ELF64_RELA_SIZE) {
+            let offset = u64::from_le_bytes(entry[..8].try_into().unwrap()) as usize;
+            let info = u64::from_le_bytes(entry[8..16].try_into().unwrap());
+            let addend = u64::from_le_bytes(entry[16..].try_into().unwrap()) as usize;
+            match ((info & 0xffff_ffff) as u32, info >> 32) {
+                (R_RISCV_NONE, _) => {}
+                (R_RISCV_RELATIVE, 0) | (R_RISCV_JUMP_SLOT, 0) => {
+                    let va = offset.wrapping_add(elf_info.load_bias);
+                    let size = core::mem::size_of::<usize>();
+                    if !segments.iter().any(|segment| {
+                        segment.vaddr <= va
+                            && va
+                                .checked_add(size)
+                                .map_or(false, |end| end <= segment.end())
+                    }) {
+                        return Err("relocation is out of image".into());
+                    }
+                    let value = elf_info.load_bias.wrapping_add(addend);
+                    self.write_image(va, &value.to_le_bytes())
+                        .ok_or(ElfLoadError::OutOfMemory)?;
+                }
+                _ => return Err("unsupported relocation type".into()),
+            }
+        }
+        Ok(())
+    }
+
+    // image_page 返回程序镜像中 vpn 对应的页框，加载时内核需要修改这些页 (比如清零
+    // .bss 或者重定位)，所以来自 page cache 的页会先被复制为当前逻辑段私有的页。
+    // 没有空闲页框时返回 None。
+    fn image_page(&mut self, vpn: VirtPageNum) -> Option<PhysPageNum> {
+        let area = self
+            .areas
+            .iter_mut()
+            .find(|area| area.vpn_range.contains(vpn))
+            .unwrap();
+        if !area.is_mapped(vpn) {
+            area.map_one(&mut self.page_table, vpn)?;
+        }
+        area.cow(&mut self.page_table, vpn)?;
+        Some(area.data_frames.get(&vpn).unwrap().ppn)
+    }
+
+    // write_image 将 data 写入程序镜像中从 start 开始的位置
+    fn write_image(&mut self, start: usize, data: &[u8]) -> Option<()> {
+        let mut current = start;
+        let end = start + data.len();
+        while current < end {
+            let va = VirtAddr::from(current);
+            let chunk_end = (VirtAddr::from(va.floor()).0 + PAGE_SIZE).min(end);
+            let offset = va.page_offset();
+            self.image_page(va.floor())?.get_bytes_array()[offset..offset + chunk_end - current]
+                .copy_from_slice(&data[current - start..chunk_end - start]);
+            current = chunk_end;
+        }
+        Some(())
+    }
+
+    // zero_fill 将同一页中的 [start, end) 清零，该页会被复制为当前逻辑段私有的页
+    fn zero_fill(&mut self, start: usize, end: usize) -> Option<()> {
+        let vpn = VirtAddr::from(start).floor();
+        let offset = VirtAddr::from(start).page_offset();
+        self.image_page(vpn)?.get_bytes_array()[offset..offset + end - start].fill(0);
+        Some(())
     }
 
     //  创建并拷贝一个已有用户地址空间 (memory_set)
-    pub fn from_existed_user(user_space: &MemorySet) -> Self {
-        let mut memory_set = Self::new_bare();
-        memory_set.map_trampoline();
+    // 用户可以访问的逻辑段使用写时复制：父子进程共享同一组页框，同时去掉双方
+    // 页表项中的写权限，等到第一次写入触发 store page fault 时才真正拷贝。
+    // 父进程页表项被修改后不需要立即刷新 TLB，因为 trap_return 在切换回用户态之前
+    // 会刷新页表被修改过的地址空间。没有空闲页框时返回 None。
+    pub fn from_existed_user(user_space: &mut MemorySet) -> Option<Self> {
+        let mut memory_set = Self::new_bare()?;
+        memory_set.map_trampoline()?;
+        memory_set.stack_limit = user_space.stack_limit;
+        memory_set.as_limit = user_space.as_limit;
+        memory_set.heap_start = user_space.heap_start;
 
         for area in user_space.areas.iter() {
-            let new_map_area = MapArea::from_another(area);
-            memory_set.push(new_map_area, None);
-            for vpn in area.vpn_range {
-                let src_ppn = user_space.translate(vpn).unwrap().ppn();
-                let dst_ppn = memory_set.translate(vpn).unwrap().ppn();
-                dst_ppn
-                    .get_bytes_array()
-                    .copy_from_slice(src_ppn.get_bytes_array());
+            let mut new_map_area = MapArea::from_another(area);
+            if area.is_shared() {
+                // 共享内存和共享文件映射在 fork 之后仍然由父子进程共享
+                new_map_area.data_frames = area.data_frames.clone();
+                if area
+                    .map_perm
+                    .intersects(MapPermission::R | MapPermission::W | MapPermission::X)
+                {
+                    for &vpn in area.data_frames.keys() {
+                        new_map_area.map_one(&mut memory_set.page_table, vpn)?;
+                    }
+                }
+                memory_set.areas.push(new_map_area);
+            } else if area.map_type != MapType::Identical
+                && area.map_perm.contains(MapPermission::U)
+            {
+                let perm = area.map_perm - MapPermission::W;
+                let pte_flags = PTEFlags::from_bits(perm.bits).unwrap();
+                // PROT_NONE 逻辑段中的页没有页表项
+                let has_pte = perm.intersects(MapPermission::R | MapPermission::X);
+                for (&vpn, frame) in area.data_frames.iter() {
+                    if has_pte {
+                        memory_set.page_table.map(vpn, frame.ppn, pte_flags)?;
+                        user_space.page_table.remap(vpn, frame.ppn, pte_flags);
+                    }
+                    new_map_area.data_frames.insert(vpn, frame.clone());
+                }
+                new_map_area.swapped = area.swapped.clone();
+                memory_set.areas.push(new_map_area);
+            } else {
+                // trap context 会被内核通过物理地址直接访问，不能共享
+                memory_set.push(new_map_area, None)?;
+                for vpn in area.vpn_range {
+                    let src_ppn = user_space.translate(vpn).unwrap().ppn();
+                    let dst_ppn = memory_set.translate(vpn).unwrap().ppn();
+                    dst_ppn
+                        .get_bytes_array()
+                        .copy_from_slice(src_ppn.get_bytes_array());
+                }
             }
         }
+        memory_set.update_peak_rss();
 
-        memory_set
+        Some(memory_set)
+    }
+
+    // handle_page_fault 处理用户地址空间中的 page fault，access 是引起 page fault 的
+    // 访问类型 (R/W/X)。lazy 逻辑段中未映射的页会在这里分配页框，写时复制页会在这里
+    // 拷贝，无法处理时返回原因：非法访问，或者换出页之后仍然没有空闲页框。
+    pub fn handle_page_fault(
+        &mut self,
+        va: VirtAddr,
+        access: MapPermission,
+    ) -> Result<(), PageFaultError> {
+        let result = self.do_handle_page_fault(va, access);
+        if result.is_ok() {
+            self.update_peak_rss();
+        }
+        result
+    }
+
+    fn do_handle_page_fault(
+        &mut self,
+        va: VirtAddr,
+        access: MapPermission,
+    ) -> Result<(), PageFaultError> {
+        let vpn = va.floor();
+        if !self.areas.iter().any(|area| area.vpn_range.contains(vpn)) && !self.grow_stack(vpn) {
+            return Err(PageFaultError::InvalidAccess);
+        }
+        let area = self
+            .areas
+            .iter()
+            .find(|area| area.vpn_range.contains(vpn))
+            .unwrap();
+        if !area.map_perm.contains(access | MapPermission::U) {
+            return Err(PageFaultError::InvalidAccess);
+        }
+        let needs_frame = area.swapped.contains_key(&vpn)
+            || (area.lazy && !area.is_mapped(vpn))
+            || (access == MapPermission::W && self.is_cow_page(vpn));
+        if !needs_frame {
+            return Err(PageFaultError::InvalidAccess);
+        }
+        // 确认这次访问合法之后才预留页框。换出的页可能正是这次访问的页，
+        // 所以预留之后要重新检查映射状态
+        self.reserve_frames();
+        let area = self
+            .areas
+            .iter_mut()
+            .find(|area| area.vpn_range.contains(vpn))
+            .unwrap();
+        if area.swapped.contains_key(&vpn) {
+            return area
+                .swap_in(&mut self.page_table, vpn)
+                .ok_or(PageFaultError::OutOfMemory);
+        }
+        if area.lazy && !area.is_mapped(vpn) {
+            area.map_one(&mut self.page_table, vpn)
+                .ok_or(PageFaultError::OutOfMemory)?;
+            // 写入私有文件映射时直接复制 page cache 中的页，不用再触发一次 page fault
+            if area.map_type == MapType::File && access == MapPermission::W && !area.is_shared() {
+                area.cow(&mut self.page_table, vpn)
+                    .ok_or(PageFaultError::OutOfMemory)?;
+            }
+            return Ok(());
+        }
+        if access != MapPermission::W {
+            return Err(PageFaultError::InvalidAccess);
+        }
+        self.handle_cow_fault(vpn)
+    }
+
+    // stack_below 返回 vpn 上方最近的 user stack 逻辑段的下标
+    fn stack_below(&self, vpn: VirtPageNum) -> Option<usize> {
+        self.areas
+            .iter()
+            .enumerate()
+            .filter(|(_, area)| area.stack_top.is_some() && area.vpn_range.get_start() > vpn)
+            .min_by_key(|(_, area)| area.vpn_range.get_start())
+            .map(|(idx, _)| idx)
+    }
+
+    // grow_stack 将 vpn 上方的 user stack 逻辑段向下扩展到 vpn，扩展之后的长度不能超过
+    // stack_limit，VSZ 不能超过 as_limit，并且与下方的逻辑段之间至少保留一个保护页。
+    // 返回 false 表示栈溢出或者 vpn 上方没有 user stack。
+    fn grow_stack(&mut self, vpn: VirtPageNum) -> bool {
+        let idx = match self.stack_below(vpn) {
+            Some(idx) => idx,
+            None => return false,
+        };
+        let start = self.areas[idx].vpn_range.get_start();
+        let top = self.areas[idx].stack_top.unwrap();
+        if (top.0 - vpn.0) * PAGE_SIZE > self.stack_limit
+            || vpn.0 == 0
+            || !self.is_range_free(VirtPageNum(vpn.0 - 1), start)
+            || !self.within_as_limit(start.0 - vpn.0)
+        {
+            return false;
+        }
+        self.areas[idx].extend_down_to(vpn);
+        true
+    }
+
+    // is_stack_overflow 判断访问 va 引起的 page fault 是否是因为 user stack 超过了
+    // stack_limit 或者碰到了下方的逻辑段
+    pub fn is_stack_overflow(&self, va: VirtAddr) -> bool {
+        let vpn = va.floor();
+        match self.stack_below(vpn) {
+            Some(idx) => {
+                let top = self.areas[idx].stack_top.unwrap();
+                !self.areas.iter().any(|area| area.vpn_range.contains(vpn))
+                    && (top.0 - vpn.0) * PAGE_SIZE <= self.stack_limit.saturating_add(PAGE_SIZE)
+            }
+            None => false,
+        }
+    }
+
+    // set_stack_limit 设置 user stack 的最大长度，已经超过 limit 的部分不会被释放
+    pub fn set_stack_limit(&mut self, limit: usize) {
+        self.stack_limit = limit;
+    }
+
+    // is_cow_page 判断 vpn 是否是一个写时复制页：已经映射但是不可写，
+    // 并且所在的逻辑段允许写入
+    fn is_cow_page(&self, vpn: VirtPageNum) -> bool {
+        match self.page_table.translate(vpn) {
+            Some(pte) if pte.is_valid() && !pte.writable() => {}
+            _ => return false,
+        }
+        self.areas.iter().any(|area| {
+            area.vpn_range.contains(vpn)
+                && area.map_perm.contains(MapPermission::W)
+                && area.data_frames.contains_key(&vpn)
+        })
     }
 
-    // activate 设置根页表地址并启用 SV39 分页
+    // handle_cow_fault 处理由写时复制引起的 store page fault，
+    // vpn 不是一个写时复制页时返回 InvalidAccess。
+    fn handle_cow_fault(&mut self, vpn: VirtPageNum) -> Result<(), PageFaultError> {
+        if !self.is_cow_page(vpn) {
+            return Err(PageFaultError::InvalidAccess);
+        }
+        self.areas
+            .iter_mut()
+            .find(|area| area.vpn_range.contains(vpn))
+            .unwrap()
+            .cow(&mut self.page_table, vpn)
+            .ok_or(PageFaultError::OutOfMemory)
+    }
+
+    // reserve_frames 保证至少有 swap::RESERVED_FRAMES 个空闲页框，先释放 page cache
+    // 中没有被映射的页，再从当前地址空间换出页，不够时从就绪队列中的其他进程换出。
+    fn reserve_frames(&mut self) {
+        while frame_free_count() < swap::RESERVED_FRAMES {
+            if !page_cache::shrink()
+                && !self.swap_out_one()
+                && !task::manager::swap_out_from_ready_tasks()
+            {
+                break;
+            }
+        }
+    }
+
+    // swap_out_one 使用 clock 算法 (second chance) 选择一个只属于当前地址空间的用户页
+    // 并将其换出，优先选择最近没有被访问并且没有被修改的页。返回 false 表示没有
+    // 可以换出的页或者交换区已满。
+    // 页表项被修改后不需要立即刷新 TLB，trap_return 在切换回用户态之前会刷新。
+    pub fn swap_out_one(&mut self) -> bool {
+        let first = match self.next_clock_page(self.clock_hand) {
+            Some(vpn) => vpn,
+            None => return false,
+        };
+        let mut victim = None;
+        'search: for round in 0..4 {
+            // 偶数轮查找 A = 0 且 D = 0 的页，不修改页表项；
+            // 奇数轮查找 A = 0 的页，同时清除经过的页的 A 位，给它们第二次机会
+            let second_chance = round % 2 == 1;
+            let mut vpn = first;
+            loop {
+                let pte = self.page_table.translate(vpn).unwrap();
+                if !pte.accessed() && (second_chance || !pte.dirty()) {
+                    victim = Some(vpn);
+                    break 'search;
+                }
+                if second_chance {
+                    self.page_table.clear_accessed(vpn);
+                }
+                vpn = self.next_clock_page(VirtPageNum(vpn.0 + 1)).unwrap();
+                if vpn == first {
+                    break;
+                }
+            }
+        }
+        // 两轮之后所有页的 A 位都已经被清除，一定能找到换出的页
+        let victim = victim.unwrap();
+        self.clock_hand = VirtPageNum(victim.0 + 1);
+        self.areas
+            .iter_mut()
+            .find(|area| area.vpn_range.contains(victim))
+            .unwrap()
+            .swap_out(&mut self.page_table, victim)
+    }
+
+    // next_clock_page 返回从 vpn 开始 (包括 vpn) 的第一个可以换出的页，到达地址空间末尾时
+    // 从头开始查找。每个逻辑段的 data_frames 按照 vpn 排序，所以不需要每次换出时收集并排序
+    // 全部的页，只属于当前地址空间并且已经映射的页才能被换出。
+    fn next_clock_page(&self, vpn: VirtPageNum) -> Option<VirtPageNum> {
+        let find = |start: VirtPageNum| {
+            self.areas
+                .iter()
+                .filter(|area| area.is_swappable())
+                .filter_map(|area| {
+                    area.data_frames
+                        .range(start..)
+                        .find(|(&vpn, frame)| {
+                            Arc::strong_count(frame) == 1
+                                && self
+                                    .page_table
+                                    .translate(vpn)
+                                    .map_or(false, |pte| pte.is_valid())
+                        })
+                        .map(|(&vpn, _)| vpn)
+                })
+                .min()
+        };
+        find(vpn).or_else(|| find(VirtPageNum(0)))
+    }
+
+    // activate 设置根页表地址并启用 Sv39/Sv48 分页
     pub fn activate(&self) {
         let satp = self.page_table.token();
         unsafe {
@@ -375,11 +1672,354 @@ impl MemorySet {
         }
     }
 
+    // flush_tlb 刷新 TLB 中属于当前地址空间并且已经过期的表项。
+    // 用户地址空间在返回用户态之前刷新，内核地址空间在修改之后立即刷新。
+    pub fn flush_tlb(&mut self) {
+        self.page_table.flush_tlb();
+    }
+
     pub fn translate(&self, vpn: VirtPageNum) -> Option<PageTableEntry> {
         self.page_table.translate(vpn)
     }
 
+    // mark_dirty 将 vpn 标记为已修改，内核通过物理地址写入用户页时不会设置 D 位
+    pub fn mark_dirty(&mut self, vpn: VirtPageNum) {
+        self.page_table.set_dirty(vpn);
+    }
+
+    // release_areas 释放全部逻辑段，共享文件映射中被修改过的页会先写回文件
     pub fn release_areas(&mut self) {
+        for area in self.areas.iter() {
+            area.sync(&mut self.page_table);
+        }
         self.areas.clear()
     }
 }
+
+// read_elf_headers 读取 elf 文件开头包含 elf header 和 program headers 的部分
+fn read_elf_headers(file: &Arc<dyn File>) -> Vec<u8> {
+    let mut data = vec![0u8; PAGE_SIZE.min(file.size())];
+    file.read_at(0, &mut data);
+    // program headers 一般紧跟在 elf header 之后，超出第一页时再读取一次
+    if data.len() < ELF64_EHDR_SIZE {
+        return data;
+    }
+    let ph_end = match xmas_elf::ElfFile::new(&data) {
+        Ok(elf) => {
+            elf.header.pt2.ph_offset() as usize
+                + elf.header.pt2.ph_count() as usize * elf.header.pt2.ph_entry_size() as usize
+        }
+        Err(_) => 0,
+    };
+    if ph_end > data.len() && ph_end <= file.size() {
+        data = vec![0u8; ph_end];
+        file.read_at(0, &mut data);
+    }
+    data
+}
+
+// ElfLoadError 是从 elf 文件创建地址空间失败的原因
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ElfLoadError {
+    // elf 文件不合法或者不能在当前内核上运行
+    Invalid(&'static str),
+    // 没有足够的空闲页框
+    OutOfMemory,
+    // argv 和 envp 放不进新的用户栈
+    ArgTooLong,
+}
+
+impl From<&'static str> for ElfLoadError {
+    fn from(reason: &'static str) -> Self {
+        Self::Invalid(reason)
+    }
+}
+
+impl fmt::Display for ElfLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Invalid(reason) => f.write_str(reason),
+            Self::OutOfMemory => f.write_str("out of memory"),
+            Self::ArgTooLong => f.write_str("argument list too long"),
+        }
+    }
+}
+
+// ElfInfo 是 exec 时通过 auxv 传递给用户程序的 elf 文件信息
+pub struct ElfInfo {
+    pub entry_point: usize,
+    // program headers 在用户地址空间中的地址，没有被加载时为 0
+    pub phdr: usize,
+    pub phent: usize,
+    pub phnum: usize,
+    // PIE 程序的加载偏移，非 PIE 程序为 0
+    pub load_bias: usize,
+    // 动态链接器 (PT_INTERP) 的加载偏移和入口，没有动态链接器时为 0
+    pub interp_base: usize,
+    pub interp_entry: usize,
+    // 初始线程的 TLS 块地址，也就是 tp 寄存器的初始值，没有 PT_TLS 时为 0
+    pub tls_pointer: usize,
+    // PIE 程序 PT_DYNAMIC 段在文件中的 (偏移, 长度)
+    dynamic: Option<(usize, usize)>,
+    // PT_INTERP 段 (动态链接器的路径) 在文件中的 (偏移, 长度)
+    interp: Option<(usize, usize)>,
+    tls: Option<ElfTls>,
+}
+
+// ElfTls 是 PT_TLS 段描述的 TLS 初始化镜像：文件中 file_size 字节的 .tdata，
+// 之后 mem_size - file_size 字节的 .tbss 被清零
+#[derive(Clone, Copy)]
+struct ElfTls {
+    offset: usize,
+    file_size: usize,
+    mem_size: usize,
+    align: usize,
+}
+
+impl ElfInfo {
+    // start_point 返回用户程序开始执行的地址：有动态链接器时先执行动态链接器，
+    // 它完成重定位之后再跳转到 AT_ENTRY
+    pub fn start_point(&self) -> usize {
+        if self.interp_entry != 0 {
+            self.interp_entry
+        } else {
+            self.entry_point
+        }
+    }
+}
+
+// ElfSegment 是一个需要加载的 PT_LOAD 段
+struct ElfSegment {
+    vaddr: usize,
+    mem_size: usize,
+    offset: usize,
+    file_size: usize,
+    map_perm: MapPermission,
+}
+
+impl ElfSegment {
+    fn end(&self) -> usize {
+        self.vaddr + self.mem_size
+    }
+
+    fn start_vpn(&self) -> VirtPageNum {
+        VirtAddr::from(self.vaddr).floor()
+    }
+
+    fn end_vpn(&self) -> VirtPageNum {
+        VirtAddr::from(self.end()).ceil()
+    }
+}
+
+// RISC-V 的 e_machine
+const EM_RISCV: u16 = 243;
+// PT_TLS 初始化镜像的最大长度
+const MAX_TLS_SIZE: usize = 0x10_0000;
+
+// PT_INTERP 中动态链接器路径的最大长度
+const MAX_INTERP_PATH: usize = 256;
+
+// ELF64 文件头、program header、dynamic 表项以及 RELA 重定位表项的大小
+const ELF64_EHDR_SIZE: usize = 64;
+const ELF64_PHDR_SIZE: usize = 56;
+const ELF64_DYN_SIZE: usize = 16;
+const ELF64_RELA_SIZE: usize = 24;
+
+// dynamic 表项的类型
+const DT_NULL: u64 = 0;
+const DT_RELA: u64 = 7;
+const DT_RELASZ: u64 = 8;
+const DT_RELAENT: u64 = 9;
+const DT_PLTRELSZ: u64 = 2;
+const DT_PLTREL: u64 = 20;
+const DT_JMPREL: u64 = 23;
+
+// RISC-V 的重定位类型
+const R_RISCV_NONE: u32 = 0;
+const R_RISCV_RELATIVE: u32 = 3;
+const R_RISCV_JUMP_SLOT: u32 = 5;
+
+// parse_elf 检查 elf 文件是否是可以在当前内核上运行的 RISC-V 64 位可执行文件，
+// 返回按照虚拟地址排列的 PT_LOAD 段以及 elf 文件的信息。elf_data 是 read_elf_headers
+// 读取的文件开头部分，file_size 是整个文件的长度，pie_load_bias 是 ET_DYN 文件的加载偏移。
+fn parse_elf(
+    elf_data: &[u8],
+    file_size: usize,
+    pie_load_bias: usize,
+) -> Result<(Vec<ElfSegment>, ElfInfo), &'static str> {
+    use xmas_elf::header::{Class, Data, Machine, Type};
+
+    if elf_data.len() < ELF64_EHDR_SIZE {
+        return Err("file is too short");
+    }
+    let elf = xmas_elf::ElfFile::new(elf_data)?;
+    let header = elf.header;
+    if header.pt1.class() != Class::SixtyFour {
+        return Err("not a 64-bit elf");
+    }
+    if header.pt1.data() != Data::LittleEndian {
+        return Err("not a little-endian elf");
+    }
+    if header.pt2.machine().as_machine() != Machine::Other(EM_RISCV) {
+        return Err("not a RISC-V elf");
+    }
+    // ET_DYN 是位置无关的可执行文件 (PIE)，加载地址由 ASLR 决定
+    let load_bias = match header.pt2.type_().as_type() {
+        Type::Executable => 0,
+        Type::SharedObject => pie_load_bias,
+        _ => return Err("not an executable elf"),
+    };
+    let ph_count = header.pt2.ph_count() as usize;
+    let ph_end = (header.pt2.ph_offset() as usize).saturating_add(ph_count * ELF64_PHDR_SIZE);
+    if ph_count == 0
+        || header.pt2.ph_entry_size() as usize != ELF64_PHDR_SIZE
+        || ph_end > elf_data.len()
+    {
+        return Err("invalid program header table");
+    }
+
+    let image_end = config::user_mmap_base();
+    let mut segments = Vec::new();
+    let mut phdr = 0;
+    let mut dynamic = None;
+    let mut interp = None;
+    let mut tls = None;
+    for i in 0..ph_count {
+        let ph = elf.program_header(i as u16)?;
+        let ph_type = ph.get_type()?;
+        if ph_type == xmas_elf::program::Type::Phdr {
+            phdr = (ph.virtual_addr() as usize).wrapping_add(load_bias);
+        }
+        if ph_type == xmas_elf::program::Type::Dynamic && load_bias != 0 {
+            let offset = ph.offset() as usize;
+            let size = ph.file_size() as usize;
+            if offset.checked_add(size).map_or(true, |end| end > file_size) {
+                return Err("dynamic segment is out of file");
+            }
+            dynamic = Some((offset, size));
+        }
+        if ph_type == xmas_elf::program::Type::Interp {
+            let offset = ph.offset() as usize;
+            let size = ph.file_size() as usize;
+            if size == 0
+                || size > MAX_INTERP_PATH
+                || offset.checked_add(size).map_or(true, |end| end > file_size)
+            {
+                return Err("invalid interpreter path");
+            }
+            interp = Some((offset, size));
+        }
+        if ph_type == xmas_elf::program::Type::Tls {
+            let tls_info = ElfTls {
+                offset: ph.offset() as usize,
+                file_size: ph.file_size() as usize,
+                mem_size: ph.mem_size() as usize,
+                align: (ph.align() as usize).max(1),
+            };
+            if tls_info.file_size > tls_info.mem_size
+                || tls_info.mem_size > MAX_TLS_SIZE
+                || !tls_info.align.is_power_of_two()
+                || tls_info.align > PAGE_SIZE
+                || tls_info
+                    .offset
+                    .checked_add(tls_info.file_size)
+                    .map_or(true, |end| end > file_size)
+            {
+                return Err("invalid TLS segment");
+            }
+            tls = Some(tls_info);
+        }
+        if ph_type != xmas_elf::program::Type::Load || ph.mem_size() == 0 {
+            continue;
+        }
+        let vaddr = (ph.virtual_addr() as usize)
+            .checked_add(load_bias)
+            .ok_or("segment is out of user space")?;
+        let mem_size = ph.mem_size() as usize;
+        let offset = ph.offset() as usize;
+        let segment_file_size = ph.file_size() as usize;
+        if segment_file_size > mem_size {
+            return Err("segment file size is larger than memory size");
+        }
+        if offset
+            .checked_add(segment_file_size)
+            .map_or(true, |end| end > file_size)
+        {
+            return Err("segment is out of file");
+        }
+        if vaddr
+            .checked_add(mem_size)
+            .map_or(true, |end| end > image_end)
+        {
+            return Err("segment is out of user space");
+        }
+        let mut map_perm = MapPermission::U;
+        let ph_flags = ph.flags();
+        if ph_flags.is_read() {
+            map_perm |= MapPermission::R;
+        }
+        if ph_flags.is_write() {
+            map_perm |= MapPermission::W;
+        }
+        if ph_flags.is_execute() {
+            map_perm |= MapPermission::X;
+        }
+        segments.push(ElfSegment {
+            vaddr,
+            mem_size,
+            offset,
+            file_size: segment_file_size,
+            map_perm,
+        });
+    }
+    if segments.is_empty() {
+        return Err("no loadable segment");
+    }
+    segments.sort_by_key(|segment| segment.vaddr);
+    if segments
+        .windows(2)
+        .any(|pair| pair[0].end() > pair[1].vaddr)
+    {
+        return Err("overlapping segments");
+    }
+
+    let entry_point = (header.pt2.entry_point() as usize).wrapping_add(load_bias);
+    if !segments.iter().any(|segment| {
+        segment.map_perm.contains(MapPermission::X)
+            && segment.vaddr <= entry_point
+            && entry_point < segment.end()
+    }) {
+        return Err("entry point is not in an executable segment");
+    }
+    // 没有 PT_PHDR 时根据包含 program headers 的 PT_LOAD 段计算它们的地址
+    let ph_offset = header.pt2.ph_offset() as usize;
+    if phdr == 0 {
+        if let Some(segment) = segments.iter().find(|segment| {
+            segment.offset <= ph_offset && ph_end <= segment.offset + segment.file_size
+        }) {
+            phdr = segment.vaddr + ph_offset - segment.offset;
+        }
+    }
+    Ok((
+        segments,
+        ElfInfo {
+            entry_point,
+            phdr,
+            phent: ELF64_PHDR_SIZE,
+            phnum: ph_count,
+            load_bias,
+            interp_base: 0,
+            interp_entry: 0,
+            tls_pointer: 0,
+            dynamic,
+            interp,
+            tls,
+        },
+    ))
+}
+
+impl Drop for MemorySet {
+    fn drop(&mut self) {
+        self.release_areas();
+    }
+}
@@ -1,13 +1,34 @@
+mod aslr;
+mod asid;
 mod heap_allocator;
 pub mod address;
 pub mod page_table;
 mod frame_allocator;
 pub mod memory_set;
+mod page_cache;
+pub mod shm;
+mod slab_allocator;
+mod swap;
+pub mod user_ptr;
 
+pub use frame_allocator::{frame_free_count, frame_total_count};
+pub use heap_allocator::{heap_stat, HeapStat};
+pub use slab_allocator::{slab_stat, SlabCache, SlabStat, TASK_CACHE};
 pub use memory_set::KERNEL_SPACE;
+pub use page_cache::truncate_pages;
+pub use aslr::enabled as aslr_enabled;
 
-pub fn init() {
+use crate::fdt;
+
+// init 初始化内存管理，dtb_pa 是 bootloader 传递过来的设备树的物理地址
+pub fn init(dtb_pa: usize) {
     heap_allocator::init_heap();
+    page_table::init_paging_mode();
+    fdt::init(dtb_pa);
     frame_allocator::init_frame_allocator();
+    #[cfg(feature = "frame-allocator-selftest")]
+    frame_allocator::frame_allocator_test();
     KERNEL_SPACE.exclusive_access().activate();
+    heap_allocator::enable_growth(KERNEL_SPACE.exclusive_access().root_ppn());
+    asid::init();
 }
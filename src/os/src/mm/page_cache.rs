@@ -0,0 +1,68 @@
+// page cache 缓存文件中的页，同一个文件页被所有映射它的逻辑段共享。
+// 私有映射通过写时复制得到自己的页框，共享映射直接修改缓存页并在 munmap/msync 时写回。
+use alloc::{collections::BTreeMap, sync::Arc};
+use lazy_static::*;
+
+use crate::{config::PAGE_SIZE, fs::File, sync::UPSafeCell};
+
+use super::frame_allocator::{frame_alloc, FrameTracker};
+
+pub struct PageCache {
+    // key 为 (ino, 页号)
+    pages: BTreeMap<(usize, usize), Arc<FrameTracker>>,
+}
+
+impl PageCache {
+    pub fn new() -> Self {
+        Self {
+            pages: BTreeMap::new(),
+        }
+    }
+}
+
+lazy_static! {
+    pub static ref PAGE_CACHE: UPSafeCell<PageCache> = unsafe { UPSafeCell::new(PageCache::new()) };
+}
+
+// get_page 返回 file 中第 index 页对应的缓存页，没有缓存时从文件中读取，
+// 超出文件末尾的部分为 0。页框不足时返回 None。
+pub fn get_page(file: &Arc<dyn File>, index: usize) -> Option<Arc<FrameTracker>> {
+    let key = (file.ino(), index);
+    if let Some(frame) = PAGE_CACHE.exclusive_access().pages.get(&key) {
+        return Some(frame.clone());
+    }
+    let frame = Arc::new(frame_alloc()?);
+    file.read_at(index * PAGE_SIZE, frame.ppn.get_bytes_array());
+    PAGE_CACHE
+        .exclusive_access()
+        .pages
+        .insert(key, frame.clone());
+    Some(frame)
+}
+
+// truncate_pages 在文件长度改变之后丢弃 ino 从第 index 页开始的缓存页，之后访问这些页时
+// 重新从文件中读取。已经被映射的页框仍然由逻辑段持有。
+pub fn truncate_pages(ino: usize, index: usize) {
+    PAGE_CACHE
+        .exclusive_access()
+        .pages
+        .retain(|&(page_ino, page_index), _| page_ino != ino || page_index < index);
+}
+
+// shrink 释放一个没有被任何逻辑段映射的缓存页，返回 false 表示没有可以释放的页。
+// 共享映射在解除时已经写回了修改，所以这些页都是干净的。
+pub fn shrink() -> bool {
+    let mut cache = PAGE_CACHE.exclusive_access();
+    let key = cache
+        .pages
+        .iter()
+        .find(|(_, frame)| Arc::strong_count(frame) == 1)
+        .map(|(&key, _)| key);
+    match key {
+        Some(key) => {
+            cache.pages.remove(&key);
+            true
+        }
+        None => false,
+    }
+}
@@ -1,14 +1,33 @@
-use alloc::{string::String, vec::Vec};
+use alloc::vec::Vec;
 use bitflags::*;
+use core::arch::asm;
 
 use super::{
-    address::{PhysAddr, PhysPageNum, StepByOne, VirtAddr, VirtPageNum},
+    address::{paging_levels, PhysPageNum, VirtPageNum},
+    asid::{asid_alloc, AsidTracker, ASID_OFFSET},
     frame_allocator::{frame_alloc, FrameTracker},
+    slab_allocator::{SlabCache, PAGE_TABLE_CACHE},
 };
 
 const PPN_OFFSET: usize = 10;
 const REVERSE_OFFSET: usize = 54;
 
+// satp 的 MODE 字段位于 [60, 64) 位
+// Ref: https://rcore-os.github.io/rCore-Tutorial-Book-v3/chapter4/3sv39-implementation-1.html#csr
+const SATP_MODE_OFFSET: usize = 60;
+const SATP_MODE_SV39: usize = 8;
+const SATP_MODE_SV48: usize = 9;
+
+// 每个页表中页表项的数量
+const PTES_PER_TABLE: usize = 512;
+// 最大的大页级别：0 为 4 KiB 的页，1 为 2 MiB 的大页，2 为 1 GiB 的大页
+pub const MAX_HUGE_PAGE_LEVEL: usize = 2;
+
+// level_pages 返回 level 级别的一个页包含多少个 4 KiB 的页
+pub fn level_pages(level: usize) -> usize {
+    PTES_PER_TABLE.pow(level as u32)
+}
+
 // PTE = Page Table Entry
 
 bitflags!(
@@ -65,44 +84,69 @@ impl PageTableEntry {
     pub fn executable(&self) -> bool {
         (self.flags() & PTEFlags::X) != PTEFlags::empty()
     }
+
+    pub fn is_user(&self) -> bool {
+        (self.flags() & PTEFlags::U) != PTEFlags::empty()
+    }
+
+    pub fn accessed(&self) -> bool {
+        (self.flags() & PTEFlags::A) != PTEFlags::empty()
+    }
+
+    pub fn dirty(&self) -> bool {
+        (self.flags() & PTEFlags::D) != PTEFlags::empty()
+    }
+
+    // is_leaf 判断页表项是否直接指向一个页：R/W/X 全为 0 的有效页表项指向下一级页表
+    pub fn is_leaf(&self) -> bool {
+        self.is_valid() && (self.readable() || self.writable() || self.executable())
+    }
 }
 
 pub struct PageTable {
     root_ppn: PhysPageNum,
-    frames: Vec<FrameTracker>,
+    // 页表的页框数组从专用的 slab 缓存 PAGE_TABLE_CACHE 中分配
+    frames: Vec<FrameTracker, &'static SlabCache<PageTable>>,
+    // 内核地址空间在 asid::init 之前创建，所以总是使用 ASID 0
+    asid: AsidTracker,
+    // need_flush 表示页表被修改之后还没有刷新 TLB 中属于当前 ASID 的表项，
+    // 新创建的页表可能使用一个回收的 ASID，所以初始值为 true
+    need_flush: bool,
 }
 
 impl PageTable {
-    pub fn new() -> Self {
-        let frame = frame_alloc().unwrap();
-        PageTable {
-            root_ppn: frame.ppn,
-            frames: vec![frame],
-        }
-    }
-
-    pub fn from_token(satp: usize) -> Self {
-        Self {
-            root_ppn: satp.into(),
-            frames: Vec::new(),
-        }
+    // new 创建一个空的页表，没有空闲页框时返回 None
+    pub fn new() -> Option<Self> {
+        let frame = frame_alloc()?;
+        let root_ppn = frame.ppn;
+        let mut frames = Vec::new_in(&*PAGE_TABLE_CACHE);
+        frames.push(frame);
+        Some(PageTable {
+            root_ppn,
+            frames,
+            asid: asid_alloc(),
+            need_flush: true,
+        })
     }
 
-    // 查找并创建页表项 (page table entry)
-    // 如果在创建途中发现二级/三级页表没有被创建，则会自动通过 frame allocator 创建。
-    fn find_pte_create(&mut self, vpn: VirtPageNum) -> Option<&mut PageTableEntry> {
+    // 查找并创建 level 级别的页表项 (page table entry)
+    // 如果在创建途中发现下一级页表没有被创建，则会自动通过 frame allocator 创建，
+    // 没有空闲页框时返回 None。
+    fn find_pte_create(&mut self, vpn: VirtPageNum, level: usize) -> Option<&mut PageTableEntry> {
+        let levels = paging_levels();
         let idxs = vpn.indexes();
         let mut ppn = self.root_ppn;
         let mut result: Option<&mut PageTableEntry> = None;
 
-        for (i, &idx) in idxs.iter().enumerate() {
+        for (i, &idx) in idxs.iter().take(levels - level).enumerate() {
             let pte = &mut ppn.get_pte_array()[idx];
-            if i == 2 {
+            if i == levels - level - 1 {
                 result = Some(pte);
                 break;
             }
+            assert!(!pte.is_leaf(), "vpn {:?} is mapped by a huge page", vpn);
             if !pte.is_valid() {
-                let frame = frame_alloc().unwrap();
+                let frame = frame_alloc()?;
                 *pte = PageTableEntry::new(frame.ppn, PTEFlags::V);
                 self.frames.push(frame);
             }
@@ -112,15 +156,18 @@ impl PageTable {
         result
     }
 
-    fn find_pte(&self, vpn: VirtPageNum) -> Option<&mut PageTableEntry> {
+    // find_pte 查找 vpn 对应的叶子页表项以及它的级别，遇到大页时提前停止
+    fn find_pte(&self, vpn: VirtPageNum) -> Option<(&mut PageTableEntry, usize)> {
+        let levels = paging_levels();
         let idxs = vpn.indexes();
         let mut ppn = self.root_ppn;
-        let mut result: Option<&mut PageTableEntry> = None;
+        let mut result: Option<(&mut PageTableEntry, usize)> = None;
 
-        for (i, &idx) in idxs.iter().enumerate() {
+        for (i, &idx) in idxs.iter().take(levels).enumerate() {
             let pte = &mut ppn.get_pte_array()[idx];
-            if i == 2 {
-                result = Some(pte);
+            let level = levels - i - 1;
+            if level == 0 || pte.is_leaf() {
+                result = Some((pte, level));
                 break;
             }
             if !pte.is_valid() {
@@ -132,91 +179,179 @@ impl PageTable {
         result
     }
 
-    #[allow(unused)]
-    pub fn map(&mut self, vpn: VirtPageNum, ppn: PhysPageNum, flags: PTEFlags) {
-        let pte = self.find_pte_create(vpn).unwrap();
+    // map 建立 vpn 到 ppn 的映射，没有空闲页框创建中间页表时返回 None
+    pub fn map(&mut self, vpn: VirtPageNum, ppn: PhysPageNum, flags: PTEFlags) -> Option<()> {
+        self.map_huge(vpn, ppn, flags, 0)
+    }
+
+    // map_huge 在 level 级别的页表中安装一个叶子页表项，level 为 1/2 时映射一个
+    // 2 MiB/1 GiB 的大页，此时 vpn 和 ppn 都需要按照大页的大小对齐
+    pub fn map_huge(
+        &mut self,
+        vpn: VirtPageNum,
+        ppn: PhysPageNum,
+        flags: PTEFlags,
+        level: usize,
+    ) -> Option<()> {
+        assert!(level <= MAX_HUGE_PAGE_LEVEL && level < paging_levels());
+        assert!(
+            vpn.0 % level_pages(level) == 0 && ppn.0 % level_pages(level) == 0,
+            "vpn {:?} or ppn {:?} is not aligned to a level {} page",
+            vpn,
+            ppn,
+            level
+        );
+        let pte = self.find_pte_create(vpn, level)?;
         assert!(!pte.is_valid(), "vpn {:?} is mapped before mapping", vpn);
         *pte = PageTableEntry::new(ppn, flags | PTEFlags::V);
+        self.need_flush = true;
+        Some(())
+    }
+
+    // remap 修改一个已经存在的映射，用于写时复制等需要替换页框或者修改权限的场景
+    pub fn remap(&mut self, vpn: VirtPageNum, ppn: PhysPageNum, flags: PTEFlags) {
+        let (pte, _) = self.find_pte(vpn).unwrap();
+        assert!(pte.is_valid(), "vpn {:?} is invalid before remapping", vpn);
+        *pte = PageTableEntry::new(ppn, flags | PTEFlags::V);
+        self.need_flush = true;
     }
 
     #[allow(unused)]
     pub fn unmap(&mut self, vpn: VirtPageNum) {
-        let pte = self.find_pte(vpn).unwrap();
+        let (pte, _) = self.find_pte(vpn).unwrap();
         assert!(pte.is_valid(), "vpn {:?} is invalid before unmapping", vpn);
         *pte = PageTableEntry::empty();
+        self.need_flush = true;
     }
 
-    pub fn translate(&self, vpn: VirtPageNum) -> Option<PageTableEntry> {
-        self.find_pte(vpn).map(|pte| *pte)
+    // clear_accessed 清除 vpn 对应页表项的 A 位
+    pub fn clear_accessed(&mut self, vpn: VirtPageNum) {
+        let (pte, _) = self.find_pte(vpn).unwrap();
+        pte.bits &= !(PTEFlags::A.bits as usize);
+        self.need_flush = true;
+    }
+
+    // clear_dirty 清除 vpn 对应页表项的 D 位
+    pub fn clear_dirty(&mut self, vpn: VirtPageNum) {
+        let (pte, _) = self.find_pte(vpn).unwrap();
+        pte.bits &= !(PTEFlags::D.bits as usize);
+        self.need_flush = true;
+    }
+
+    // set_dirty 设置 vpn 对应页表项的 D 位，用于内核通过物理地址写入用户页的情况
+    pub fn set_dirty(&mut self, vpn: VirtPageNum) {
+        let (pte, _) = self.find_pte(vpn).unwrap();
+        pte.bits |= PTEFlags::D.bits as usize;
+    }
+
+    // frame_count 返回页表自身占用的页框数量
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    // prepare 预先创建映射 [start, end) 需要的各级页表 (不包括叶子页表项)，之后可以通过
+    // prepared_pte 修改这个范围内的映射而不需要申请页框，没有空闲页框时返回 None
+    pub fn prepare(&mut self, start: VirtPageNum, end: VirtPageNum) -> Option<()> {
+        let mut vpn = start.0;
+        while vpn < end.0 {
+            self.find_pte_create(VirtPageNum(vpn), 0)?;
+            vpn = (vpn / level_pages(1) + 1) * level_pages(1);
+        }
+        Some(())
     }
 
-    fn translate_va(&self, va: VirtAddr) -> Option<PhysAddr> {
-        self.find_pte(va.clone().floor()).map(|pte| {
-            let phys_addr: PhysAddr = pte.ppn().into();
-            let phys_addr_usize: usize = phys_addr.into();
-            let page_offset = va.page_offset();
-            PhysAddr::from(phys_addr_usize + page_offset)
+    pub fn root_ppn(&self) -> PhysPageNum {
+        self.root_ppn
+    }
+
+    // flush_tlb 在页表被修改之后刷新 TLB 中属于当前 ASID 的表项
+    pub fn flush_tlb(&mut self) {
+        if self.need_flush {
+            unsafe {
+                asm!("sfence.vma x0, {}", in(reg) self.asid.0);
+            }
+            self.need_flush = false;
+        }
+    }
+
+    // translate 返回 vpn 对应的页表项，vpn 位于大页中时返回的页表项指向 vpn 对应的 4 KiB 页
+    pub fn translate(&self, vpn: VirtPageNum) -> Option<PageTableEntry> {
+        self.find_pte(vpn).map(|(pte, level)| {
+            let offset = vpn.0 & (level_pages(level) - 1);
+            PageTableEntry::new(PhysPageNum(pte.ppn().0 + offset), pte.flags())
         })
     }
 
-    // token 返回启用 SV39 分页机制且指向根页表地址的 satp 的 CSR 寄存器
+    // token 返回启用 Sv39/Sv48 分页机制且指向根页表地址的 satp 的 CSR 寄存器
     pub fn token(&self) -> usize {
-        // 8usize << 60 表示启用 SV39 分页机制
-        // Ref: https://rcore-os.github.io/rCore-Tutorial-Book-v3/chapter4/3sv39-implementation-1.html#csr
-        8usize << 60 | self.root_ppn.0
+        // MODE 为 8 表示启用 Sv39 分页机制，为 9 表示启用 Sv48，[44, 60) 位是 ASID
+        satp_mode() << SATP_MODE_OFFSET | self.asid.0 << ASID_OFFSET | self.root_ppn.0
     }
 }
 
-// 将 token 地址空间的数据保存到 Vec 缓冲区中，ptr 是 token 地址空间的虚拟地址。
-// 一个页框本身是一个数组 `&'static mut [u8]`，如果 len 横跨多
-// 个页框，那么就整体的数据结果就是 `Vec<&'static mut [u8]>`。
-pub fn translated_byte_buffer(token: usize, ptr: *const u8, len: usize) -> Vec<&'static mut [u8]> {
-    let page_table = PageTable::from_token(token);
-    let mut start = ptr as usize;
-    let end = start + len;
-    let mut v = Vec::new();
-
-    while start < end {
-        let start_va = VirtAddr::from(start);
-        let mut vpn = start_va.floor();
-        let ppn = page_table.find_pte(vpn).unwrap().ppn();
-        vpn.step();
-        let mut end_va: VirtAddr = vpn.into();
-        end_va = end_va.min(VirtAddr::from(end));
-        if end_va.page_offset() == 0 {
-            v.push(&mut ppn.get_bytes_array()[start_va.page_offset()..]);
-        } else {
-            v.push(&mut ppn.get_bytes_array()[start_va.page_offset()..end_va.page_offset()]);
-        }
-        start = end_va.into();
+// prepared_pte 返回根页表为 root_ppn 的页表中 vpn 的叶子页表项，vpn 所在的各级页表
+// 必须已经通过 PageTable::prepare 创建。它既不申请页框也不申请堆内存，所以可以在
+// 扩展内核堆的时候使用。
+pub fn prepared_pte(root_ppn: PhysPageNum, vpn: VirtPageNum) -> &'static mut PageTableEntry {
+    let levels = paging_levels();
+    let idxs = vpn.indexes();
+    let mut ppn = root_ppn;
+    for &idx in idxs.iter().take(levels - 1) {
+        let pte = &ppn.get_pte_array()[idx];
+        assert!(
+            pte.is_valid() && !pte.is_leaf(),
+            "vpn {:?} is not prepared",
+            vpn
+        );
+        ppn = pte.ppn();
     }
-    v
+    &mut ppn.get_pte_array()[idxs[levels - 1]]
 }
 
-pub fn translated_str(token: usize, ptr: *const u8) -> String {
-    let page_table = PageTable::from_token(token);
-    let mut string = String::new();
-    let mut va = ptr as usize;
-
-    loop {
-        let ch: u8 = *(page_table
-            .translate_va(VirtAddr::from(va))
-            .unwrap()
-            .get_mut());
-        if ch == 0 {
-            break;
-        } else {
-            string.push(ch as char);
-            va += 1;
-        }
+fn satp_mode() -> usize {
+    match paging_levels() {
+        3 => SATP_MODE_SV39,
+        _ => SATP_MODE_SV48,
+    }
+}
+
+// init_paging_mode 确定使用的分页模式，需要在创建任何页表之前调用。
+// 开启 sv48 feature 时先探测硬件是否支持 Sv48：satp 的 MODE 字段是 WARL 的，
+// 写入不支持的模式时整个写操作无效。探测时使用一个临时的根页表，它用一个
+// 512 GiB 的大页恒等映射低地址空间，这样即使写入成功内核也能继续执行。
+#[cfg(feature = "sv48")]
+pub fn init_paging_mode() {
+    use super::address::{set_paging_levels, MAX_PAGING_LEVELS};
+    use riscv::register::satp;
+
+    #[repr(C, align(4096))]
+    struct ProbePageTable([PageTableEntry; 512]);
+    static mut PROBE_PAGE_TABLE: ProbePageTable = ProbePageTable([PageTableEntry { bits: 0 }; 512]);
+
+    let supported = unsafe {
+        let root = core::ptr::addr_of_mut!(PROBE_PAGE_TABLE);
+        (*root).0[0] = PageTableEntry::new(
+            PhysPageNum(0),
+            PTEFlags::V | PTEFlags::R | PTEFlags::W | PTEFlags::X | PTEFlags::A | PTEFlags::D,
+        );
+        let root_ppn = root as usize >> crate::config::PAGE_SIZE_BITS;
+        satp::write(SATP_MODE_SV48 << SATP_MODE_OFFSET | root_ppn);
+        asm!("sfence.vma");
+        let mode = satp::read().bits() >> SATP_MODE_OFFSET;
+        // 关闭分页，等待 KERNEL_SPACE 激活
+        satp::write(0);
+        asm!("sfence.vma");
+        mode == SATP_MODE_SV48
+    };
+    if supported {
+        set_paging_levels(MAX_PAGING_LEVELS);
+        println!("[kernel] paging mode: Sv48");
+    } else {
+        println!("[kernel] Sv48 is not supported, fall back to Sv39");
     }
-    string
 }
 
-pub fn translated_ref_mut<T>(token: usize, ptr: *const T) -> &'static mut T {
-    let page_table = PageTable::from_token(token);
-    page_table
-        .translate_va(VirtAddr::from(ptr as usize))
-        .unwrap()
-        .get_mut()
+#[cfg(not(feature = "sv48"))]
+pub fn init_paging_mode() {
+    println!("[kernel] paging mode: Sv39");
 }
@@ -0,0 +1,114 @@
+// System V 风格的共享内存。共享内存段的页框使用 Arc 做引用计数，可以同时被多个
+// 逻辑段映射，最后一个映射被解除时共享内存段以及它的页框被释放。
+use alloc::{
+    collections::BTreeMap,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use lazy_static::*;
+
+use crate::sync::UPSafeCell;
+
+use super::frame_allocator::{frame_alloc, FrameTracker};
+
+// IPC_PRIVATE 表示每次都创建一个新的共享内存段
+pub const IPC_PRIVATE: usize = 0;
+
+pub struct ShmSegment {
+    key: usize,
+    pub frames: Vec<Arc<FrameTracker>>,
+}
+
+struct ShmEntry {
+    segment: Weak<ShmSegment>,
+    // 还没有被映射过的共享内存段由 pending 保持，第一次 attach 时移交给逻辑段，
+    // 或者在 shmctl(IPC_RMID) 时随着 ShmEntry 一起被释放
+    pending: Option<Arc<ShmSegment>>,
+}
+
+impl ShmEntry {
+    fn upgrade(&self) -> Option<Arc<ShmSegment>> {
+        self.segment.upgrade()
+    }
+}
+
+pub struct ShmManager {
+    next_id: usize,
+    entries: BTreeMap<usize, ShmEntry>,
+}
+
+impl ShmManager {
+    pub fn new() -> Self {
+        Self {
+            next_id: 1,
+            entries: BTreeMap::new(),
+        }
+    }
+
+    // 移除所有映射都已经被解除的共享内存段
+    fn purge(&mut self) {
+        self.entries.retain(|_, entry| entry.upgrade().is_some());
+    }
+}
+
+lazy_static! {
+    pub static ref SHM_MANAGER: UPSafeCell<ShmManager> =
+        unsafe { UPSafeCell::new(ShmManager::new()) };
+}
+
+// shm_find 根据 key 查找共享内存段，返回 shmid 和共享内存段的页数
+pub fn shm_find(key: usize) -> Option<(usize, usize)> {
+    let mut manager = SHM_MANAGER.exclusive_access();
+    manager.purge();
+    manager.entries.iter().find_map(|(&id, entry)| {
+        let segment = entry.upgrade()?;
+        if key != IPC_PRIVATE && segment.key == key {
+            Some((id, segment.frames.len()))
+        } else {
+            None
+        }
+    })
+}
+
+// shm_create 创建一个包含 page_count 个页框的共享内存段并返回 shmid，
+// 页框不足时返回 None。
+pub fn shm_create(key: usize, page_count: usize) -> Option<usize> {
+    let mut frames = Vec::with_capacity(page_count);
+    for _ in 0..page_count {
+        frames.push(Arc::new(frame_alloc()?));
+    }
+    let segment = Arc::new(ShmSegment { key, frames });
+    let mut manager = SHM_MANAGER.exclusive_access();
+    let id = manager.next_id;
+    manager.next_id += 1;
+    manager.entries.insert(
+        id,
+        ShmEntry {
+            segment: Arc::downgrade(&segment),
+            pending: Some(segment),
+        },
+    );
+    Some(id)
+}
+
+// shm_get 返回 shmid 对应的共享内存段，用于 attach
+pub fn shm_get(id: usize) -> Option<Arc<ShmSegment>> {
+    SHM_MANAGER.exclusive_access().entries.get(&id)?.upgrade()
+}
+
+// shm_remove 删除 shmid 对应的共享内存段，之后不能再通过 shmget 找到或者通过 shmat 映射它。
+// 已经存在的映射不受影响，最后一个映射被解除时页框被释放；还没有被映射过的共享内存段立即
+// 被释放。shmid 不存在时返回 false。
+pub fn shm_remove(id: usize) -> bool {
+    let mut manager = SHM_MANAGER.exclusive_access();
+    manager.purge();
+    manager.entries.remove(&id).is_some()
+}
+
+// shm_attached 在共享内存段第一次被成功映射之后释放 pending，此后共享内存段由映射它的
+// 逻辑段持有。attach 失败时不能释放 pending，否则还没有被映射过的共享内存段会被销毁。
+pub fn shm_attached(id: usize) {
+    if let Some(entry) = SHM_MANAGER.exclusive_access().entries.get_mut(&id) {
+        entry.pending = None;
+    }
+}
@@ -0,0 +1,327 @@
+// slab 分配器在页框分配器之上维护对象缓存。每个缓存 (ObjectCaches) 按照对象大小分为
+// SLAB_CACHES 类，每一类的对象只放在属于这一类的 slab 页中。KMALLOC 处理内核堆中一般的
+// 小对象申请 (见 heap_allocator)，SlabCache<T> 是类型 T 专用的缓存，通过 allocator_api
+// 交给 Arc、Vec 和 BTreeMap 使用，这样 TaskControlBlock、页表的页框数组以及 MapArea 的
+// BTreeMap 节点都在各自的 slab 页中分配，创建和销毁进程时不会让其它对象所在的页产生碎片。
+// 每个 slab 是直接从页框分配器申请的一个页框，内核中物理内存是恒等映射的，所以不需要建立
+// 新的映射。slab 页在页框分配器中有标记，释放对象时据此判断对象是否属于 slab 分配器。
+use alloc::vec::Vec;
+use core::{
+    alloc::{AllocError, Allocator, Layout},
+    marker::PhantomData,
+    mem::size_of,
+    ptr::{self, NonNull},
+};
+use lazy_static::*;
+
+use crate::{
+    config::{PAGE_SIZE, PAGE_SIZE_BITS},
+    sync::UPSafeCell,
+    task::TaskControlBlock,
+};
+
+use super::{
+    address::{PhysAddr, PhysPageNum},
+    frame_allocator::{alloc_heap_frames, free_heap_frame, is_slab_frame, set_slab_frame},
+    memory_set::MapArea,
+    page_table::PageTable,
+};
+
+// 对象大小的分类，每一类对应一个缓存，对象按照大小的最低位 1 对齐
+const SIZE_CLASSES: [usize; 12] = [16, 32, 48, 64, 96, 128, 192, 256, 384, 512, 768, 1024];
+const SLAB_CACHES: usize = SIZE_CLASSES.len();
+// SLAB_NAME_LEN 是 SlabStat 中缓存名字的最大长度
+const SLAB_NAME_LEN: usize = 16;
+
+// Slab 是放在 slab 页开头的描述符，描述符之后是这个 slab 的对象
+struct Slab {
+    prev: *mut Slab,
+    next: *mut Slab,
+    free: *mut FreeObject,
+    inuse: usize,
+    // owner 是 slab 所属的缓存，class 是缓存中的大小分类
+    owner: *const ObjectCaches,
+    class: usize,
+}
+
+// 空闲对象的开头用来保存空闲链表的下一项
+struct FreeObject {
+    next: *mut FreeObject,
+}
+
+// SizeCache 管理一个大小分类的全部 slab
+struct SizeCache {
+    size: usize,
+    align: usize,
+    // 还有空闲对象的 slab 组成的双向链表，对象全部被分配出去的 slab 不在链表中
+    partial: *mut Slab,
+    slabs: usize,
+    // 对象全部空闲的 slab 的数量
+    empty: usize,
+    inuse: usize,
+}
+
+impl SizeCache {
+    fn new(size: usize) -> Self {
+        Self {
+            size,
+            align: 1 << size.trailing_zeros(),
+            partial: ptr::null_mut(),
+            slabs: 0,
+            empty: 0,
+            inuse: 0,
+        }
+    }
+
+    // first_offset 是第一个对象在 slab 页中的偏移
+    fn first_offset(&self) -> usize {
+        (size_of::<Slab>() + self.align - 1) & !(self.align - 1)
+    }
+
+    fn objects_per_slab(&self) -> usize {
+        (PAGE_SIZE - self.first_offset()) / self.size
+    }
+
+    unsafe fn push(&mut self, slab: *mut Slab) {
+        (*slab).prev = ptr::null_mut();
+        (*slab).next = self.partial;
+        if !self.partial.is_null() {
+            (*self.partial).prev = slab;
+        }
+        self.partial = slab;
+    }
+
+    unsafe fn unlink(&mut self, slab: *mut Slab) {
+        let (prev, next) = ((*slab).prev, (*slab).next);
+        if prev.is_null() {
+            self.partial = next;
+        } else {
+            (*prev).next = next;
+        }
+        if !next.is_null() {
+            (*next).prev = prev;
+        }
+    }
+
+    // add_slab 将一个对象全部空闲的 slab 加入缓存，page 是 slab 页的起始地址
+    unsafe fn add_slab(&mut self, page: usize, owner: *const ObjectCaches, class: usize) {
+        let mut free = ptr::null_mut();
+        for i in (0..self.objects_per_slab()).rev() {
+            let object = (page + self.first_offset() + i * self.size) as *mut FreeObject;
+            (*object).next = free;
+            free = object;
+        }
+        let slab = page as *mut Slab;
+        slab.write(Slab {
+            prev: ptr::null_mut(),
+            next: ptr::null_mut(),
+            free,
+            inuse: 0,
+            owner,
+            class,
+        });
+        self.push(slab);
+        self.slabs += 1;
+        self.empty += 1;
+    }
+
+    // alloc 从第一个有空闲对象的 slab 中分配对象，调用者需要保证 partial 不为空
+    unsafe fn alloc(&mut self) -> *mut u8 {
+        let slab = self.partial;
+        let object = (*slab).free;
+        (*slab).free = (*object).next;
+        if (*slab).inuse == 0 {
+            self.empty -= 1;
+        }
+        (*slab).inuse += 1;
+        if (*slab).free.is_null() {
+            self.unlink(slab);
+        }
+        self.inuse += 1;
+        object as *mut u8
+    }
+
+    // dealloc 释放 slab 中的对象，返回 true 表示这个 slab 已经从缓存中移除，
+    // 调用者需要释放它所在的页框
+    unsafe fn dealloc(&mut self, slab: *mut Slab, object: *mut u8) -> bool {
+        if (*slab).free.is_null() {
+            self.push(slab);
+        }
+        let object = object as *mut FreeObject;
+        (*object).next = (*slab).free;
+        (*slab).free = object;
+        (*slab).inuse -= 1;
+        self.inuse -= 1;
+        if (*slab).inuse > 0 {
+            return false;
+        }
+        // 每个缓存保留一个空闲的 slab，避免反复申请和释放页框
+        if self.empty == 0 {
+            self.empty += 1;
+            return false;
+        }
+        self.unlink(slab);
+        self.slabs -= 1;
+        true
+    }
+}
+
+// ObjectCaches 是一组按照大小分类的缓存，name 用于统计信息
+struct ObjectCaches {
+    name: &'static str,
+    caches: UPSafeCell<[SizeCache; SLAB_CACHES]>,
+}
+
+// slab 链表中的裸指针都指向由 ObjectCaches 独占的 slab 页
+unsafe impl Send for ObjectCaches {}
+
+impl ObjectCaches {
+    fn new(name: &'static str) -> Self {
+        assert!(name.len() <= SLAB_NAME_LEN);
+        Self {
+            name,
+            caches: unsafe { UPSafeCell::new(SIZE_CLASSES.map(SizeCache::new)) },
+        }
+    }
+
+    // alloc 从缓存中分配对象，layout 太大或者没有空闲页框时返回 None。
+    // 申请 slab 页的过程中不会申请堆内存，所以持有 caches 时不会再次进入这里。
+    fn alloc(&self, layout: Layout) -> Option<*mut u8> {
+        let class = size_class(layout)?;
+        let mut caches = self.caches.exclusive_access();
+        let cache = &mut caches[class];
+        unsafe {
+            if cache.partial.is_null() {
+                let mut page = 0;
+                if !alloc_heap_frames(1, |ppn| page = PhysAddr::from(ppn).0) {
+                    return None;
+                }
+                set_slab_frame(PhysAddr::from(page).floor(), true);
+                cache.add_slab(page, self, class);
+            }
+            Some(cache.alloc())
+        }
+    }
+
+    // stat 将有 slab 的大小分类的使用情况加入 stats，调用者需要预留足够的空间
+    fn stat(&self, stats: &mut Vec<SlabStat>) {
+        let mut name = [0; SLAB_NAME_LEN];
+        name[..self.name.len()].copy_from_slice(self.name.as_bytes());
+        for cache in self.caches.exclusive_access().iter() {
+            if cache.slabs == 0 {
+                continue;
+            }
+            stats.push(SlabStat {
+                name,
+                size: cache.size,
+                slabs: cache.slabs,
+                objects: cache.slabs * cache.objects_per_slab(),
+                inuse: cache.inuse,
+            });
+        }
+    }
+}
+
+// SlabCache<T> 是类型 T 专用的对象缓存，T 只用于区分不同的缓存。它实现了 Allocator，
+// 对象太大或者没有空闲页框时从内核堆分配，释放时由内核堆根据 slab 页的标记分发。
+pub struct SlabCache<T> {
+    caches: ObjectCaches,
+    _type: PhantomData<fn() -> T>,
+}
+
+impl<T> SlabCache<T> {
+    fn new(name: &'static str) -> Self {
+        Self {
+            caches: ObjectCaches::new(name),
+            _type: PhantomData,
+        }
+    }
+}
+
+unsafe impl<T> Allocator for SlabCache<T> {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        match self.caches.alloc(layout).and_then(NonNull::new) {
+            Some(ptr) => Ok(NonNull::slice_from_raw_parts(ptr, layout.size())),
+            None => alloc::alloc::Global.allocate(layout),
+        }
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        alloc::alloc::Global.deallocate(ptr, layout)
+    }
+}
+
+lazy_static! {
+    static ref KMALLOC: ObjectCaches = ObjectCaches::new("kmalloc");
+    pub static ref TASK_CACHE: SlabCache<TaskControlBlock> = SlabCache::new("task");
+    pub static ref PAGE_TABLE_CACHE: SlabCache<PageTable> = SlabCache::new("page_table");
+    pub static ref MAP_AREA_CACHE: SlabCache<MapArea> = SlabCache::new("map_area");
+}
+
+// size_class 返回能够容纳 layout 的最小的大小分类
+fn size_class(layout: Layout) -> Option<usize> {
+    SIZE_CLASSES
+        .iter()
+        .position(|&size| size >= layout.size() && 1 << size.trailing_zeros() >= layout.align())
+}
+
+// alloc 从 KMALLOC 中分配内核堆的小对象，返回 None 时由 buddy 堆分配
+pub fn alloc(layout: Layout) -> Option<*mut u8> {
+    KMALLOC.alloc(layout)
+}
+
+// owns 判断 ptr 是否由 slab 分配器分配，也就是 ptr 所在的页是否被标记为 slab 页。buddy 堆的
+// 初始空间在内核镜像的 .bss 中，扩展出来的空间在 kernel_heap_base() 之上，都不会被标记。
+pub fn owns(ptr: *mut u8) -> bool {
+    is_slab_frame(ptr as usize >> PAGE_SIZE_BITS)
+}
+
+// dealloc 将对象还给它所在的 slab 所属的缓存，对象可以来自 KMALLOC 或者任意 SlabCache
+pub fn dealloc(ptr: *mut u8) {
+    let page = ptr as usize & !(PAGE_SIZE - 1);
+    let slab = page as *mut Slab;
+    let (owner, class) = unsafe { (&*(*slab).owner, (*slab).class) };
+    let release = unsafe { owner.caches.exclusive_access()[class].dealloc(slab, ptr) };
+    if !release {
+        return;
+    }
+    // 释放页框时不能持有缓存，页框分配器正在被使用时把 slab 放回缓存
+    let ppn = PhysPageNum(page >> PAGE_SIZE_BITS);
+    set_slab_frame(ppn, false);
+    if !free_heap_frame(ppn) {
+        set_slab_frame(ppn, true);
+        unsafe {
+            owner.caches.exclusive_access()[class].add_slab(page, owner, class);
+        }
+    }
+}
+
+// SlabStat 是一个缓存中一个大小分类的使用情况
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct SlabStat {
+    // name 是缓存的名字，不足 SLAB_NAME_LEN 字节时以 0 结尾
+    pub name: [u8; SLAB_NAME_LEN],
+    // size 是这个大小分类的对象大小
+    pub size: usize,
+    pub slabs: usize,
+    // objects 是全部 slab 中对象的个数，inuse 是已经分配出去的个数
+    pub objects: usize,
+    pub inuse: usize,
+}
+
+// slab_stat 返回 KMALLOC 和每个 SlabCache 中有 slab 的大小分类的使用情况
+pub fn slab_stat() -> Vec<SlabStat> {
+    let caches: [&ObjectCaches; 4] = [
+        &KMALLOC,
+        &TASK_CACHE.caches,
+        &PAGE_TABLE_CACHE.caches,
+        &MAP_AREA_CACHE.caches,
+    ];
+    // 持有缓存时不能申请堆内存，所以预先分配足够的空间
+    let mut stats = Vec::with_capacity(caches.len() * SLAB_CACHES);
+    for cache in caches {
+        cache.stat(&mut stats);
+    }
+    stats
+}
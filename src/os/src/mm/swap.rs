@@ -0,0 +1,81 @@
+// 交换区：内存不足时把用户页写入交换设备，等到再次访问触发 page fault 时再读回。
+// 交换区按页划分为 slot，每个 slot 占用 PAGE_SIZE / BLOCK_SZ 个连续的块。
+use alloc::vec::Vec;
+use lazy_static::*;
+
+use crate::{
+    config::PAGE_SIZE,
+    drivers::block::{BLOCK_SZ, SWAP_DEVICE},
+    sync::UPSafeCell,
+};
+
+use super::address::PhysPageNum;
+
+// 处理 page fault 之前至少需要保留的空闲页框数量：
+// 一个数据页以及最多两个新的页表页，另外多保留一个。
+pub const RESERVED_FRAMES: usize = 4;
+
+const BLOCKS_PER_SLOT: usize = PAGE_SIZE / BLOCK_SZ;
+
+// SwapTracker 表示一个保存了页数据的 slot，被 drop 时释放该 slot
+pub struct SwapTracker {
+    slot: usize,
+}
+
+impl SwapTracker {
+    // swap_in 将 slot 中保存的数据读入到 ppn 对应的页框中
+    pub fn swap_in(&self, ppn: PhysPageNum) {
+        let bytes = ppn.get_bytes_array();
+        for (i, block) in bytes.chunks_mut(BLOCK_SZ).enumerate() {
+            SWAP_DEVICE.read_block(self.slot * BLOCKS_PER_SLOT + i, block);
+        }
+    }
+}
+
+impl Drop for SwapTracker {
+    fn drop(&mut self) {
+        SWAP_SPACE.exclusive_access().dealloc(self.slot);
+    }
+}
+
+// SwapSpace 记录交换区中每个 slot 是否已经被使用
+struct SwapSpace {
+    used: Vec<bool>,
+}
+
+impl SwapSpace {
+    fn new() -> Self {
+        Self {
+            used: vec![false; SWAP_DEVICE.num_blocks() / BLOCKS_PER_SLOT],
+        }
+    }
+
+    fn alloc(&mut self) -> Option<usize> {
+        let slot = self.used.iter().position(|&used| !used)?;
+        self.used[slot] = true;
+        Some(slot)
+    }
+
+    fn dealloc(&mut self, slot: usize) {
+        assert!(
+            self.used[slot],
+            "swap slot {} has not been allocated!",
+            slot
+        );
+        self.used[slot] = false;
+    }
+}
+
+lazy_static! {
+    static ref SWAP_SPACE: UPSafeCell<SwapSpace> = unsafe { UPSafeCell::new(SwapSpace::new()) };
+}
+
+// swap_out 将 ppn 对应页框的数据写入一个空闲的 slot，返回 None 表示交换区已满
+pub fn swap_out(ppn: PhysPageNum) -> Option<SwapTracker> {
+    let slot = SWAP_SPACE.exclusive_access().alloc()?;
+    let bytes = ppn.get_bytes_array();
+    for (i, block) in bytes.chunks(BLOCK_SZ).enumerate() {
+        SWAP_DEVICE.write_block(slot * BLOCKS_PER_SLOT + i, block);
+    }
+    Some(SwapTracker { slot })
+}
@@ -0,0 +1,203 @@
+// 内核访问用户地址空间的接口。用户传入的指针可能是非法的，所以这里的每个接口都会
+// 检查页表项的 V、U 以及 R/W 权限，访问失败时返回 PageFaultError::InvalidAccess，
+// 由系统调用返回 -EFAULT，而不是让整个内核 panic。处理 page fault 时没有空闲页框则返回
+// PageFaultError::OutOfMemory，由调用者决定是否杀死其他进程之后重试。
+use alloc::{string::String, vec::Vec};
+use core::{marker::PhantomData, mem::MaybeUninit};
+
+use crate::config::{self, PAGE_SIZE};
+
+use super::{
+    address::{PhysPageNum, VirtAddr, VirtPageNum},
+    memory_set::{MapPermission, MemorySet, PageFaultError},
+};
+
+// 用户字符串的最大长度 (包括结尾的 '\0')
+const USER_STR_MAX: usize = 4096;
+
+fn check_user_page(
+    memory_set: &MemorySet,
+    vpn: VirtPageNum,
+    access: MapPermission,
+) -> Option<PhysPageNum> {
+    let pte = memory_set.translate(vpn)?;
+    let permitted = match access {
+        MapPermission::W => pte.writable(),
+        _ => pte.readable(),
+    };
+    if pte.is_valid() && pte.is_user() && permitted {
+        Some(pte.ppn())
+    } else {
+        None
+    }
+}
+
+// user_page 返回用户虚拟地址 va 所在页对应的物理页框。内核通过物理地址访问用户内存
+// 不会触发 page fault，所以对于 lazy 页和写时复制页需要先手动处理 page fault。
+fn user_page(
+    memory_set: &mut MemorySet,
+    va: VirtAddr,
+    access: MapPermission,
+) -> Result<PhysPageNum, PageFaultError> {
+    let ppn = match check_user_page(memory_set, va.floor(), access) {
+        Some(ppn) => ppn,
+        None => {
+            memory_set.handle_page_fault(va, access)?;
+            check_user_page(memory_set, va.floor(), access).ok_or(PageFaultError::InvalidAccess)?
+        }
+    };
+    // 内核写入用户页时硬件不会设置 D 位，需要手动设置，否则修改不会被写回文件
+    if access == MapPermission::W {
+        memory_set.mark_dirty(va.floor());
+    }
+    Ok(ppn)
+}
+
+// for_each_user_page 将 [start, start + len) 按页拆分，依次使用每一段对应的物理内存
+// 调用 f，f 的第二个参数是这一段相对于 start 的偏移。
+fn for_each_user_page(
+    memory_set: &mut MemorySet,
+    start: usize,
+    len: usize,
+    access: MapPermission,
+    mut f: impl FnMut(&'static mut [u8], usize),
+) -> Result<(), PageFaultError> {
+    let end = start
+        .checked_add(len)
+        .filter(|&end| end <= config::user_space_end())
+        .ok_or(PageFaultError::InvalidAccess)?;
+    let mut current = start;
+    while current < end {
+        let va = VirtAddr::from(current);
+        let ppn = user_page(memory_set, va, access)?;
+        let chunk_end = ((current & !(PAGE_SIZE - 1)) + PAGE_SIZE).min(end);
+        let offset = va.page_offset();
+        f(
+            &mut ppn.get_bytes_array()[offset..offset + chunk_end - current],
+            current - start,
+        );
+        current = chunk_end;
+    }
+    Ok(())
+}
+
+// copy_from_user 将用户地址空间中从 src 开始的数据拷贝到 dst 中
+pub fn copy_from_user(
+    memory_set: &mut MemorySet,
+    src: usize,
+    dst: &mut [u8],
+) -> Result<(), PageFaultError> {
+    for_each_user_page(
+        memory_set,
+        src,
+        dst.len(),
+        MapPermission::R,
+        |chunk, offset| {
+            dst[offset..offset + chunk.len()].copy_from_slice(chunk);
+        },
+    )
+}
+
+// copy_to_user 将 src 拷贝到用户地址空间中从 dst 开始的位置
+pub fn copy_to_user(
+    memory_set: &mut MemorySet,
+    dst: usize,
+    src: &[u8],
+) -> Result<(), PageFaultError> {
+    for_each_user_page(
+        memory_set,
+        dst,
+        src.len(),
+        MapPermission::W,
+        |chunk, offset| {
+            chunk.copy_from_slice(&src[offset..offset + chunk.len()]);
+        },
+    )
+}
+
+// read_user_chunks 按页依次读取用户地址空间中 [src, src + len) 的数据，不会按照用户给出的
+// len 申请内核内存。范围越界时在访问任何一页之前返回错误。
+pub fn read_user_chunks(
+    memory_set: &mut MemorySet,
+    src: usize,
+    len: usize,
+    mut f: impl FnMut(&[u8]),
+) -> Result<(), PageFaultError> {
+    for_each_user_page(memory_set, src, len, MapPermission::R, |chunk, _| f(chunk))
+}
+
+// write_user_chunks 按页依次填充用户地址空间中 [dst, dst + len) 的数据，f 的第二个参数是
+// 这一段相对于 dst 的偏移。范围越界时在访问任何一页之前返回错误。
+pub fn write_user_chunks(
+    memory_set: &mut MemorySet,
+    dst: usize,
+    len: usize,
+    f: impl FnMut(&mut [u8], usize),
+) -> Result<(), PageFaultError> {
+    for_each_user_page(memory_set, dst, len, MapPermission::W, f)
+}
+
+// read_user_str 读取用户地址空间中以 '\0' 结尾的字符串，字符串太长或者不是合法的
+// UTF-8 时同样返回 PageFaultError::InvalidAccess
+pub fn read_user_str(memory_set: &mut MemorySet, ptr: usize) -> Result<String, PageFaultError> {
+    let mut bytes = Vec::new();
+    let mut current = ptr;
+    while bytes.len() < USER_STR_MAX {
+        if current >= config::user_space_end() {
+            return Err(PageFaultError::InvalidAccess);
+        }
+        let va = VirtAddr::from(current);
+        let ppn = user_page(memory_set, va, MapPermission::R)?;
+        let page = &ppn.get_bytes_array()[va.page_offset()..];
+        match page.iter().position(|&c| c == 0) {
+            Some(len) => {
+                bytes.extend_from_slice(&page[..len]);
+                return String::from_utf8(bytes).map_err(|_| PageFaultError::InvalidAccess);
+            }
+            None => {
+                bytes.extend_from_slice(page);
+                current += page.len();
+            }
+        }
+    }
+    Err(PageFaultError::InvalidAccess)
+}
+
+// UserPtr 表示一个指向用户地址空间中 T 类型数据的指针，T 必须是可以按字节拷贝的类型。
+// 读写都按照字节拷贝进行，所以不要求用户指针按照 T 对齐。
+pub struct UserPtr<T: Copy> {
+    addr: usize,
+    _marker: PhantomData<T>,
+}
+
+impl<T: Copy> UserPtr<T> {
+    pub fn new(addr: usize) -> Self {
+        Self {
+            addr,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        self.addr == 0
+    }
+
+    pub fn read(&self, memory_set: &mut MemorySet) -> Result<T, PageFaultError> {
+        let mut value = MaybeUninit::<T>::uninit();
+        let bytes = unsafe {
+            core::slice::from_raw_parts_mut(
+                value.as_mut_ptr() as *mut u8,
+                core::mem::size_of::<T>(),
+            )
+        };
+        copy_from_user(memory_set, self.addr, bytes)?;
+        Ok(unsafe { value.assume_init() })
+    }
+
+    pub fn write(&self, memory_set: &mut MemorySet, value: T) -> Result<(), PageFaultError> {
+        let bytes = unsafe {
+            core::slice::from_raw_parts(&value as *const T as *const u8, core::mem::size_of::<T>())
+        };
+        copy_to_user(memory_set, self.addr, bytes)
+    }
+}
@@ -0,0 +1,27 @@
+// 内核的伪随机数生成器 (xorshift64*)，种子来自设备树中的 rng-seed 和时钟周期数，
+// 每次生成随机数时还会混入当前的时钟周期数。只用于地址空间随机化这类不需要
+// 密码学安全的场景。
+use lazy_static::*;
+
+use crate::{fdt, sync::UPSafeCell, timer};
+
+lazy_static! {
+    static ref RNG_STATE: UPSafeCell<u64> = unsafe {
+        let seed = fdt::MACHINE_INFO.exclusive_access().rng_seed ^ timer::get_time() as u64;
+        // xorshift 的状态不能为 0
+        UPSafeCell::new(seed | 1)
+    };
+}
+
+pub fn rand_u64() -> u64 {
+    let mut state = RNG_STATE.exclusive_access();
+    let mut x = *state ^ (timer::get_time() as u64).rotate_left(32);
+    if x == 0 {
+        x = 1;
+    }
+    x ^= x >> 12;
+    x ^= x << 25;
+    x ^= x >> 27;
+    *state = x;
+    x.wrapping_mul(0x2545_f491_4f6c_dd1d)
+}
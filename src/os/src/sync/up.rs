@@ -27,4 +27,9 @@ impl<T> UPSafeCell<T> {
 	pub fn exclusive_access(&self) -> RefMut<'_, T> {
 		self.inner.borrow_mut()
 	}
+
+	// try_exclusive_access 在已经被借用时返回 None 而不是 panic
+	pub fn try_exclusive_access(&self) -> Option<RefMut<'_, T>> {
+		self.inner.try_borrow_mut().ok()
+	}
 }
\ No newline at end of file
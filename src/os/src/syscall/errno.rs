@@ -0,0 +1,16 @@
+// Linux 风格的错误码，系统调用失败时返回对应的负数，比如 -EINVAL。
+// Ref: https://man7.org/linux/man-pages/man3/errno.3.html
+
+pub const EPERM: isize = 1;
+pub const ENOENT: isize = 2;
+pub const ESRCH: isize = 3;
+pub const E2BIG: isize = 7;
+pub const ENOEXEC: isize = 8;
+pub const EBADF: isize = 9;
+pub const EAGAIN: isize = 11;
+pub const ENOMEM: isize = 12;
+pub const EACCES: isize = 13;
+pub const EFAULT: isize = 14;
+pub const EEXIST: isize = 17;
+pub const EINVAL: isize = 22;
+pub const EMFILE: isize = 24;
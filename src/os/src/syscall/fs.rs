@@ -1,32 +1,182 @@
-use crate::{mm::page_table, task::{processor, self}, sbi};
+use alloc::sync::Arc;
+
+use crate::{
+    config::PAGE_SIZE,
+    fs::{AppFile, MemFile, OpenFile},
+    mm::{self, user_ptr},
+    sbi,
+    task::{self, processor},
+};
+
+use super::{
+    errno::{EACCES, EBADF, EINVAL, EMFILE, ENOENT, ENOMEM},
+    user_access,
+};
 
 const FD_STDIN: usize = 0;
 const FD_STDOUT: usize = 1;
 
+const O_ACCMODE: usize = 3;
+const O_RDONLY: usize = 0;
+
+// Utf8Decoder 将按页拆分的数据解码成字符串，一个字符可能跨越两页，
+// 所以上一段末尾不完整的字符保存在 pending 中
+struct Utf8Decoder {
+    pending: [u8; 4],
+    len: usize,
+}
+
+impl Utf8Decoder {
+    fn new() -> Self {
+        Self {
+            pending: [0; 4],
+            len: 0,
+        }
+    }
+
+    // feed 解码 data 并用完整的字符串调用 out，data 不是合法的 UTF-8 时返回 false
+    fn feed(&mut self, mut data: &[u8], mut out: impl FnMut(&str)) -> bool {
+        while self.len > 0 && !data.is_empty() {
+            // 补全上一段末尾的字符，UTF-8 的字符最长 4 个字节
+            let take = (4 - self.len).min(data.len());
+            self.pending[self.len..self.len + take].copy_from_slice(&data[..take]);
+            let bytes = &self.pending[..self.len + take];
+            match core::str::from_utf8(bytes) {
+                Ok(s) => {
+                    out(s);
+                    data = &data[take..];
+                    self.len = 0;
+                }
+                Err(e) if e.valid_up_to() > 0 => {
+                    let valid = e.valid_up_to();
+                    out(unsafe { core::str::from_utf8_unchecked(&bytes[..valid]) });
+                    data = &data[valid - self.len..];
+                    self.len = 0;
+                }
+                Err(e) if e.error_len().is_none() => {
+                    data = &data[take..];
+                    self.len += take;
+                }
+                Err(_) => return false,
+            }
+        }
+        match core::str::from_utf8(data) {
+            Ok(s) => out(s),
+            Err(e) if e.error_len().is_none() => {
+                let valid = e.valid_up_to();
+                out(unsafe { core::str::from_utf8_unchecked(&data[..valid]) });
+                let rest = &data[valid..];
+                self.pending[..rest.len()].copy_from_slice(rest);
+                self.len = rest.len();
+            }
+            Err(_) => return false,
+        }
+        true
+    }
+
+    // finish 检查数据的末尾是否有不完整的字符
+    fn finish(&self) -> bool {
+        self.len == 0
+    }
+}
+
 /// write buf of length `len` to a file with `fd`
 pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> isize {
     match fd {
         FD_STDOUT => {
-            let buffers =
-                page_table::translated_byte_buffer(processor::current_user_token(), buf, len);
-            for buffer in buffers {
-                print!("{}", core::str::from_utf8(buffer).unwrap());
+            // 第一遍只检查整段数据是否可以访问以及是否是合法的 UTF-8，第二遍再逐页输出，
+            // 这样出错时不会输出一部分数据。第一遍之后所有的页都已经映射，第二遍不会失败。
+            let valid = user_access(|memory_set| {
+                let mut decoder = Utf8Decoder::new();
+                let mut valid = true;
+                user_ptr::read_user_chunks(memory_set, buf as usize, len, |chunk| {
+                    valid = valid && decoder.feed(chunk, |_| {});
+                })?;
+                Ok(valid && decoder.finish())
+            });
+            match valid {
+                Ok(true) => {}
+                Ok(false) => return -EINVAL,
+                Err(errno) => return -errno,
             }
+            let mut decoder = Utf8Decoder::new();
+            let task = processor::current_task().unwrap();
+            let memory_set = &mut task.inner_exclusive_access().memory_set;
+            let _ = user_ptr::read_user_chunks(memory_set, buf as usize, len, |chunk| {
+                decoder.feed(chunk, |s| print!("{}", s));
+            });
             len as isize
         }
         _ => {
-            panic!("Unsupported fd in sys_write!");
+            let open_file = match get_open_file(fd) {
+                Some(open_file) => open_file,
+                None => return -EBADF,
+            };
+            write_file(&open_file, buf as usize, len)
+        }
+    }
+}
+
+// get_open_file 返回当前进程中 fd 对应的 OpenFile
+fn get_open_file(fd: usize) -> Option<Arc<OpenFile>> {
+    processor::current_task()
+        .unwrap()
+        .inner_exclusive_access()
+        .get_open_file(fd)
+}
+
+// write_file 从文件位置开始写入最多 len 字节并移动文件位置，返回写入的字节数。
+// 写入不会改变文件的长度，也不经过 page cache，与 sys_pread 一样。
+fn write_file(open_file: &OpenFile, buf: usize, len: usize) -> isize {
+    let file = &open_file.file;
+    if !file.writable() {
+        return -EBADF;
+    }
+    let offset = open_file.offset();
+    let len = len.min(file.size().saturating_sub(offset));
+    let result = user_access(|memory_set| {
+        let mut pos = offset;
+        user_ptr::read_user_chunks(memory_set, buf, len, |chunk| {
+            pos += file.write_at(pos, chunk);
+        })
+    });
+    match result {
+        Ok(()) => {
+            open_file.set_offset(offset + len);
+            len as isize
         }
+        Err(errno) => -errno,
     }
 }
 
-// sys_read 在目前版本中只能接收一个字符，如果字符是 0 则说明没有
+// read_file 从文件位置开始读取最多 len 字节到 buf 中并移动文件位置，返回读取的字节数
+fn read_file(open_file: &OpenFile, buf: usize, len: usize) -> isize {
+    let file = &open_file.file;
+    let offset = open_file.offset();
+    let len = len.min(file.size().saturating_sub(offset));
+    let result = user_access(|memory_set| {
+        user_ptr::write_user_chunks(memory_set, buf, len, |chunk, pos| {
+            file.read_at(offset + pos, chunk);
+        })
+    });
+    match result {
+        Ok(()) => {
+            open_file.set_offset(offset + len);
+            len as isize
+        }
+        Err(errno) => -errno,
+    }
+}
+
+// sys_read 从标准输入读取时在目前版本中只能接收一个字符，如果字符是 0 则说明没有
 // 新的输入，那么就会让出 CPU，反之如果有则将字符保存在 buf 的第一个
-// 位置中。
+// 位置中。其它的 fd 从文件位置开始读取文件。
 pub fn sys_read(fd: usize, buf: *const u8, len: usize) -> isize {
     match fd {
         FD_STDIN => {
-            assert!(len == 1, "Only support len == 1 in sys_read!");
+            if len != 1 {
+                return -EINVAL;
+            }
             let mut c: usize;
             loop {
                 c = sbi::console_getchar();
@@ -39,18 +189,113 @@ pub fn sys_read(fd: usize, buf: *const u8, len: usize) -> isize {
             }
 
             let ch = c as u8;
-            let mut buffers = page_table::translated_byte_buffer(
-                processor::current_user_token(),
-                buf,
-                len,
-            );
-            unsafe {
-                buffers[0].as_mut_ptr().write_volatile(ch);
+            let result =
+                user_access(|memory_set| user_ptr::copy_to_user(memory_set, buf as usize, &[ch]));
+            match result {
+                Ok(()) => 0,
+                Err(errno) => -errno,
             }
-            0
         }
         _ => {
-            panic!("Unsupported fd in sys_read!");
+            let open_file = match get_open_file(fd) {
+                Some(open_file) => open_file,
+                None => return -EBADF,
+            };
+            read_file(&open_file, buf as usize, len)
+        }
+    }
+}
+
+// sys_open 以只读方式打开名为 path 的用户程序，返回文件描述符
+pub fn sys_open(path: *const u8, flags: usize) -> isize {
+    let path = match user_access(|memory_set| user_ptr::read_user_str(memory_set, path as usize)) {
+        Ok(path) => path,
+        Err(errno) => return -errno,
+    };
+    if flags & O_ACCMODE != O_RDONLY {
+        return -EACCES;
+    }
+    let file = match AppFile::open(path.as_str()) {
+        Some(file) => file,
+        None => return -ENOENT,
+    };
+    let task = processor::current_task().unwrap();
+    let mut task_inner = task.inner_exclusive_access();
+    match task_inner.alloc_fd(file) {
+        Some(fd) => fd as isize,
+        None => -EMFILE,
+    }
+}
+
+// sys_memfd_create 创建一个长度为 0 的可读写内存文件，返回文件描述符。
+// name 只用于调试，这里只检查它是否可以读取。
+pub fn sys_memfd_create(name: *const u8, flags: usize) -> isize {
+    let result = user_access(|memory_set| user_ptr::read_user_str(memory_set, name as usize));
+    if let Err(errno) = result {
+        return -errno;
+    }
+    if flags != 0 {
+        return -EINVAL;
+    }
+    let task = processor::current_task().unwrap();
+    let mut task_inner = task.inner_exclusive_access();
+    match task_inner.alloc_fd(MemFile::new()) {
+        Some(fd) => fd as isize,
+        None => -EMFILE,
+    }
+}
+
+pub fn sys_close(fd: usize) -> isize {
+    let task = processor::current_task().unwrap();
+    let mut task_inner = task.inner_exclusive_access();
+    match task_inner.fd_table.get_mut(fd) {
+        Some(file) if file.is_some() => {
+            *file = None;
+            0
         }
+        _ => -EBADF,
+    }
+}
+
+// sys_pread 从文件的 offset 处读取最多 len 字节到 buf 中，返回读取的字节数。
+// 读取不经过 page cache，所以共享文件映射中的修改在 msync 或者 munmap 写回之后才能读到。
+pub fn sys_pread(fd: usize, buf: *mut u8, len: usize, offset: usize) -> isize {
+    let file = match processor::current_task()
+        .unwrap()
+        .inner_exclusive_access()
+        .get_file(fd)
+    {
+        Some(file) => file,
+        None => return -EBADF,
+    };
+    let len = len.min(file.size().saturating_sub(offset));
+    match user_access(|memory_set| {
+        user_ptr::write_user_chunks(memory_set, buf as usize, len, |chunk, pos| {
+            file.read_at(offset + pos, chunk);
+        })
+    }) {
+        Ok(()) => len as isize,
+        Err(errno) => -errno,
+    }
+}
+
+// sys_ftruncate 将文件的长度修改为 len，并丢弃 page cache 中受影响的页
+pub fn sys_ftruncate(fd: usize, len: usize) -> isize {
+    let file = match processor::current_task()
+        .unwrap()
+        .inner_exclusive_access()
+        .get_file(fd)
+    {
+        Some(file) => file,
+        None => return -EBADF,
+    };
+    if !file.writable() {
+        return -EINVAL;
+    }
+    let old_len = file.size();
+    if !file.set_size(len) {
+        return -ENOMEM;
     }
+    mm::truncate_pages(file.ino(), old_len.min(len) / PAGE_SIZE);
+    0
 }
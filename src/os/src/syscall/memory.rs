@@ -0,0 +1,386 @@
+use crate::{
+    config::{self, PAGE_SIZE},
+    mm::{
+        self,
+        address::{VirtAddr, VirtPageNum},
+        memory_set::{MapPermission, MemoryStat},
+        shm::{self, IPC_PRIVATE},
+        user_ptr::UserPtr,
+        HeapStat, SlabStat,
+    },
+    task::processor,
+};
+
+use super::{
+    errno::{EACCES, EBADF, EEXIST, EINVAL, ENOENT, ENOMEM},
+    user_access,
+};
+
+const PROT_READ: usize = 1 << 0;
+const PROT_WRITE: usize = 1 << 1;
+const PROT_EXEC: usize = 1 << 2;
+
+const MAP_SHARED: usize = 0x01;
+const MAP_PRIVATE: usize = 0x02;
+const MAP_FIXED: usize = 0x10;
+const MAP_ANONYMOUS: usize = 0x20;
+
+const MS_ASYNC: usize = 1;
+const MS_INVALIDATE: usize = 2;
+const MS_SYNC: usize = 4;
+
+const IPC_CREAT: usize = 0o1000;
+const IPC_EXCL: usize = 0o2000;
+
+// shmctl 的命令，目前只支持 IPC_RMID
+const IPC_RMID: usize = 0;
+const SHM_RDONLY: usize = 0o10000;
+const SHM_RND: usize = 0o20000;
+
+// FrameStat 是物理页框的使用情况，单位是页
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct FrameStat {
+    pub total: usize,
+    pub free: usize,
+}
+
+// prot_to_permission 将 PROT_* 转换为 MapPermission，非法的 prot 返回 None。
+// RISC-V 不允许只写不读的页表项，所以 PROT_WRITE 同时意味着可读。
+fn prot_to_permission(prot: usize) -> Option<MapPermission> {
+    if prot & !(PROT_READ | PROT_WRITE | PROT_EXEC) != 0 {
+        return None;
+    }
+    let mut permission = MapPermission::U;
+    if prot & PROT_READ != 0 {
+        permission |= MapPermission::R;
+    }
+    if prot & PROT_WRITE != 0 {
+        permission |= MapPermission::R | MapPermission::W;
+    }
+    if prot & PROT_EXEC != 0 {
+        permission |= MapPermission::X;
+    }
+    Some(permission)
+}
+
+// user_vpn_range 检查用户传入的 [start, start + len) 是否页对齐且位于用户地址空间，
+// 返回对应的 vpn 范围。
+fn user_vpn_range(start: usize, len: usize) -> Result<(VirtPageNum, VirtPageNum), isize> {
+    if start % PAGE_SIZE != 0 || len == 0 {
+        return Err(EINVAL);
+    }
+    let end = match start.checked_add(len) {
+        Some(end) if end <= config::user_space_end() => end,
+        _ => return Err(ENOMEM),
+    };
+    Ok((VirtAddr::from(start).floor(), VirtAddr::from(end).ceil()))
+}
+
+// sys_brk 将 heap 的结束地址设置为 addr，返回新的结束地址。
+// addr 为 0 或者调整失败时返回当前的结束地址，与 Linux 的行为保持一致。
+pub fn sys_brk(addr: usize) -> isize {
+    let task = processor::current_task().unwrap();
+    let mut task_inner = task.inner_exclusive_access();
+    if addr != 0 {
+        task_inner.change_program_brk(addr);
+    }
+    task_inner.program_brk as isize
+}
+
+// sys_mmap 支持匿名私有映射以及文件映射，匿名映射会忽略 fd 和 offset。
+// 文件映射的页在第一次访问时从 page cache 中获取，MAP_SHARED 映射中的修改直接写入
+// page cache，并在 msync/munmap 时写回文件；MAP_PRIVATE 映射在写入时复制。
+pub fn sys_mmap(
+    start: usize,
+    len: usize,
+    prot: usize,
+    flags: usize,
+    fd: usize,
+    offset: usize,
+) -> isize {
+    if flags & !(MAP_SHARED | MAP_PRIVATE | MAP_FIXED | MAP_ANONYMOUS) != 0
+        || flags & (MAP_SHARED | MAP_PRIVATE) == MAP_SHARED | MAP_PRIVATE
+        || flags & (MAP_SHARED | MAP_PRIVATE) == 0
+    {
+        return -EINVAL;
+    }
+    let shared = flags & MAP_SHARED != 0;
+    let task = processor::current_task().unwrap();
+    let file = if flags & MAP_ANONYMOUS != 0 {
+        // 还不支持匿名共享映射
+        if shared {
+            return -EINVAL;
+        }
+        None
+    } else {
+        let file = match task.inner_exclusive_access().get_file(fd) {
+            Some(file) => file,
+            None => return -EBADF,
+        };
+        if offset % PAGE_SIZE != 0 {
+            return -EINVAL;
+        }
+        // 只读文件不能以可写的方式共享映射
+        if shared && prot & PROT_WRITE != 0 && !file.writable() {
+            return -EACCES;
+        }
+        Some(file)
+    };
+    let permission = match prot_to_permission(prot) {
+        Some(permission) => permission,
+        None => return -EINVAL,
+    };
+    let (start_vpn, end_vpn) = match user_vpn_range(start, len) {
+        Ok(range) => range,
+        Err(errno) => return -errno,
+    };
+    let page_count = end_vpn.0 - start_vpn.0;
+
+    let memory_set = &mut task.inner_exclusive_access().memory_set;
+    let start_vpn = if flags & MAP_FIXED != 0 {
+        if start == 0 || memory_set.overlaps_heap(start_vpn, end_vpn) {
+            return -EINVAL;
+        }
+        // MAP_FIXED 会替换掉区域内已有的映射
+        memory_set.munmap(start_vpn, end_vpn);
+        start_vpn
+    } else if start != 0 && memory_set.is_range_free(start_vpn, end_vpn) {
+        start_vpn
+    } else {
+        match memory_set.find_free_area(
+            VirtAddr::from(config::user_mmap_base()).floor(),
+            page_count,
+            VirtAddr::from(config::user_space_end()).floor(),
+        ) {
+            Some(vpn) => vpn,
+            None => return -ENOMEM,
+        }
+    };
+    let end_vpn = VirtPageNum(start_vpn.0 + page_count);
+    // 超过 RLIMIT_AS
+    let mapped = match file {
+        Some(file) => memory_set.mmap_file(start_vpn, end_vpn, permission, file, offset, shared),
+        None => memory_set.mmap(start_vpn, end_vpn, permission),
+    };
+    if !mapped {
+        return -ENOMEM;
+    }
+    VirtAddr::from(start_vpn).0 as isize
+}
+
+pub fn sys_munmap(start: usize, len: usize) -> isize {
+    let (start_vpn, end_vpn) = match user_vpn_range(start, len) {
+        Ok(range) => range,
+        Err(_) => return -EINVAL,
+    };
+    let task = processor::current_task().unwrap();
+    let memory_set = &mut task.inner_exclusive_access().memory_set;
+    // heap 只能通过 brk 调整
+    if memory_set.overlaps_heap(start_vpn, end_vpn) {
+        return -EINVAL;
+    }
+    memory_set.munmap(start_vpn, end_vpn);
+    0
+}
+
+pub fn sys_mprotect(start: usize, len: usize, prot: usize) -> isize {
+    let permission = match prot_to_permission(prot) {
+        Some(permission) => permission,
+        None => return -EINVAL,
+    };
+    let (start_vpn, end_vpn) = match user_vpn_range(start, len) {
+        Ok(range) => range,
+        Err(errno) => return -errno,
+    };
+    let task = processor::current_task().unwrap();
+    let memory_set = &mut task.inner_exclusive_access().memory_set;
+    if memory_set.overlaps_heap(start_vpn, end_vpn) {
+        return -EINVAL;
+    }
+    // 以只读方式映射的共享内存以及只读文件的共享映射不能被修改为可写
+    if permission.contains(MapPermission::W) && memory_set.is_write_denied(start_vpn, end_vpn) {
+        return -EACCES;
+    }
+    if !memory_set.mprotect(start_vpn, end_vpn, permission) {
+        return -ENOMEM;
+    }
+    0
+}
+
+// sys_msync 将 [start, start + len) 中共享文件映射被修改过的页写回文件，
+// 写回总是同步完成的，所以 MS_ASYNC 和 MS_SYNC 的行为相同。
+pub fn sys_msync(start: usize, len: usize, flags: usize) -> isize {
+    if flags & !(MS_ASYNC | MS_INVALIDATE | MS_SYNC) != 0
+        || flags & (MS_ASYNC | MS_SYNC) == MS_ASYNC | MS_SYNC
+    {
+        return -EINVAL;
+    }
+    if len == 0 {
+        return if start % PAGE_SIZE == 0 { 0 } else { -EINVAL };
+    }
+    let (start_vpn, end_vpn) = match user_vpn_range(start, len) {
+        Ok(range) => range,
+        Err(errno) => return -errno,
+    };
+    let task = processor::current_task().unwrap();
+    if !task
+        .inner_exclusive_access()
+        .memory_set
+        .msync(start_vpn, end_vpn)
+    {
+        return -ENOMEM;
+    }
+    0
+}
+
+// sys_shmget 返回 key 对应的共享内存段的 shmid，IPC_CREAT 表示不存在时创建一个
+// 大小为 size 的共享内存段。shmflg 中的访问权限位会被忽略。
+pub fn sys_shmget(key: usize, size: usize, shmflg: usize) -> isize {
+    let page_count = (size + PAGE_SIZE - 1) / PAGE_SIZE;
+    if key != IPC_PRIVATE {
+        if let Some((id, segment_pages)) = shm::shm_find(key) {
+            if shmflg & IPC_CREAT != 0 && shmflg & IPC_EXCL != 0 {
+                return -EEXIST;
+            }
+            if page_count > segment_pages {
+                return -EINVAL;
+            }
+            return id as isize;
+        }
+        if shmflg & IPC_CREAT == 0 {
+            return -ENOENT;
+        }
+    }
+    if size == 0 {
+        return -EINVAL;
+    }
+    match shm::shm_create(key, page_count) {
+        Some(id) => id as isize,
+        None => -ENOMEM,
+    }
+}
+
+// sys_shmat 将 shmid 对应的共享内存段映射到 shmaddr，shmaddr 为 0 时由内核选择地址，
+// 返回映射的起始地址。
+pub fn sys_shmat(shmid: usize, shmaddr: usize, shmflg: usize) -> isize {
+    let segment = match shm::shm_get(shmid) {
+        Some(segment) => segment,
+        None => return -EINVAL,
+    };
+    let permission = if shmflg & SHM_RDONLY != 0 {
+        MapPermission::R | MapPermission::U
+    } else {
+        MapPermission::R | MapPermission::W | MapPermission::U
+    };
+    let shmaddr = if shmflg & SHM_RND != 0 {
+        shmaddr & !(PAGE_SIZE - 1)
+    } else {
+        shmaddr
+    };
+    let page_count = segment.frames.len();
+
+    let task = processor::current_task().unwrap();
+    let memory_set = &mut task.inner_exclusive_access().memory_set;
+    let start_vpn = if shmaddr == 0 {
+        match memory_set.find_free_area(
+            VirtAddr::from(config::user_mmap_base()).floor(),
+            page_count,
+            VirtAddr::from(config::user_space_end()).floor(),
+        ) {
+            Some(vpn) => vpn,
+            None => return -ENOMEM,
+        }
+    } else {
+        let (start_vpn, end_vpn) = match user_vpn_range(shmaddr, page_count * PAGE_SIZE) {
+            Ok(range) => range,
+            Err(_) => return -EINVAL,
+        };
+        if !memory_set.is_range_free(start_vpn, end_vpn) {
+            return -EINVAL;
+        }
+        start_vpn
+    };
+    if memory_set
+        .attach_shm(start_vpn, segment, permission)
+        .is_none()
+    {
+        return -ENOMEM;
+    }
+    shm::shm_attached(shmid);
+    VirtAddr::from(start_vpn).0 as isize
+}
+
+// sys_shmdt 解除 shmaddr 处共享内存段的映射，最后一个映射被解除时共享内存段被释放
+pub fn sys_shmdt(shmaddr: usize) -> isize {
+    if shmaddr % PAGE_SIZE != 0 || shmaddr >= config::user_space_end() {
+        return -EINVAL;
+    }
+    let task = processor::current_task().unwrap();
+    if !task
+        .inner_exclusive_access()
+        .memory_set
+        .detach_shm(VirtAddr::from(shmaddr).floor())
+    {
+        return -EINVAL;
+    }
+    0
+}
+
+// sys_shmctl 目前只支持 IPC_RMID：删除 shmid 对应的共享内存段，已经存在的映射在解除之前
+// 仍然可以使用。buf 只被 IPC_RMID 之外的命令使用。
+pub fn sys_shmctl(shmid: usize, cmd: usize, _buf: usize) -> isize {
+    if cmd != IPC_RMID || !shm::shm_remove(shmid) {
+        return -EINVAL;
+    }
+    0
+}
+
+// sys_memstat 将当前进程地址空间的内存使用情况 (VSZ/RSS 等) 写入 stat
+pub fn sys_memstat(stat: *mut MemoryStat) -> isize {
+    let memory_stat = processor::current_task()
+        .unwrap()
+        .inner_exclusive_access()
+        .memory_set
+        .stat();
+    match user_access(|memory_set| UserPtr::new(stat as usize).write(memory_set, memory_stat)) {
+        Ok(()) => 0,
+        Err(errno) => -errno,
+    }
+}
+
+// sys_framestat 将全局的物理页框使用情况写入 stat，可以用来检查进程退出之后是否有页框泄漏
+pub fn sys_framestat(stat: *mut FrameStat) -> isize {
+    let frame_stat = FrameStat {
+        total: mm::frame_total_count(),
+        free: mm::frame_free_count(),
+    };
+    match user_access(|memory_set| UserPtr::new(stat as usize).write(memory_set, frame_stat)) {
+        Ok(()) => 0,
+        Err(errno) => -errno,
+    }
+}
+
+// sys_heapstat 将内核堆的使用情况写入 stat，用于调试内核堆的扩展
+pub fn sys_heapstat(stat: *mut HeapStat) -> isize {
+    let heap_stat = mm::heap_stat();
+    match user_access(|memory_set| UserPtr::new(stat as usize).write(memory_set, heap_stat)) {
+        Ok(()) => 0,
+        Err(errno) => -errno,
+    }
+}
+
+// sys_slabstat 将前 len 项 slab 缓存的使用情况写入 stats，返回全部的项数。每一项是一个缓存
+// 中的一个大小分类，只包括已经有 slab 的大小分类。
+pub fn sys_slabstat(stats: *mut SlabStat, len: usize) -> isize {
+    let slab_stats = mm::slab_stat();
+    for (i, &slab_stat) in slab_stats.iter().take(len).enumerate() {
+        let addr = stats as usize + i * core::mem::size_of::<SlabStat>();
+        if let Err(errno) =
+            user_access(|memory_set| UserPtr::new(addr).write(memory_set, slab_stat))
+        {
+            return -errno;
+        }
+    }
+    slab_stats.len() as isize
+}
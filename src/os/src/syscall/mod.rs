@@ -1,30 +1,117 @@
+const SYSCALL_FTRUNCATE: usize = 46;
+const SYSCALL_OPEN: usize = 56;
+const SYSCALL_CLOSE: usize = 57;
 const SYSCALL_READ: usize = 63;
 const SYSCALL_WRITE: usize = 64;
+const SYSCALL_PREAD: usize = 67;
+const SYSCALL_PERSONALITY: usize = 92;
 const SYSCALL_EXIT: usize = 93;
 const SYSCALL_YIELD: usize = 124;
+const SYSCALL_GETRLIMIT: usize = 163;
+const SYSCALL_SETRLIMIT: usize = 164;
 const SYSCALL_GET_TIME: usize = 169;
 const SYSCALL_GETPID: usize = 172;
+const SYSCALL_SHMGET: usize = 194;
+const SYSCALL_SHMCTL: usize = 195;
+const SYSCALL_SHMAT: usize = 196;
+const SYSCALL_SHMDT: usize = 197;
+const SYSCALL_BRK: usize = 214;
+const SYSCALL_MUNMAP: usize = 215;
 const SYSCALL_FORK: usize = 220;
 const SYSCALL_EXEC: usize = 221;
+const SYSCALL_MMAP: usize = 222;
+const SYSCALL_MPROTECT: usize = 226;
+const SYSCALL_MSYNC: usize = 227;
 const SYSCALL_WAITPID: usize = 260;
+const SYSCALL_PRLIMIT: usize = 261;
+const SYSCALL_MEMFD_CREATE: usize = 279;
+// rCore 自定义的系统调用
+const SYSCALL_MEMSTAT: usize = 500;
+const SYSCALL_FRAMESTAT: usize = 501;
+const SYSCALL_HEAPSTAT: usize = 502;
+const SYSCALL_SLABSTAT: usize = 503;
 
+mod errno;
 mod fs;
+mod memory;
 mod process;
+mod resource;
 
 use fs::*;
+use memory::*;
 use process::*;
+use resource::*;
 
-pub fn syscall(syscall_id: usize, args: [usize; 3]) -> isize {
+use crate::{
+    mm::{
+        memory_set::{MemorySet, MemoryStat, PageFaultError},
+        HeapStat, SlabStat,
+    },
+    task::{self, processor, rlimit::RLimit},
+};
+
+use errno::{EFAULT, ENOMEM};
+
+pub fn syscall(syscall_id: usize, args: [usize; 6]) -> isize {
     match syscall_id {
         SYSCALL_EXIT => sys_exit(args[0] as i32),
         SYSCALL_WRITE => sys_write(args[0], args[1] as *const u8, args[2]),
         SYSCALL_YIELD => sys_yield(),
         SYSCALL_GET_TIME => sys_get_time(),
         SYSCALL_READ => sys_read(args[0], args[1] as *const u8, args[2]),
+        SYSCALL_PREAD => sys_pread(args[0], args[1] as *mut u8, args[2], args[3]),
+        SYSCALL_OPEN => sys_open(args[0] as *const u8, args[1]),
+        SYSCALL_CLOSE => sys_close(args[0]),
+        SYSCALL_FTRUNCATE => sys_ftruncate(args[0], args[1]),
+        SYSCALL_MEMFD_CREATE => sys_memfd_create(args[0] as *const u8, args[1]),
         SYSCALL_GETPID => sys_getpid(),
+        SYSCALL_PERSONALITY => sys_personality(args[0]),
         SYSCALL_FORK => sys_fork(),
-        SYSCALL_EXEC => sys_exec(args[0] as *const u8),
+        SYSCALL_EXEC => sys_exec(
+            args[0] as *const u8,
+            args[1] as *const usize,
+            args[2] as *const usize,
+        ),
         SYSCALL_WAITPID => sys_waitpid(args[0] as isize, args[1] as *mut i32),
+        SYSCALL_GETRLIMIT => sys_getrlimit(args[0], args[1] as *mut RLimit),
+        SYSCALL_SETRLIMIT => sys_setrlimit(args[0], args[1] as *const RLimit),
+        SYSCALL_PRLIMIT => sys_prlimit(
+            args[0],
+            args[1],
+            args[2] as *const RLimit,
+            args[3] as *mut RLimit,
+        ),
+        SYSCALL_BRK => sys_brk(args[0]),
+        SYSCALL_MMAP => sys_mmap(args[0], args[1], args[2], args[3], args[4], args[5]),
+        SYSCALL_MUNMAP => sys_munmap(args[0], args[1]),
+        SYSCALL_MPROTECT => sys_mprotect(args[0], args[1], args[2]),
+        SYSCALL_MSYNC => sys_msync(args[0], args[1], args[2]),
+        SYSCALL_SHMGET => sys_shmget(args[0], args[1], args[2]),
+        SYSCALL_SHMCTL => sys_shmctl(args[0], args[1], args[2]),
+        SYSCALL_SHMAT => sys_shmat(args[0], args[1], args[2]),
+        SYSCALL_SHMDT => sys_shmdt(args[0]),
+        SYSCALL_MEMSTAT => sys_memstat(args[0] as *mut MemoryStat),
+        SYSCALL_FRAMESTAT => sys_framestat(args[0] as *mut FrameStat),
+        SYSCALL_HEAPSTAT => sys_heapstat(args[0] as *mut HeapStat),
+        SYSCALL_SLABSTAT => sys_slabstat(args[0] as *mut SlabStat, args[1]),
         _ => panic!("Unsupported system_id: {}", syscall_id),
     }
 }
+
+// user_access 在当前进程的地址空间上执行访问用户内存的 f，用户地址非法时返回 EFAULT。
+// 页框耗尽时与 page fault 的处理相同：杀死 RSS 最大的其他进程之后重试，没有可以杀死的
+// 进程时返回 ENOMEM。f 可能被执行多次，调用时不能持有当前进程的 inner。
+fn user_access<T>(
+    mut f: impl FnMut(&mut MemorySet) -> Result<T, PageFaultError>,
+) -> Result<T, isize> {
+    let task = processor::current_task().unwrap();
+    loop {
+        let result = f(&mut task.inner_exclusive_access().memory_set);
+        match result {
+            Ok(value) => return Ok(value),
+            Err(PageFaultError::InvalidAccess) => return Err(EFAULT),
+            Err(PageFaultError::OutOfMemory) if !task::oom_kill() => return Err(ENOMEM),
+            Err(PageFaultError::OutOfMemory) => {}
+        }
+    }
+}
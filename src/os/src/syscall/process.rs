@@ -1,17 +1,33 @@
-use alloc::sync::Arc;
+use alloc::{string::String, sync::Arc, vec::Vec};
 
 use crate::{
-    loader,
-    mm::page_table,
-    task::{self, manager, processor},
+    fs::AppFile,
+    mm::{
+        self,
+        memory_set::ElfLoadError,
+        user_ptr::{self, UserPtr},
+    },
+    task::{self, manager, processor, ForkError, TaskControlBlock},
     timer,
 };
 
+use super::{
+    errno::{E2BIG, EAGAIN, EINVAL, ENOEXEC, ENOMEM},
+    user_access,
+};
+
 const ANY_PROCESS: isize = -1;
 
 const NO_CHILDREN_RUNNING: isize = -1;
 const CHILDREN_RUNNING: isize = -2;
 
+// exec 参数的总长度上限，包括字符串和指针
+const ARG_MAX: usize = 0x2_0000;
+
+// personality 的查询参数以及表示关闭地址空间随机化的标志
+const PERSONALITY_QUERY: usize = 0xffff_ffff;
+const ADDR_NO_RANDOMIZE: usize = 0x0040000;
+
 pub fn sys_exit(exit_code: i32) -> ! {
     println!("[kernel] Application exited with code {}", exit_code);
     task::exit_current_and_run_next(exit_code);
@@ -33,7 +49,11 @@ pub fn sys_getpid() -> isize {
 
 pub fn sys_fork() -> isize {
     let parent_tcb = processor::current_task().unwrap();
-    let child_tcb = parent_tcb.fork();
+    let child_tcb = match TaskControlBlock::fork(&parent_tcb) {
+        Ok(child_tcb) => child_tcb,
+        Err(ForkError::ProcessLimit) => return -EAGAIN,
+        Err(ForkError::OutOfMemory) => return -ENOMEM,
+    };
     let child_pid = child_tcb.getpid();
     let mut child_trap_cx = child_tcb.inner_exclusive_access().get_trap_cx();
     // child process's return value is 0
@@ -43,23 +63,80 @@ pub fn sys_fork() -> isize {
     child_pid as isize
 }
 
-pub fn sys_exec(path: *const u8) -> isize {
-    let token = processor::current_user_token();
-    let path = page_table::translated_str(token, path);
-    if let Some(data) = loader::get_app_data_by_name(path.as_str()) {
-        processor::current_task().unwrap().exec(data);
-        return 0;
+// read_user_str_array 读取用户地址空间中以空指针结尾的字符串指针数组，ptr 为空指针时返回空数组。
+// total 是 argv 和 envp 已经占用的空间，两者加起来不能超过 ARG_MAX。
+fn read_user_str_array(ptr: usize, total: &mut usize) -> Result<Vec<String>, isize> {
+    let mut strs = Vec::new();
+    if ptr == 0 {
+        return Ok(strs);
+    }
+    loop {
+        let str_ptr = UserPtr::<usize>::new(ptr + strs.len() * core::mem::size_of::<usize>());
+        let str_ptr = user_access(|memory_set| str_ptr.read(memory_set))?;
+        if str_ptr == 0 {
+            return Ok(strs);
+        }
+        let s = user_access(|memory_set| user_ptr::read_user_str(memory_set, str_ptr))?;
+        *total += s.len() + 1 + core::mem::size_of::<usize>();
+        if *total > ARG_MAX {
+            return Err(E2BIG);
+        }
+        strs.push(s);
+    }
+}
+
+// sys_exec 执行 path 对应的程序，args 和 envp 分别是以空指针结尾的参数和环境变量
+// 字符串指针数组，都可以为空指针
+pub fn sys_exec(path: *const u8, args: *const usize, envp: *const usize) -> isize {
+    let path = match user_access(|memory_set| user_ptr::read_user_str(memory_set, path as usize)) {
+        Ok(path) => path,
+        Err(errno) => return -errno,
+    };
+    let mut total = 0;
+    let argv = match read_user_str_array(args as usize, &mut total) {
+        Ok(argv) => argv,
+        Err(errno) => return -errno,
+    };
+    let envp = match read_user_str_array(envp as usize, &mut total) {
+        Ok(envp) => envp,
+        Err(errno) => return -errno,
+    };
+    let task = processor::current_task().unwrap();
+    if let Some(file) = AppFile::open(path.as_str()) {
+        return match task.exec(&file, argv, envp) {
+            Ok(()) => 0,
+            Err(ElfLoadError::OutOfMemory) => -ENOMEM,
+            Err(ElfLoadError::ArgTooLong) => -E2BIG,
+            Err(err) => {
+                println!("[kernel] Failed to exec {}: {}", path, err);
+                -ENOEXEC
+            }
+        };
     }
     -1
 }
 
+// sys_personality 返回当前的执行域。地址空间随机化由启动参数 norandmaps 统一控制，
+// 所以目前只支持查询 (或者设置为当前的值)，其他设置返回 -EINVAL。
+pub fn sys_personality(persona: usize) -> isize {
+    let current = if mm::aslr_enabled() {
+        0
+    } else {
+        ADDR_NO_RANDOMIZE
+    };
+    if persona != PERSONALITY_QUERY && persona != current {
+        return -EINVAL;
+    }
+    current as isize
+}
+
 // 返回数据有三种类型：
 // 1. 当关心的子进程处于 Zombie 状态时，返回该进程的 pid (pid >= 0)；
 // 2. 当关心的子进程都已经退出时，返回 NO_CHILDREN_RUNNING；
 // 3. 当关心的子进程还没有退出时，返回 CHILDREN_RUNNING。
 pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut i32) -> isize {
     let current_task = processor::current_task().unwrap();
-    let mut current_task_inner = current_task.inner_exclusive_access();
+    let current_task_inner = current_task.inner_exclusive_access();
 
     if current_task_inner
         .children
@@ -78,16 +155,23 @@ pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut i32) -> isize {
             child.inner_exclusive_access().is_zombie()
                 && (pid == ANY_PROCESS || (pid as usize) == child.getpid())
         });
-    if let Some((idx, _)) = pair {
-        let child = current_task_inner.children.remove(idx);
-        // 确保子进程的强引用在 child 被释放时资源也可以被释放
-        assert_eq!(Arc::strong_count(&child), 1);
+    if let Some((idx, child)) = pair {
         let child_pid = child.getpid();
         let exit_code = child.inner_exclusive_access().exit_code;
-        *(page_table::translated_ref_mut(
-            current_task_inner.get_user_token(),
-            exit_code_ptr,
-        )) = exit_code;
+        drop(current_task_inner);
+        // 先写回退出码再回收子进程，这样地址非法时子进程仍然可以被再次等待
+        let exit_code_ptr = UserPtr::<i32>::new(exit_code_ptr as usize);
+        if !exit_code_ptr.is_null() {
+            if let Err(errno) = user_access(|memory_set| exit_code_ptr.write(memory_set, exit_code))
+            {
+                return -errno;
+            }
+        }
+        // 写回退出码时可能有进程被 OOM killer 杀死，它的子进程会被加入 initproc 的
+        // children 末尾，所以 idx 仍然指向同一个子进程
+        let child = current_task.inner_exclusive_access().children.remove(idx);
+        // 确保子进程的强引用在 child 被释放时资源也可以被释放
+        assert_eq!(Arc::strong_count(&child), 1);
         return child_pid as isize;
     }
 
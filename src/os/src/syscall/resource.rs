@@ -0,0 +1,74 @@
+use crate::{
+    mm::user_ptr::UserPtr,
+    task::{
+        self, processor,
+        rlimit::{RLimit, RLIM_NLIMITS},
+    },
+};
+
+use super::{
+    errno::{EINVAL, EPERM, ESRCH},
+    user_access,
+};
+
+pub fn sys_getrlimit(resource: usize, rlim: *mut RLimit) -> isize {
+    sys_prlimit(0, resource, core::ptr::null(), rlim)
+}
+
+pub fn sys_setrlimit(resource: usize, rlim: *const RLimit) -> isize {
+    sys_prlimit(0, resource, rlim, core::ptr::null_mut())
+}
+
+// sys_prlimit 读取并修改 pid 对应进程的资源限制，pid 为 0 表示当前进程。
+// new_limit 不为空时设置新的限制，old_limit 不为空时写回原来的限制。
+// 目前没有特权进程，所以任何进程都只能降低硬限制，软限制不能超过硬限制。
+pub fn sys_prlimit(
+    pid: usize,
+    resource: usize,
+    new_limit: *const RLimit,
+    old_limit: *mut RLimit,
+) -> isize {
+    if resource >= RLIM_NLIMITS {
+        return -EINVAL;
+    }
+    let current_task = processor::current_task().unwrap();
+    let task = if pid == 0 || pid == current_task.getpid() {
+        current_task.clone()
+    } else {
+        match task::find_task(pid) {
+            Some(task) => task,
+            None => return -ESRCH,
+        }
+    };
+    let new_limit = if new_limit.is_null() {
+        None
+    } else {
+        let new_limit = UserPtr::<RLimit>::new(new_limit as usize);
+        match user_access(|memory_set| new_limit.read(memory_set)) {
+            Ok(limit) => Some(limit),
+            Err(errno) => return -errno,
+        }
+    };
+
+    let mut task_inner = task.inner_exclusive_access();
+    let limit = task_inner.rlimits.get(resource);
+    if let Some(new_limit) = new_limit {
+        if new_limit.cur > new_limit.max {
+            return -EINVAL;
+        }
+        if new_limit.max > limit.max {
+            return -EPERM;
+        }
+        task_inner.set_rlimit(resource, new_limit);
+    }
+    drop(task_inner);
+
+    let old_limit = UserPtr::<RLimit>::new(old_limit as usize);
+    if old_limit.is_null() {
+        return 0;
+    }
+    match user_access(|memory_set| old_limit.write(memory_set, limit)) {
+        Ok(()) => 0,
+        Err(errno) => -errno,
+    }
+}
@@ -0,0 +1,89 @@
+// 按照 System V ABI 构造用户程序的初始栈，从栈顶 (高地址) 到 sp 依次是：
+// 参数和环境变量字符串、auxv、envp、argv 以及 argc。
+//
+//   sp -> argc
+//         argv[0] ... argv[argc - 1], NULL
+//         envp[0] ... NULL
+//         auxv: (AT_*, value) ... (AT_NULL, 0)
+//         padding
+//         字符串
+use alloc::{string::String, vec::Vec};
+
+use crate::{
+    config::PAGE_SIZE,
+    mm::{
+        memory_set::{ElfInfo, MemorySet, PageFaultError},
+        user_ptr,
+    },
+};
+
+// auxv 的类型，Ref: https://man7.org/linux/man-pages/man3/getauxval.3.html
+const AT_NULL: usize = 0;
+const AT_PHDR: usize = 3;
+const AT_PHENT: usize = 4;
+const AT_PHNUM: usize = 5;
+const AT_PAGESZ: usize = 6;
+const AT_BASE: usize = 7;
+const AT_ENTRY: usize = 9;
+
+// RISC-V 要求 sp 按照 16 字节对齐
+const STACK_ALIGN: usize = 16;
+
+// push_str 将一个以 '\0' 结尾的字符串压入用户栈，返回字符串的地址
+fn push_str(memory_set: &mut MemorySet, sp: &mut usize, s: &str) -> Result<usize, PageFaultError> {
+    *sp -= s.len() + 1;
+    user_ptr::copy_to_user(memory_set, *sp, s.as_bytes())?;
+    user_ptr::copy_to_user(memory_set, *sp + s.len(), &[0])?;
+    Ok(*sp)
+}
+
+// init_user_stack 从 user_sp 开始构造初始栈，返回新的 sp 以及 argv 的地址。
+// 用户栈空间不足时返回 PageFaultError::InvalidAccess，没有空闲页框时返回
+// PageFaultError::OutOfMemory。
+pub fn init_user_stack(
+    memory_set: &mut MemorySet,
+    user_sp: usize,
+    argv: &[String],
+    envp: &[String],
+    elf_info: &ElfInfo,
+) -> Result<(usize, usize), PageFaultError> {
+    let mut sp = user_sp;
+    let argv_ptrs = argv
+        .iter()
+        .map(|arg| push_str(memory_set, &mut sp, arg))
+        .collect::<Result<Vec<usize>, _>>()?;
+    let envp_ptrs = envp
+        .iter()
+        .map(|env| push_str(memory_set, &mut sp, env))
+        .collect::<Result<Vec<usize>, _>>()?;
+
+    let mut auxv = vec![
+        (AT_PAGESZ, PAGE_SIZE),
+        (AT_PHENT, elf_info.phent),
+        (AT_PHNUM, elf_info.phnum),
+        (AT_ENTRY, elf_info.entry_point),
+    ];
+    if elf_info.phdr != 0 {
+        auxv.push((AT_PHDR, elf_info.phdr));
+    }
+    if elf_info.interp_base != 0 {
+        auxv.push((AT_BASE, elf_info.interp_base));
+    }
+    auxv.push((AT_NULL, 0));
+
+    let mut words: Vec<usize> = Vec::new();
+    words.push(argv_ptrs.len());
+    words.extend(argv_ptrs.iter());
+    words.push(0);
+    words.extend(envp_ptrs.iter());
+    words.push(0);
+    for (key, value) in auxv {
+        words.push(key);
+        words.push(value);
+    }
+
+    sp = (sp - words.len() * core::mem::size_of::<usize>()) & !(STACK_ALIGN - 1);
+    let bytes: Vec<u8> = words.iter().flat_map(|word| word.to_ne_bytes()).collect();
+    user_ptr::copy_to_user(memory_set, sp, &bytes)?;
+    Ok((sp, sp + core::mem::size_of::<usize>()))
+}
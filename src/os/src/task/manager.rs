@@ -3,12 +3,15 @@ use lazy_static::*;
 
 use crate::sync::UPSafeCell;
 
-use super::task::TaskControlBlock;
+use super::{
+    task::{TaskAlloc, TaskControlBlock},
+    INITPROC,
+};
 
 // TaskManager 管理全局需要执行的进程 (TaskControlBlock)，
 // 需要和 Processor 相互配合。
 pub struct TaskManager {
-    ready_queue: VecDeque<Arc<TaskControlBlock>>,
+    ready_queue: VecDeque<Arc<TaskControlBlock, TaskAlloc>>,
 }
 
 impl TaskManager {
@@ -18,13 +21,17 @@ impl TaskManager {
         }
     }
 
-    pub fn add(&mut self, task: Arc<TaskControlBlock>) {
+    pub fn add(&mut self, task: Arc<TaskControlBlock, TaskAlloc>) {
         self.ready_queue.push_back(task)
     }
 
-    pub fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
+    pub fn fetch(&mut self) -> Option<Arc<TaskControlBlock, TaskAlloc>> {
         self.ready_queue.pop_front()
     }
+
+    pub fn remove(&mut self, task: &Arc<TaskControlBlock, TaskAlloc>) {
+        self.ready_queue.retain(|t| !Arc::ptr_eq(t, task));
+    }
 }
 
 lazy_static! {
@@ -33,10 +40,39 @@ lazy_static! {
 }
 
 // 添加一个任务
-pub fn add_task(task: Arc<TaskControlBlock>) {
+pub fn add_task(task: Arc<TaskControlBlock, TaskAlloc>) {
     TASK_MANAGER.exclusive_access().add(task);
 }
 
-pub fn fetch_task() -> Option<Arc<TaskControlBlock>> {
+pub fn fetch_task() -> Option<Arc<TaskControlBlock, TaskAlloc>> {
     TASK_MANAGER.exclusive_access().fetch()
 }
+
+// remove_task 将 task 从就绪队列中移除
+pub fn remove_task(task: &Arc<TaskControlBlock, TaskAlloc>) {
+    TASK_MANAGER.exclusive_access().remove(task);
+}
+
+// max_rss_task 返回就绪队列中 RSS 最大的进程 (initproc 除外) 以及它的 RSS
+pub fn max_rss_task() -> Option<(Arc<TaskControlBlock, TaskAlloc>, usize)> {
+    TASK_MANAGER
+        .exclusive_access()
+        .ready_queue
+        .iter()
+        .filter(|task| !Arc::ptr_eq(task, &INITPROC))
+        .map(|task| {
+            let rss = task.inner_exclusive_access().memory_set.stat().rss;
+            (task.clone(), rss)
+        })
+        .max_by_key(|(_, rss)| *rss)
+}
+
+// swap_out_from_ready_tasks 从就绪队列中的进程换出一个页，返回 false 表示没有可以换出的页。
+// 就绪队列中的进程没有在运行，所以可以安全地访问它们的地址空间。
+pub fn swap_out_from_ready_tasks() -> bool {
+    TASK_MANAGER
+        .exclusive_access()
+        .ready_queue
+        .iter()
+        .any(|task| task.inner_exclusive_access().memory_set.swap_out_one())
+}
@@ -1,26 +1,38 @@
 mod context;
+mod init_stack;
 pub mod manager;
 mod pid;
 pub mod processor;
+pub mod rlimit;
 mod switch;
 mod task;
 
-use alloc::sync::Arc;
+use alloc::{sync::Arc, vec};
 use lazy_static::*;
 
-use crate::{loader, task::task::TaskControlBlock};
+use crate::{fs::AppFile, mm::TASK_CACHE, timer};
 
-pub use {context::TaskContext, processor::run_tasks};
+pub use {
+    context::TaskContext,
+    processor::run_tasks,
+    task::{ForkError, TaskAlloc, TaskControlBlock},
+};
 
-use self::{task::TaskStatus};
+use self::{rlimit::RLIMIT_CPU, task::TaskStatus};
 
 const INITPROC_NAME: &str = "initproc";
 
+// 被 OOM killer 杀死的进程的退出码
+pub const OOM_KILLED: i32 = -4;
+// CPU 时间超过 RLIMIT_CPU 的进程的退出码
+pub const CPU_LIMIT_EXCEEDED: i32 = -5;
+
 lazy_static! {
-    pub static ref INITPROC: Arc<TaskControlBlock> = {
-        Arc::new(TaskControlBlock::new(
-            loader::get_app_data_by_name(INITPROC_NAME).unwrap(),
-        ))
+    pub static ref INITPROC: Arc<TaskControlBlock, TaskAlloc> = {
+        Arc::new_in(
+            TaskControlBlock::new(&AppFile::open(INITPROC_NAME).unwrap()),
+            &*TASK_CACHE,
+        )
     };
 }
 
@@ -40,21 +52,53 @@ pub fn suspend_current_and_run_next() {
     processor::schedule(current_task_cx_ptr);
 }
 
-pub fn exit_current_and_run_next(exit_code: i32) {
-    let current_task = processor::take_current_task().unwrap();
+// charge_current_tick 在时钟中断时将一个时钟周期的 CPU 时间记到当前进程上，
+// 返回 false 表示当前进程的 CPU 时间已经达到 RLIMIT_CPU，调用者负责结束当前进程
+pub fn charge_current_tick() -> bool {
+    let current_task = processor::current_task().unwrap();
     let mut current_task_inner = current_task.inner_exclusive_access();
-    current_task_inner.task_status = TaskStatus::Zombie;
-    current_task_inner.exit_code = exit_code;
+    current_task_inner.cpu_time += timer::MSEC_PER_TICK;
+    let limit = current_task_inner.rlimits.get(RLIMIT_CPU).cur;
+    // 软限制为 RLIM_INFINITY 时乘法饱和，不会超过限制
+    current_task_inner.cpu_time < limit.saturating_mul(timer::MSEC_PER_SEC)
+}
+
+// find_task 在以 initproc 为根的进程树中查找 pid 对应的进程，已经退出的进程不会被找到
+pub fn find_task(pid: usize) -> Option<Arc<TaskControlBlock, TaskAlloc>> {
+    let mut tasks = vec![INITPROC.clone()];
+    while let Some(task) = tasks.pop() {
+        let task_inner = task.inner_exclusive_access();
+        if task.getpid() == pid {
+            if task_inner.is_zombie() {
+                return None;
+            }
+            drop(task_inner);
+            return Some(task);
+        }
+        tasks.extend(task_inner.children.iter().cloned());
+    }
+    None
+}
+
+// exit_task 将 task 标记为 Zombie 并释放它的地址空间，子进程交给 initproc 管理，
+// kernel stack 等资源在父进程 waitpid 时释放
+fn exit_task(task: &Arc<TaskControlBlock, TaskAlloc>, exit_code: i32) {
+    let mut task_inner = task.inner_exclusive_access();
+    task_inner.task_status = TaskStatus::Zombie;
+    task_inner.exit_code = exit_code;
     let mut initproc_inner = INITPROC.inner_exclusive_access();
-    for child in current_task_inner.children.iter() {
+    for child in task_inner.children.iter() {
         child.inner_exclusive_access().parent = Some(Arc::downgrade(&INITPROC));
         initproc_inner.children.push(child.clone());
     }
-    current_task_inner.children.clear();
-    current_task_inner.memory_set.release_areas();
-    
-    drop(initproc_inner);
-    drop(current_task_inner);
+    task_inner.children.clear();
+    task_inner.fd_table.clear();
+    task_inner.memory_set.release_areas();
+}
+
+pub fn exit_current_and_run_next(exit_code: i32) {
+    let current_task = processor::take_current_task().unwrap();
+    exit_task(&current_task, exit_code);
     drop(current_task);
 
     // 这里我有个疑问：`_unused` 何时被释放？
@@ -65,3 +109,25 @@ pub fn exit_current_and_run_next(exit_code: i32) {
     let mut _unused = TaskContext::zero_init();
     processor::schedule((&mut _unused) as *mut TaskContext)
 }
+
+// oom_kill 在处理 page fault 时页框耗尽调用：从当前进程和就绪队列中的进程里选择 RSS
+// 最大的进程 (initproc 除外) 杀死并释放它的内存。返回 false 表示被选中的是当前进程，
+// 或者没有其他可以杀死的进程，由调用者负责退出当前进程。
+pub fn oom_kill() -> bool {
+    let current_task = processor::current_task().unwrap();
+    let current_rss = current_task.inner_exclusive_access().memory_set.stat().rss;
+    // initproc 不能被杀死，它只能杀死其他进程
+    let is_initproc = Arc::ptr_eq(&current_task, &INITPROC);
+    let victim = match manager::max_rss_task() {
+        Some((victim, rss)) if rss > current_rss || is_initproc => victim,
+        _ => return false,
+    };
+    println!(
+        "[kernel] Out of memory: killed process {} (rss {} pages)",
+        victim.getpid(),
+        victim.inner_exclusive_access().memory_set.stat().rss
+    );
+    manager::remove_task(&victim);
+    exit_task(&victim, OOM_KILLED);
+    true
+}
@@ -31,6 +31,11 @@ impl PidAllocator {
         }
     }
 
+    // count 返回已经分配出去的 pid 的个数，也就是进程的个数
+    pub fn count(&self) -> usize {
+        self.current - self.recycled.len()
+    }
+
     pub fn dealloc(&mut self, pid: usize) {
         assert!(pid < self.current);
         assert!(
@@ -53,9 +58,13 @@ impl Drop for PidHandle {
     }
 }
 
-// 申请一个 pid 并返回 PidHandle
-pub fn pid_alloc() -> PidHandle {
-    PID_ALLOCATOR.exclusive_access().alloc()
+// 申请一个 pid 并返回 PidHandle，进程的个数已经达到 nproc_limit (RLIMIT_NPROC) 时返回 None
+pub fn pid_alloc(nproc_limit: usize) -> Option<PidHandle> {
+    let mut allocator = PID_ALLOCATOR.exclusive_access();
+    if allocator.count() >= nproc_limit {
+        return None;
+    }
+    Some(allocator.alloc())
 }
 
 pub struct KernelStack {
@@ -63,15 +72,18 @@ pub struct KernelStack {
 }
 
 impl KernelStack {
-    pub fn new(pid_handle: &PidHandle) -> Self {
+    // new 为 pid 分配 kernel stack，没有空闲页框时返回 None
+    pub fn new(pid_handle: &PidHandle) -> Option<Self> {
         let pid = pid_handle.0;
         let (bottom, top) = kernel_stack_position(pid);
-        KERNEL_SPACE.exclusive_access().insert_framed_area(
+        let mut kernel_space = KERNEL_SPACE.exclusive_access();
+        kernel_space.insert_framed_area(
             VirtAddr::from(bottom),
             VirtAddr::from(top),
             MapPermission::R | MapPermission::W,
-        );
-        KernelStack { pid: pid }
+        )?;
+        kernel_space.flush_tlb();
+        Some(KernelStack { pid: pid })
     }
 
     #[allow(unused)]
@@ -97,15 +109,15 @@ impl Drop for KernelStack {
     // kernel stack 被释放的时候，其占用的物理内存 frames 被释放
     fn drop(&mut self) {
         let (bottom, _) = kernel_stack_position(self.pid);
-        KERNEL_SPACE
-            .exclusive_access()
-            .remove_area_with_start_vpn(VirtAddr::from(bottom).into())
+        let mut kernel_space = KERNEL_SPACE.exclusive_access();
+        kernel_space.remove_area_with_start_vpn(VirtAddr::from(bottom).into());
+        kernel_space.flush_tlb();
     }
 }
 
 // 返回 kernel stack 的 bottom 和 top 地址
 pub fn kernel_stack_position(pid: usize) -> (usize, usize) {
-    let top = config::TRAMPOLINE - pid * (config::KERNEL_STACK_SIZE + config::PAGE_SIZE);
+    let top = config::trampoline() - pid * (config::KERNEL_STACK_SIZE + config::PAGE_SIZE);
     let bottom = top - config::KERNEL_STACK_SIZE;
     (bottom, top)
 }
@@ -6,7 +6,7 @@ use super::{
     context::TaskContext,
     manager,
     switch::__switch,
-    task::{TaskControlBlock, TaskStatus},
+    task::{TaskAlloc, TaskControlBlock, TaskStatus},
 };
 
 use lazy_static::*;
@@ -14,7 +14,7 @@ use lazy_static::*;
 // Processor 负责实际管理核心进行运行情况，比如可以执行
 // 新进程时，就从 TaskManager 中 fetch 一个进程执行。
 pub struct Processor {
-    current: Option<Arc<TaskControlBlock>>,
+    current: Option<Arc<TaskControlBlock, TaskAlloc>>,
     idle_task_cx: TaskContext,
 }
 
@@ -27,12 +27,12 @@ impl Processor {
     }
 
     // 取出正在执行的任务的 TCB，此时 self.current 为 None
-    fn take_current(&mut self) -> Option<Arc<TaskControlBlock>> {
+    fn take_current(&mut self) -> Option<Arc<TaskControlBlock, TaskAlloc>> {
         self.current.take()
     }
 
     // 复制正在执行任务的 TCB，以克隆的方式传递，不会导致正在执行的 TCB 终止
-    fn current(&self) -> Option<Arc<TaskControlBlock>> {
+    fn current(&self) -> Option<Arc<TaskControlBlock, TaskAlloc>> {
         self.current.as_ref().map(|ptr| Arc::clone(ptr))
     }
 
@@ -45,11 +45,11 @@ lazy_static! {
     pub static ref PROCESSOR: UPSafeCell<Processor> = unsafe { UPSafeCell::new(Processor::new()) };
 }
 
-pub fn take_current_task() -> Option<Arc<TaskControlBlock>> {
+pub fn take_current_task() -> Option<Arc<TaskControlBlock, TaskAlloc>> {
     PROCESSOR.exclusive_access().take_current()
 }
 
-pub fn current_task() -> Option<Arc<TaskControlBlock>> {
+pub fn current_task() -> Option<Arc<TaskControlBlock, TaskAlloc>> {
     PROCESSOR.exclusive_access().current()
 }
 
@@ -0,0 +1,65 @@
+// 进程的资源限制，与 Linux 的 getrlimit/setrlimit 相同，每种资源有一个软限制和一个硬限制，
+// 内核按照软限制检查，进程可以在硬限制以内调整软限制。资源限制在 fork 时被子进程继承，
+// exec 之后保持不变。
+// Ref: https://man7.org/linux/man-pages/man2/getrlimit.2.html
+use crate::config;
+
+// CPU 时间，单位是秒
+pub const RLIMIT_CPU: usize = 0;
+// user stack 的最大长度，单位是字节
+pub const RLIMIT_STACK: usize = 3;
+// 进程的个数 (包括还没有被回收的僵尸进程)
+pub const RLIMIT_NPROC: usize = 6;
+// 文件描述符的个数
+pub const RLIMIT_NOFILE: usize = 7;
+// 地址空间的大小，单位是字节
+pub const RLIMIT_AS: usize = 9;
+pub const RLIM_NLIMITS: usize = 16;
+
+pub const RLIM_INFINITY: usize = usize::MAX;
+
+const NOFILE_CUR: usize = 1024;
+const NOFILE_MAX: usize = 4096;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct RLimit {
+    pub cur: usize,
+    pub max: usize,
+}
+
+impl RLimit {
+    const fn infinity() -> Self {
+        Self {
+            cur: RLIM_INFINITY,
+            max: RLIM_INFINITY,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct ResourceLimits {
+    limits: [RLimit; RLIM_NLIMITS],
+}
+
+impl ResourceLimits {
+    // new 返回 initproc 使用的默认资源限制
+    pub fn new() -> Self {
+        let mut limits = [RLimit::infinity(); RLIM_NLIMITS];
+        limits[RLIMIT_STACK].cur = config::USER_STACK_LIMIT;
+        limits[RLIMIT_NOFILE] = RLimit {
+            cur: NOFILE_CUR,
+            max: NOFILE_MAX,
+        };
+        Self { limits }
+    }
+
+    // get 返回 resource 的限制，调用者需要保证 resource < RLIM_NLIMITS
+    pub fn get(&self, resource: usize) -> RLimit {
+        self.limits[resource]
+    }
+
+    pub fn set(&mut self, resource: usize, limit: RLimit) {
+        self.limits[resource] = limit;
+    }
+}
@@ -1,27 +1,41 @@
 use core::cell::RefMut;
 
 use alloc::{
+    string::String,
     sync::{Arc, Weak},
     vec::Vec,
 };
 
 use super::{
+    init_stack::init_user_stack,
     pid::{self, KernelStack, PidHandle},
+    rlimit::{
+        ResourceLimits, RLimit, RLIMIT_AS, RLIMIT_NOFILE, RLIMIT_NPROC, RLIMIT_STACK,
+        RLIM_INFINITY,
+    },
     TaskContext,
 };
 
 use crate::{
     config,
+    fs::{File, OpenFile},
     mm::{
         self,
         address::{PhysPageNum, VirtAddr},
-        memory_set::MemorySet,
-        KERNEL_SPACE,
+        memory_set::{ElfLoadError, MemorySet, PageFaultError},
+        SlabCache, KERNEL_SPACE, TASK_CACHE,
     },
     sync::UPSafeCell,
     trap::{self, trap_handler, TrapContext},
 };
 
+// 标准输入输出占用的文件描述符个数
+const FD_RESERVED: usize = 3;
+
+// TaskControlBlock 从专用的 slab 缓存 TASK_CACHE 中分配，所以指向它的 Arc 和 Weak 都带有
+// 这个分配器
+pub type TaskAlloc = &'static SlabCache<TaskControlBlock>;
+
 pub struct TaskControlBlock {
     // immutable
     pub pid: PidHandle,
@@ -32,15 +46,26 @@ pub struct TaskControlBlock {
 
 pub struct TaskControlBlockInner {
     pub trap_cx_ppn: PhysPageNum,
+    // base_size 是程序镜像的大小，也就是 heap 的起始地址
     pub base_size: usize,
+    // program_brk 是 heap 当前的结束地址
+    pub program_brk: usize,
     pub task_status: TaskStatus,
     pub task_cx: TaskContext,
     pub memory_set: MemorySet,
 
-    pub parent: Option<Weak<TaskControlBlock>>,
-    pub children: Vec<Arc<TaskControlBlock>>,
+    pub parent: Option<Weak<TaskControlBlock, TaskAlloc>>,
+    pub children: Vec<Arc<TaskControlBlock, TaskAlloc>>,
 
     pub exit_code: i32,
+
+    pub rlimits: ResourceLimits,
+    // cpu_time 是进程在用户态运行的时间 (毫秒)，在时钟中断时累加
+    pub cpu_time: usize,
+
+    // fd_table 的下标就是文件描述符，0、1、2 是标准输入输出，由 sys_read/sys_write
+    // 直接处理，在 fd_table 中总是为 None
+    pub fd_table: Vec<Option<Arc<OpenFile>>>,
 }
 
 impl TaskControlBlockInner {
@@ -56,6 +81,56 @@ impl TaskControlBlockInner {
     pub fn is_zombie(&self) -> bool {
         self.get_status() == TaskStatus::Zombie
     }
+
+    // change_program_brk 将 heap 的结束地址调整为 new_brk，heap 对应的逻辑段按页
+    // 增长或者收缩，返回 false 表示调整失败。
+    pub fn change_program_brk(&mut self, new_brk: usize) -> bool {
+        if new_brk < self.base_size || new_brk > config::user_space_end() {
+            return false;
+        }
+        let heap_start = VirtAddr::from(self.base_size).floor();
+        let new_end = VirtAddr::from(new_brk).ceil();
+        if !self.memory_set.resize_area(heap_start, new_end) {
+            return false;
+        }
+        self.program_brk = new_brk;
+        true
+    }
+
+    // alloc_fd 为 file 分配最小的空闲文件描述符，文件描述符不能超过 RLIMIT_NOFILE
+    pub fn alloc_fd(&mut self, file: Arc<dyn File>) -> Option<usize> {
+        let fd = (FD_RESERVED..self.fd_table.len())
+            .find(|&fd| self.fd_table[fd].is_none())
+            .unwrap_or(self.fd_table.len().max(FD_RESERVED));
+        if fd >= self.rlimits.get(RLIMIT_NOFILE).cur {
+            return None;
+        }
+        if fd >= self.fd_table.len() {
+            self.fd_table.resize(fd + 1, None);
+        }
+        self.fd_table[fd] = Some(Arc::new(OpenFile::new(file)));
+        Some(fd)
+    }
+
+    // get_file 返回 fd 对应的文件
+    pub fn get_file(&self, fd: usize) -> Option<Arc<dyn File>> {
+        Some(self.get_open_file(fd)?.file.clone())
+    }
+
+    // get_open_file 返回 fd 对应的 OpenFile，read/write 通过它读写文件位置
+    pub fn get_open_file(&self, fd: usize) -> Option<Arc<OpenFile>> {
+        self.fd_table.get(fd)?.clone()
+    }
+
+    // set_rlimit 修改资源限制，地址空间相关的限制同时交给 memory_set 检查
+    pub fn set_rlimit(&mut self, resource: usize, limit: RLimit) {
+        self.rlimits.set(resource, limit);
+        match resource {
+            RLIMIT_STACK => self.memory_set.set_stack_limit(limit.cur),
+            RLIMIT_AS => self.memory_set.set_as_limit(limit.cur),
+            _ => {}
+        }
+    }
 }
 
 impl TaskControlBlock {
@@ -64,16 +139,19 @@ impl TaskControlBlock {
     }
 
     // new 读取用户 elf 程序，创建用户空间同时初始化 kernel stack
-    pub fn new(elf_data: &[u8]) -> Self {
-        let (memory_set, user_sp, entry_point) = MemorySet::from_elf(elf_data);
+    pub fn new(elf_file: &Arc<dyn File>) -> Self {
+        let (mut memory_set, user_sp, heap_bottom, elf_info) =
+            MemorySet::from_elf(elf_file).unwrap();
+        let (user_sp, argv_base) =
+            init_user_stack(&mut memory_set, user_sp, &[], &[], &elf_info).unwrap();
         let trap_cx_ppn = memory_set
-            .translate(VirtAddr::from(config::TRAP_CONTEXT).into())
+            .translate(VirtAddr::from(config::trap_context()).into())
             .unwrap()
             .ppn();
         let task_status = TaskStatus::Ready;
 
-        let pid_handle = pid::pid_alloc();
-        let kernel_stack = pid::KernelStack::new(&pid_handle);
+        let pid_handle = pid::pid_alloc(RLIM_INFINITY).unwrap();
+        let kernel_stack = pid::KernelStack::new(&pid_handle).unwrap();
         let kernel_stack_top = kernel_stack.get_top();
         let task_cx_block_inner = unsafe {
             UPSafeCell::new(TaskControlBlockInner {
@@ -81,10 +159,14 @@ impl TaskControlBlock {
                 task_cx: TaskContext::goto_trap_return(kernel_stack_top),
                 memory_set,
                 trap_cx_ppn,
-                base_size: user_sp,
+                base_size: heap_bottom,
+                program_brk: heap_bottom,
                 parent: None,
                 children: Vec::new(),
                 exit_code: 0,
+                rlimits: ResourceLimits::new(),
+                cpu_time: 0,
+                fd_table: Vec::new(),
             })
         };
 
@@ -97,12 +179,16 @@ impl TaskControlBlock {
         // init trap context
         let trap_cx = task_control_block.inner_exclusive_access().get_trap_cx();
         *trap_cx = TrapContext::app_init_context(
-            entry_point,
+            elf_info.start_point(),
             user_sp,
             mm::KERNEL_SPACE.exclusive_access().token(),
             kernel_stack_top,
             trap_handler as usize,
         );
+        trap_cx.x[10] = 0;
+        trap_cx.x[11] = argv_base;
+        // tp 指向初始线程的 TLS 块
+        trap_cx.x[4] = elf_info.tls_pointer;
 
         task_control_block
     }
@@ -111,65 +197,113 @@ impl TaskControlBlock {
         self.pid.0
     }
 
-    pub fn fork(self: &Arc<TaskControlBlock>) -> Arc<TaskControlBlock> {
-        let mut parent_inner = self.inner_exclusive_access();
+    // fork 创建一个当前进程的子进程，子进程继承父进程的资源限制。进程的个数达到
+    // RLIMIT_NPROC 或者没有空闲页框创建 kernel stack 和地址空间时返回错误
+    pub fn fork(
+        parent: &Arc<TaskControlBlock, TaskAlloc>,
+    ) -> Result<Arc<TaskControlBlock, TaskAlloc>, ForkError> {
+        let mut parent_inner = parent.inner_exclusive_access();
 
-        let pid_handle = pid::pid_alloc();
-        let kernel_stack = pid::KernelStack::new(&pid_handle);
+        let pid_handle = pid::pid_alloc(parent_inner.rlimits.get(RLIMIT_NPROC).cur)
+            .ok_or(ForkError::ProcessLimit)?;
+        let kernel_stack =
+            pid::KernelStack::new(&pid_handle).ok_or(ForkError::OutOfMemory)?;
         let kernel_stack_top = kernel_stack.get_top();
 
         // tcb inner
-        let memory_set = MemorySet::from_existed_user(&parent_inner.memory_set);
+        let memory_set = MemorySet::from_existed_user(&mut parent_inner.memory_set)
+            .ok_or(ForkError::OutOfMemory)?;
         let trap_cx_ppn = memory_set
-            .translate(VirtAddr::from(config::TRAP_CONTEXT).into())
+            .translate(VirtAddr::from(config::trap_context()).into())
             .unwrap()
             .ppn();
         let tcb_inner = TaskControlBlockInner {
             trap_cx_ppn,
             base_size: parent_inner.base_size,
+            program_brk: parent_inner.program_brk,
             task_status: TaskStatus::Ready,
             task_cx: TaskContext::goto_trap_return(kernel_stack_top),
             memory_set,
-            parent: Some(Arc::downgrade(self)),
+            parent: Some(Arc::downgrade(parent)),
             children: Vec::new(),
             exit_code: 0,
+            rlimits: parent_inner.rlimits,
+            cpu_time: 0,
+            fd_table: parent_inner.fd_table.clone(),
         };
 
-        let tcb = Arc::new(TaskControlBlock {
-            pid: pid_handle,
-            kernel_stack,
-            inner: unsafe { UPSafeCell::new(tcb_inner) },
-        });
+        let tcb = Arc::new_in(
+            TaskControlBlock {
+                pid: pid_handle,
+                kernel_stack,
+                inner: unsafe { UPSafeCell::new(tcb_inner) },
+            },
+            &*TASK_CACHE,
+        );
 
         parent_inner.children.push(tcb.clone());
 
         let mut trap_cx = tcb.inner_exclusive_access().get_trap_cx();
         trap_cx.kernel_sp = kernel_stack_top;
 
-        tcb
+        Ok(tcb)
     }
 
-    pub fn exec(&self, elf_data: &[u8]) {
-        let (mmset, user_sp, entrypoint) = MemorySet::from_elf(elf_data);
+    // exec 使用 elf_file 替换当前进程的地址空间，elf 文件不合法、没有空闲页框或者新的
+    // 地址空间超过 RLIMIT_AS 时保持原来的地址空间不变并返回错误原因。
+    // argv 和 envp 会被拷贝到新的用户栈上，a0 和 a1 分别是 argc 和 argv 的地址。
+    pub fn exec(
+        &self,
+        elf_file: &Arc<dyn File>,
+        argv: Vec<String>,
+        envp: Vec<String>,
+    ) -> Result<(), ElfLoadError> {
+        let (mut mmset, user_sp, heap_bottom, elf_info) = MemorySet::from_elf(elf_file)?;
+        let rlimits = self.inner_exclusive_access().rlimits;
+        mmset.set_stack_limit(rlimits.get(RLIMIT_STACK).cur);
+        mmset.set_as_limit(rlimits.get(RLIMIT_AS).cur);
+        if !mmset.within_as_limit(0) {
+            return Err(ElfLoadError::OutOfMemory);
+        }
+        let (user_sp, argv_base) = init_user_stack(&mut mmset, user_sp, &argv, &envp, &elf_info)
+            .map_err(|err| match err {
+                PageFaultError::InvalidAccess => ElfLoadError::ArgTooLong,
+                PageFaultError::OutOfMemory => ElfLoadError::OutOfMemory,
+            })?;
         
         let trap_cx_ppn = mmset
-            .translate(VirtAddr::from(config::TRAP_CONTEXT).into())
+            .translate(VirtAddr::from(config::trap_context()).into())
             .unwrap()
             .ppn();
         let mut tcb_inner = self.inner_exclusive_access();
         tcb_inner.memory_set = mmset;
         tcb_inner.trap_cx_ppn = trap_cx_ppn;
+        tcb_inner.base_size = heap_bottom;
+        tcb_inner.program_brk = heap_bottom;
         let trap_cx = tcb_inner.get_trap_cx();
         *trap_cx = TrapContext::app_init_context(
-            entrypoint,
+            elf_info.start_point(),
             user_sp,
             KERNEL_SPACE.exclusive_access().token(),
             self.kernel_stack.get_top(),
             trap::trap_handler as usize,
         );
+        trap_cx.x[10] = argv.len();
+        trap_cx.x[11] = argv_base;
+        // tp 指向初始线程的 TLS 块
+        trap_cx.x[4] = elf_info.tls_pointer;
+        Ok(())
     }
 }
 
+// ForkError 是 fork 失败的原因
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ForkError {
+    // 进程的个数达到了 RLIMIT_NPROC
+    ProcessLimit,
+    OutOfMemory,
+}
+
 #[derive(Clone, Copy, PartialEq)]
 pub enum TaskStatus {
     Ready,
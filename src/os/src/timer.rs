@@ -2,7 +2,9 @@ use riscv::register::time;
 use crate::{config, sbi};
 
 const TICKS_PER_SEC: usize = 100;
-const MSEC_PER_SEC: usize = 1000;
+pub const MSEC_PER_SEC: usize = 1000;
+// 两次时钟中断之间的毫秒数
+pub const MSEC_PER_TICK: usize = MSEC_PER_SEC / TICKS_PER_SEC;
 
 pub fn get_time() -> usize {
     time::read()
@@ -2,6 +2,10 @@ mod context;
 
 use crate::{
     config,
+    mm::{
+        address::VirtAddr,
+        memory_set::{MapPermission, PageFaultError},
+    },
     syscall::syscall,
     task::{self, processor},
     timer,
@@ -32,7 +36,7 @@ fn set_kernel_trap_entry() {
 
 fn set_user_trap_entry() {
     unsafe {
-        stvec::write(config::TRAMPOLINE as usize, TrapMode::Direct);
+        stvec::write(config::trampoline(), TrapMode::Direct);
     }
 }
 
@@ -54,8 +58,9 @@ pub fn trap_handler() -> ! {
             // sepc 目前指向的是 ecall 指令的地址，但是它应该指向的是下一条指令，
             // 已知 ecall 指令的长度为 4，所以这里需要加 4。
             trap_cx.sepc += 4;
-            let result =
-                syscall(trap_cx.x[17], [trap_cx.x[10], trap_cx.x[11], trap_cx.x[12]]) as usize;
+            let mut args = [0usize; 6];
+            args.copy_from_slice(&trap_cx.x[10..16]);
+            let result = syscall(trap_cx.x[17], args) as usize;
             // trap_cx 在执行 `exec` 被执行后会被回收，
             // 所以这里需要重新获取一个新的 `trap_cx`。
             trap_cx = processor::current_trap_cx();
@@ -64,9 +69,25 @@ pub fn trap_handler() -> ! {
         Trap::Exception(Exception::StoreFault)
         | Trap::Exception(Exception::StorePageFault)
         | Trap::Exception(Exception::LoadFault)
-        | Trap::Exception(Exception::LoadPageFault) => {
-            println!("[kernel] PageFault in application, kernel killed it.");
-            task::exit_current_and_run_next(MEM_FAULT);
+        | Trap::Exception(Exception::LoadPageFault)
+        | Trap::Exception(Exception::InstructionFault)
+        | Trap::Exception(Exception::InstructionPageFault) => {
+            let va = VirtAddr::from(stval);
+            match handle_page_fault(scause.cause(), va) {
+                Ok(()) => {}
+                Err(PageFaultError::OutOfMemory) => {
+                    println!("[kernel] Out of memory in application, kernel killed it.");
+                    task::exit_current_and_run_next(task::OOM_KILLED);
+                }
+                Err(PageFaultError::InvalidAccess) => {
+                    if is_stack_overflow(va) {
+                        println!("[kernel] Stack overflow in application, kernel killed it.");
+                    } else {
+                        println!("[kernel] PageFault in application, kernel killed it.");
+                    }
+                    task::exit_current_and_run_next(MEM_FAULT);
+                }
+            }
         }
         Trap::Exception(Exception::IllegalInstruction) => {
             println!("[kernel] IllegalInstruction in application, kernel killed it.");
@@ -74,6 +95,10 @@ pub fn trap_handler() -> ! {
         }
         Trap::Interrupt(Interrupt::SupervisorTimer) => {
             timer::set_next_trigger();
+            if !task::charge_current_tick() {
+                println!("[kernel] CPU time limit exceeded in application, kernel killed it.");
+                task::exit_current_and_run_next(task::CPU_LIMIT_EXCEEDED);
+            }
             task::suspend_current_and_run_next();
         }
         _ => {
@@ -88,17 +113,53 @@ pub fn trap_handler() -> ! {
     trap_return();
 }
 
+// handle_page_fault 尝试修复用户程序的 page fault，返回 Ok 表示修复成功，
+// 用户程序可以重新执行引起 page fault 的指令。页框耗尽时通过 OOM killer 杀死
+// 其他进程释放内存后重试，应该被杀死的是当前进程时返回 OutOfMemory。
+fn handle_page_fault(cause: Trap, va: VirtAddr) -> Result<(), PageFaultError> {
+    let access = match cause {
+        Trap::Exception(Exception::LoadPageFault) => MapPermission::R,
+        Trap::Exception(Exception::StorePageFault) => MapPermission::W,
+        Trap::Exception(Exception::InstructionPageFault) => MapPermission::X,
+        _ => return Err(PageFaultError::InvalidAccess),
+    };
+    let task = processor::current_task().unwrap();
+    loop {
+        let result = task
+            .inner_exclusive_access()
+            .memory_set
+            .handle_page_fault(va, access);
+        if result != Err(PageFaultError::OutOfMemory) || !task::oom_kill() {
+            return result;
+        }
+    }
+}
+
+fn is_stack_overflow(va: VirtAddr) -> bool {
+    processor::current_task()
+        .unwrap()
+        .inner_exclusive_access()
+        .memory_set
+        .is_stack_overflow(va)
+}
+
 #[no_mangle]
 // 用于从内核态切换为用户态，并在用户态调用 __restore 方法
 pub fn trap_return() -> ! {
     set_user_trap_entry();
-    let trap_cx_ptr = config::TRAP_CONTEXT;
+    let trap_cx_ptr = config::trap_context();
+    // trap.S 切换 satp 时不再刷新整个 TLB，所以需要在这里刷新页表修改过的表项
+    processor::current_task()
+        .unwrap()
+        .inner_exclusive_access()
+        .memory_set
+        .flush_tlb();
     let user_token = processor::current_user_token();
     extern "C" {
         fn __alltraps();
         fn __restore();
     }
-    let restore_va = __restore as usize - __alltraps as usize + config::TRAMPOLINE;
+    let restore_va = __restore as usize - __alltraps as usize + config::trampoline();
 
     unsafe {
         asm!(
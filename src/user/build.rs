@@ -0,0 +1,55 @@
+use std::fs::read_dir;
+
+// PIE_APPS 中的程序链接为 PIE (ET_DYN)，由内核随机选择加载地址并处理其中的
+// 重定位，其他程序使用 src/linker.ld 链接到固定的地址。所有程序都使用
+// -C relocation-model=pie 编译 (见 .cargo/config)，这样 user_lib 可以同时被两种程序使用。
+const PIE_APPS: &[&str] = &["pie_test"];
+
+// DYNAMIC_APPS 中的程序以 /lib/ld 作为动态链接器，未定义的符号由 ld 在运行时从共享库
+// libuser 中解析。cargo 会并行地链接各个程序，链接这些程序时 libuser 可能还不存在，
+// 所以允许未定义的符号，符号缺失的错误在 ld 加载程序时报告。
+const DYNAMIC_APPS: &[&str] = &["dyn_test"];
+
+fn link_args(app: &str, args: &[&str]) {
+    for arg in args {
+        println!("cargo:rustc-link-arg-bin={}={}", app, arg);
+    }
+}
+
+fn main() {
+    println!("cargo:rerun-if-changed=src/bin");
+    println!("cargo:rerun-if-changed=src/linker.ld");
+    for entry in read_dir("src/bin").unwrap() {
+        let name = entry.unwrap().file_name().into_string().unwrap();
+        let app = name.trim_end_matches(".rs");
+        if app == "libuser" {
+            // 预编译的 core 中有 .rodata 里的跳转表，需要 -z notext 允许对只读段的重定位，
+            // ld 在重定位完成之后才设置段的访问权限
+            link_args(
+                app,
+                &[
+                    "-shared",
+                    "-soname=libuser",
+                    "-Bsymbolic",
+                    "-znotext",
+                    "--hash-style=sysv",
+                ],
+            );
+        } else if app == "ld" {
+            link_args(app, &["-pie", "--no-dynamic-linker", "-e_dl_start"]);
+        } else if DYNAMIC_APPS.contains(&app) {
+            link_args(
+                app,
+                &[
+                    "-pie",
+                    "--dynamic-linker=/lib/ld",
+                    "--unresolved-symbols=ignore-all",
+                ],
+            );
+        } else if PIE_APPS.contains(&app) {
+            link_args(app, &["-pie", "--no-dynamic-linker"]);
+        } else {
+            link_args(app, &["-Tsrc/linker.ld"]);
+        }
+    }
+}
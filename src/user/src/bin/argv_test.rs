@@ -0,0 +1,30 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{getauxval, AT_ENTRY, AT_PAGESZ, AT_PHDR, AT_PHNUM};
+
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    println!("argc = {}", argc);
+    assert_eq!(argc, argv.len());
+    for (i, arg) in argv.iter().enumerate() {
+        println!("argv[{}] = {}", i, arg);
+    }
+    if argc > 0 {
+        assert_eq!(argv[0], "argv_test");
+    }
+
+    assert_eq!(getauxval(AT_PAGESZ), Some(4096));
+    assert!(getauxval(AT_ENTRY).unwrap() != 0);
+    // AT_PHDR 指向已经加载的 program headers，第一个字段是 p_type
+    let phdr = getauxval(AT_PHDR).unwrap();
+    assert!(getauxval(AT_PHNUM).unwrap() > 0);
+    println!("AT_PHDR = {:#x}, p_type = {}", phdr, unsafe {
+        *(phdr as *const u32)
+    });
+    println!("argv_test passed!");
+    0
+}
@@ -0,0 +1,50 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exec, fork, personality, waitpid, ADDR_NO_RANDOMIZE, PERSONALITY_QUERY};
+
+const CHILDREN: usize = 4;
+
+#[no_mangle]
+pub fn main(argc: usize, _argv: &[&str]) -> i32 {
+    let local = 0usize;
+    let sp = &local as *const usize as usize;
+    if argc > 1 {
+        // 子进程通过退出码返回栈地址的页号低位
+        return ((sp >> 12) & 0xff) as i32;
+    }
+    println!("stack variable at {:#x}", sp);
+    let mut codes = [0i32; CHILDREN];
+    for code in codes.iter_mut() {
+        let pid = fork();
+        if pid == 0 {
+            exec(
+                "aslr_test\0",
+                &[
+                    "aslr_test\0".as_ptr(),
+                    "child\0".as_ptr(),
+                    core::ptr::null(),
+                ],
+            );
+            panic!("exec failed");
+        }
+        assert_eq!(waitpid(pid as usize, code), pid);
+        println!("child {} stack page low bits = {:#x}", pid, code);
+    }
+    // 以 norandmaps 启动时所有子进程的栈地址都相同，否则 4 个子进程的栈地址
+    // 全部相同的概率可以忽略
+    let persona = personality(PERSONALITY_QUERY);
+    assert!(persona >= 0);
+    let randomized = !codes.iter().all(|code| *code == codes[0]);
+    if persona as usize & ADDR_NO_RANDOMIZE != 0 {
+        assert!(!randomized);
+        println!("aslr_test: address space randomization is disabled");
+    } else {
+        assert!(randomized);
+    }
+    println!("aslr_test passed!");
+    0
+}
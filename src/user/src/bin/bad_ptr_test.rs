@@ -0,0 +1,69 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    exit, fork, mmap, munmap, waitpid, write, MAP_ANONYMOUS, MAP_PRIVATE, PROT_READ, PROT_WRITE,
+};
+
+const PAGE_SIZE: usize = 4096;
+const EFAULT: isize = 14;
+const EINVAL: isize = 22;
+
+#[no_mangle]
+pub fn main(_argc: usize, _argv: &[&str]) -> i32 {
+    // 未映射的地址以及超出用户地址空间的地址
+    let start = mmap(0, PAGE_SIZE, PROT_READ, MAP_PRIVATE | MAP_ANONYMOUS);
+    assert!(start > 0);
+    let start = start as usize;
+    assert_eq!(munmap(start, PAGE_SIZE), 0);
+    let unmapped = unsafe { core::slice::from_raw_parts(start as *const u8, 16) };
+    assert_eq!(write(1, unmapped), -EFAULT);
+    let kernel =
+        unsafe { core::slice::from_raw_parts(0xffff_ffc0_8020_0000usize as *const u8, 16) };
+    assert_eq!(write(1, kernel), -EFAULT);
+    let overflow = unsafe { core::slice::from_raw_parts(usize::MAX as *const u8, 16) };
+    assert_eq!(write(1, overflow), -EFAULT);
+    // 长度远大于物理内存时内核不能按照长度申请缓冲区
+    for len in [1usize << 37, usize::MAX / 2] {
+        let huge = unsafe { core::slice::from_raw_parts(start as *const u8, len) };
+        assert_eq!(write(1, huge), -EFAULT);
+    }
+    println!("bad buffer ok.");
+
+    // 跨越两页的字符可以正常输出，结尾不完整的字符返回 -EINVAL
+    let pages = mmap(
+        0,
+        2 * PAGE_SIZE,
+        PROT_READ | PROT_WRITE,
+        MAP_PRIVATE | MAP_ANONYMOUS,
+    );
+    assert!(pages > 0);
+    let pages =
+        unsafe { core::slice::from_raw_parts_mut(pages as usize as *mut u8, 2 * PAGE_SIZE) };
+    let text = "é\n".as_bytes();
+    pages[PAGE_SIZE - 1..PAGE_SIZE - 1 + text.len()].copy_from_slice(text);
+    let split = &pages[PAGE_SIZE - 1..PAGE_SIZE - 1 + text.len()];
+    assert_eq!(write(1, split), text.len() as isize);
+    assert_eq!(write(1, &split[..1]), -EINVAL);
+    println!("split utf-8 ok.");
+
+    // 退出码不能写入只读页，写入失败时子进程不会被回收
+    let readonly = mmap(0, PAGE_SIZE, PROT_READ, MAP_PRIVATE | MAP_ANONYMOUS);
+    assert!(readonly > 0);
+    let exit_code = unsafe { &mut *(readonly as usize as *mut i32) };
+    let pid = fork();
+    if pid == 0 {
+        exit(7);
+    }
+    assert_eq!(waitpid(pid as usize, exit_code), -EFAULT);
+    let mut code = 0;
+    assert_eq!(waitpid(pid as usize, &mut code), pid);
+    assert_eq!(code, 7);
+    println!("bad exit code pointer ok.");
+
+    println!("bad_ptr_test passed!");
+    0
+}
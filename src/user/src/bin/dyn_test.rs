@@ -0,0 +1,79 @@
+// dyn_test 是动态链接的程序，不包含 user_lib，下面的函数和变量由 ld 在运行时从共享库
+// libuser 中解析。
+#![no_std]
+#![no_main]
+
+extern "C" {
+    static USER_LIB_VERSION: usize;
+    fn user_write(fd: usize, buf: *const u8, len: usize) -> isize;
+    fn user_exit(exit_code: i32) -> !;
+    fn user_getpid() -> isize;
+    fn user_fork() -> isize;
+    fn user_waitpid(pid: usize, exit_code: *mut i32) -> isize;
+
+    // 链接器定义的程序镜像的开始和结束位置
+    static __ehdr_start: u8;
+    static _end: u8;
+}
+
+const FD_STDOUT: usize = 1;
+const USER_PIE_BASE: usize = 0x4000_0000;
+const CHILD_EXIT_CODE: i32 = 7;
+
+fn print(s: &str) {
+    unsafe {
+        user_write(FD_STDOUT, s.as_ptr(), s.len());
+    }
+}
+
+fn check(condition: bool, msg: &str) {
+    if !condition {
+        print("dyn_test failed: ");
+        print(msg);
+        print("\n");
+        unsafe { user_exit(-1) }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn _start(_argc: usize, _argv: usize) -> ! {
+    print("hello from a dynamically linked program\n");
+    let (image_start, image_end) = unsafe { (&__ehdr_start as *const u8, &_end as *const u8) };
+    let image = image_start as usize..image_end as usize;
+    check(
+        image.start >= USER_PIE_BASE,
+        "program is not position independent",
+    );
+    // 共享库中的函数和变量不在程序自己的镜像中
+    check(
+        !image.contains(&(user_write as usize)),
+        "user_write is linked statically",
+    );
+    let version = unsafe { &USER_LIB_VERSION as *const usize };
+    check(
+        !image.contains(&(version as usize)),
+        "USER_LIB_VERSION is copied",
+    );
+    check(unsafe { *version } == 1, "wrong USER_LIB_VERSION");
+
+    let pid = unsafe { user_fork() };
+    if pid == 0 {
+        check(unsafe { user_getpid() } > 0, "bad pid");
+        unsafe { user_exit(CHILD_EXIT_CODE) }
+    }
+    check(pid > 0, "fork failed");
+    let mut exit_code = 0;
+    check(
+        unsafe { user_waitpid(pid as usize, &mut exit_code) } == pid,
+        "waitpid failed",
+    );
+    check(exit_code == CHILD_EXIT_CODE, "wrong exit code");
+    print("dyn_test passed!\n");
+    unsafe { user_exit(0) }
+}
+
+#[panic_handler]
+fn panic(_info: &core::panic::PanicInfo) -> ! {
+    print("dyn_test panicked\n");
+    unsafe { user_exit(-1) }
+}
@@ -0,0 +1,59 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{env_iter, exec, fork, getenv, setenv, unsetenv, waitpid};
+
+// 被 exec 的子进程检查从 envp 中继承的环境变量
+fn check_exec_env() -> i32 {
+    for (key, value) in env_iter() {
+        println!("{}={}", key, value);
+    }
+    assert_eq!(getenv("ENV_TEST").as_deref(), Some("exec"));
+    assert_eq!(getenv("ENV_TEST_REMOVED"), None);
+    0
+}
+
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    if argc > 1 && argv[1] == "exec" {
+        return check_exec_env();
+    }
+
+    assert!(setenv("ENV_TEST", "fork"));
+    assert!(!setenv("ENV=TEST", "invalid"));
+    assert_eq!(getenv("ENV_TEST").as_deref(), Some("fork"));
+
+    // fork 之后子进程拥有一份环境变量的拷贝，修改不会影响父进程
+    let pid = fork();
+    if pid == 0 {
+        assert_eq!(getenv("ENV_TEST").as_deref(), Some("fork"));
+        setenv("ENV_TEST", "child");
+        return 0;
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    assert_eq!(getenv("ENV_TEST").as_deref(), Some("fork"));
+
+    // exec 时环境变量通过 envp 传递给新的程序
+    setenv("ENV_TEST", "exec");
+    setenv("ENV_TEST_REMOVED", "1");
+    unsetenv("ENV_TEST_REMOVED");
+    let pid = fork();
+    if pid == 0 {
+        let args = [
+            "env_test\0".as_ptr(),
+            "exec\0".as_ptr(),
+            core::ptr::null::<u8>(),
+        ];
+        exec("env_test\0", &args);
+        return -1;
+    }
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    println!("env_test passed!");
+    0
+}
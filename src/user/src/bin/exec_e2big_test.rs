@@ -0,0 +1,63 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::{string::String, vec::Vec};
+use user_lib::{
+    exec, exit, fork, getrlimit, setenv, setrlimit, unsetenv, waitpid, RLimit, RLIMIT_STACK,
+};
+
+const E2BIG: isize = 7;
+
+// long_arg 返回一个长度为 len (不包括结尾的 '\0') 的参数
+fn long_arg(len: usize) -> String {
+    let mut arg: String = core::iter::repeat('a').take(len).collect();
+    arg.push('\0');
+    arg
+}
+
+// exec_with 在子进程中以 args 为参数 exec hello_world，返回子进程的退出码。
+// exec 失败时子进程以 exec 的返回值退出。
+fn exec_with(args: &[String], stack_limit: Option<usize>) -> i32 {
+    let pid = fork();
+    if pid == 0 {
+        if let Some(limit) = stack_limit {
+            let mut rlimit = RLimit::default();
+            assert_eq!(getrlimit(RLIMIT_STACK, &mut rlimit), 0);
+            rlimit.cur = limit;
+            assert_eq!(setrlimit(RLIMIT_STACK, &rlimit), 0);
+        }
+        let mut argv: Vec<*const u8> = args.iter().map(|arg| arg.as_ptr()).collect();
+        argv.insert(0, "hello_world\0".as_ptr());
+        argv.push(core::ptr::null());
+        exit(exec("hello_world\0", &argv) as i32);
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    exit_code
+}
+
+#[no_mangle]
+pub fn main(_argc: usize, _argv: &[&str]) -> i32 {
+    let args: Vec<String> = (0..4).map(|_| long_arg(8 * 1024)).collect();
+    assert_eq!(exec_with(&args, None), 0);
+    // 参数的总长度没有超过 ARG_MAX，但是超过了 RLIMIT_STACK，新的用户栈放不下
+    assert_eq!(exec_with(&args, Some(16 * 1024)), -E2BIG as i32);
+    println!("stack overflow ok.");
+
+    // argv 和 envp 分别都没有超过 ARG_MAX (128 KiB)，但是加起来超过了
+    let args = [long_arg(80 * 1024)];
+    assert_eq!(exec_with(&args, None), 0);
+    let mut value = long_arg(80 * 1024);
+    value.pop();
+    assert!(setenv("E2BIG_TEST", &value));
+    assert_eq!(exec_with(&[], None), 0);
+    assert_eq!(exec_with(&args, None), -E2BIG as i32);
+    unsetenv("E2BIG_TEST");
+    println!("ARG_MAX ok.");
+    println!("exec_e2big_test passed!");
+    0
+}
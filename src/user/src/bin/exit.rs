@@ -8,7 +8,7 @@ use user_lib::{exit, fork, wait, waitpid, yield_};
 const MAGIC: i32 = -0x10384;
 
 #[no_mangle]
-pub fn main() -> i32 {
+pub fn main(_argc: usize, _argv: &[&str]) -> i32 {
     println!("I am the parent. Forking the child...");
     let pid = fork();
     if pid == 0 {
@@ -7,7 +7,7 @@ extern crate user_lib;
 use user_lib::{exec, fork, getpid, wait};
 
 #[no_mangle]
-pub fn main() -> i32 {
+pub fn main(_argc: usize, _argv: &[&str]) -> i32 {
     println!("pid {}: parent start forking ...", getpid());
     let pid = fork();
     if pid == 0 {
@@ -16,7 +16,7 @@ pub fn main() -> i32 {
             "pid {}: forked child start execing hello_world app ... ",
             getpid()
         );
-        exec("hello_world");
+        exec("hello_world\0", &[core::ptr::null::<u8>()]);
         100
     } else {
         // parent process
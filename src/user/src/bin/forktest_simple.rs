@@ -7,7 +7,7 @@ extern crate user_lib;
 use user_lib::{fork, getpid, wait, WAITPID_NO_CHILDREN_RUNNING};
 
 #[no_mangle]
-pub fn main() -> i32 {
+pub fn main(_argc: usize, _argv: &[&str]) -> i32 {
     assert_eq!(wait(&mut 0i32), WAITPID_NO_CHILDREN_RUNNING);
     println!("sys_wait without child process test passed!");
     println!("parent start, pid = {}!", getpid());
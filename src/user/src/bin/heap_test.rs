@@ -0,0 +1,70 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    exit, fork, heapstat, mmap, sleep, waitpid, HeapStat, MAP_ANONYMOUS, MAP_PRIVATE, PROT_READ,
+    PROT_WRITE,
+};
+
+const PAGE_SIZE: usize = 4096;
+const CHILDREN: usize = 32;
+const PAGES: usize = 128;
+
+fn check(stat: &HeapStat) {
+    assert!(stat.requested <= stat.allocated);
+    assert!(stat.allocated <= stat.total);
+    assert!(stat.grown <= stat.total);
+}
+
+// child 映射并访问一些页，让内核为它记录页框的结构占用内核堆，然后等待一段时间再退出
+fn child() -> i32 {
+    let len = PAGES * PAGE_SIZE;
+    let addr = mmap(0, len, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS);
+    assert!(addr > 0);
+    let buf = unsafe { core::slice::from_raw_parts_mut(addr as usize as *mut u8, len) };
+    for i in 0..PAGES {
+        buf[i * PAGE_SIZE] = i as u8;
+    }
+    sleep(200);
+    0
+}
+
+#[no_mangle]
+pub fn main(_argc: usize, _argv: &[&str]) -> i32 {
+    let mut before = HeapStat::default();
+    assert_eq!(heapstat(&mut before), 0);
+    check(&before);
+    println!("heap before: {:?}", before);
+
+    let mut pids = [0isize; CHILDREN];
+    for pid in pids.iter_mut() {
+        *pid = fork();
+        if *pid == 0 {
+            exit(child());
+        }
+        assert!(*pid > 0);
+    }
+    let mut busy = HeapStat::default();
+    assert_eq!(heapstat(&mut busy), 0);
+    check(&busy);
+    println!("heap with {} children: {:?}", CHILDREN, busy);
+    assert!(busy.requested > before.requested);
+
+    for &pid in pids.iter() {
+        let mut exit_code = 0;
+        assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+        assert_eq!(exit_code, 0);
+    }
+    let mut after = HeapStat::default();
+    assert_eq!(heapstat(&mut after), 0);
+    check(&after);
+    println!("heap after: {:?}", after);
+    // 内核堆扩展之后不会收缩
+    assert!(after.total >= busy.total && after.grown >= busy.grown);
+    assert!(after.requested < busy.requested);
+    println!("heap_test passed!");
+    0
+}
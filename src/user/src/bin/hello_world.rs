@@ -7,7 +7,7 @@ extern crate user_lib;
 use user_lib::getpid;
 
 #[no_mangle]
-pub fn main() -> i32 {
+pub fn main(_argc: usize, _argv: &[&str]) -> i32 {
     println!("pid {}: Hello world from user mode program!", getpid());
     0
 }
@@ -7,12 +7,12 @@ extern crate user_lib;
 use user_lib::{exec, fork, wait, yield_};
 
 #[no_mangle]
-fn main() -> i32 {
+fn main(_argc: usize, _argv: &[&str]) -> i32 {
     println!("[initproc] Started.");
     if fork() == 0 {
         // child process
         println!("[initproc] User shell will be started.");
-        exec("user_shell\0");
+        exec("user_shell\0", &[core::ptr::null::<u8>()]);
     } else {
         // parent process
         println!("[initproc] Waiting for user shell to exit.");
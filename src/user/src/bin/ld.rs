@@ -0,0 +1,431 @@
+// ld 是用户态的动态链接器。PT_INTERP 为 /lib/ld 的程序由内核加载之后先运行 ld，
+// 此时内核已经把程序映射到地址空间中并完成了 ld 自身的重定位。ld 把共享库 libuser
+// 映射到地址空间中，重定位 libuser 和程序 (程序中未定义的符号都在 libuser 中查找)，
+// 然后跳转到程序的入口 AT_ENTRY。
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::{mem::size_of, slice};
+use user_lib::{
+    close, exit, mmap, mmap_file, mprotect, open, pread, AT_BASE, AT_ENTRY, AT_NULL, AT_PHDR,
+    AT_PHNUM, MAP_ANONYMOUS, MAP_FIXED, MAP_PRIVATE, O_RDONLY, PROT_EXEC, PROT_READ, PROT_WRITE,
+};
+
+const PAGE_SIZE: usize = 4096;
+
+// 动态链接的程序依赖的共享库
+const LIBRARY: &str = "libuser\0";
+
+const ELF64_EHDR_SIZE: usize = 64;
+const ET_DYN: u16 = 3;
+const EM_RISCV: u16 = 243;
+// 共享库最多有 MAX_PHDRS 个 program header
+const MAX_PHDRS: usize = 16;
+
+const PT_LOAD: u32 = 1;
+const PT_DYNAMIC: u32 = 2;
+const PT_PHDR: u32 = 6;
+
+const PF_X: u32 = 1;
+const PF_W: u32 = 2;
+const PF_R: u32 = 4;
+
+const DT_NULL: usize = 0;
+const DT_PLTRELSZ: usize = 2;
+const DT_HASH: usize = 4;
+const DT_STRTAB: usize = 5;
+const DT_SYMTAB: usize = 6;
+const DT_RELA: usize = 7;
+const DT_RELASZ: usize = 8;
+const DT_RELAENT: usize = 9;
+const DT_JMPREL: usize = 23;
+
+const R_RISCV_NONE: u32 = 0;
+const R_RISCV_64: u32 = 2;
+const R_RISCV_RELATIVE: u32 = 3;
+const R_RISCV_JUMP_SLOT: u32 = 5;
+
+const SHN_UNDEF: u16 = 0;
+const STB_WEAK: u8 = 2;
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct Elf64Phdr {
+    p_type: u32,
+    p_flags: u32,
+    p_offset: usize,
+    p_vaddr: usize,
+    p_paddr: usize,
+    p_filesz: usize,
+    p_memsz: usize,
+    p_align: usize,
+}
+
+#[repr(C)]
+struct Elf64Dyn {
+    tag: usize,
+    value: usize,
+}
+
+#[repr(C)]
+struct Elf64Rela {
+    offset: usize,
+    info: usize,
+    addend: usize,
+}
+
+#[repr(C)]
+struct Elf64Sym {
+    name: u32,
+    info: u8,
+    other: u8,
+    shndx: u16,
+    value: usize,
+    size: usize,
+}
+
+fn fail(msg: &str) -> ! {
+    println!("[ld] {}", msg);
+    exit(-1);
+    unreachable!();
+}
+
+fn page_floor(addr: usize) -> usize {
+    addr & !(PAGE_SIZE - 1)
+}
+
+fn page_ceil(addr: usize) -> usize {
+    (addr + PAGE_SIZE - 1) & !(PAGE_SIZE - 1)
+}
+
+// elf_hash 是 DT_HASH 使用的 System V 哈希函数
+fn elf_hash(name: &[u8]) -> u32 {
+    let mut h: u32 = 0;
+    for &c in name {
+        h = (h << 4).wrapping_add(c as u32);
+        let g = h & 0xf000_0000;
+        if g != 0 {
+            h ^= g >> 24;
+        }
+        h &= !g;
+    }
+    h
+}
+
+// DynObject 是一个已经被映射到地址空间中的 ELF 对象 (程序或者共享库)
+struct DynObject {
+    base: usize,
+    symtab: *const Elf64Sym,
+    strtab: *const u8,
+    // DT_HASH 表，没有这个表的对象不导出符号
+    hash: *const u32,
+    rela: &'static [Elf64Rela],
+    jmprel: &'static [Elf64Rela],
+}
+
+impl DynObject {
+    // new 解析加载偏移为 base 的对象在 dynamic 处的 PT_DYNAMIC
+    unsafe fn new(base: usize, dynamic: usize) -> Self {
+        let mut object = Self {
+            base,
+            symtab: core::ptr::null(),
+            strtab: core::ptr::null(),
+            hash: core::ptr::null(),
+            rela: &[],
+            jmprel: &[],
+        };
+        let (mut rela, mut rela_size, mut jmprel, mut jmprel_size) = (0, 0, 0, 0);
+        let mut entry = dynamic as *const Elf64Dyn;
+        loop {
+            let Elf64Dyn { tag, value } = entry.read();
+            match tag {
+                DT_NULL => break,
+                DT_HASH => object.hash = (base + value) as *const u32,
+                DT_STRTAB => object.strtab = (base + value) as *const u8,
+                DT_SYMTAB => object.symtab = (base + value) as *const Elf64Sym,
+                DT_RELA => rela = base + value,
+                DT_RELASZ => rela_size = value,
+                DT_JMPREL => jmprel = base + value,
+                DT_PLTRELSZ => jmprel_size = value,
+                DT_RELAENT if value != size_of::<Elf64Rela>() => {
+                    fail("invalid relocation entry size")
+                }
+                _ => {}
+            }
+            entry = entry.add(1);
+        }
+        if rela != 0 {
+            object.rela = relocation_table(rela, rela_size);
+        }
+        if jmprel != 0 {
+            object.jmprel = relocation_table(jmprel, jmprel_size);
+        }
+        object
+    }
+
+    unsafe fn symbol(&self, index: usize) -> &Elf64Sym {
+        &*self.symtab.add(index)
+    }
+
+    unsafe fn symbol_name(&self, sym: &Elf64Sym) -> &[u8] {
+        let name = self.strtab.add(sym.name as usize);
+        let len = (0..).find(|&i| *name.add(i) == 0).unwrap();
+        slice::from_raw_parts(name, len)
+    }
+
+    // lookup 通过 DT_HASH 查找对象中定义的符号，返回符号的地址
+    unsafe fn lookup(&self, name: &[u8]) -> Option<usize> {
+        if self.hash.is_null() {
+            return None;
+        }
+        let nbucket = *self.hash as usize;
+        let buckets = self.hash.add(2);
+        let chains = buckets.add(nbucket);
+        let mut index = *buckets.add(elf_hash(name) as usize % nbucket) as usize;
+        while index != 0 {
+            let sym = self.symbol(index);
+            if sym.shndx != SHN_UNDEF && self.symbol_name(sym) == name {
+                return Some(self.base + sym.value);
+            }
+            index = *chains.add(index) as usize;
+        }
+        None
+    }
+
+    // symbol_address 返回第 index 个符号的地址，对象中没有定义的符号在 library 中查找，
+    // 找不到的弱符号的地址为 0
+    unsafe fn symbol_address(&self, index: usize, library: &DynObject) -> usize {
+        if index == 0 {
+            return 0;
+        }
+        let sym = self.symbol(index);
+        if sym.shndx != SHN_UNDEF {
+            return self.base + sym.value;
+        }
+        let name = self.symbol_name(sym);
+        match library.lookup(name) {
+            Some(addr) => addr,
+            None if sym.info >> 4 == STB_WEAK => 0,
+            None => {
+                let name = core::str::from_utf8(name).unwrap_or("?");
+                println!("[ld] undefined symbol: {}", name);
+                fail("cannot link the program");
+            }
+        }
+    }
+
+    // relocate 处理 DT_RELA 和 DT_JMPREL 中的重定位
+    unsafe fn relocate(&self, library: &DynObject) {
+        for rela in self.rela.iter().chain(self.jmprel.iter()) {
+            let index = rela.info >> 32;
+            let value = match (rela.info & 0xffff_ffff) as u32 {
+                R_RISCV_NONE => continue,
+                R_RISCV_RELATIVE => self.base.wrapping_add(rela.addend),
+                // 链接器为本地函数生成的不引用符号的 JUMP_SLOT，与内核的处理相同
+                R_RISCV_JUMP_SLOT if index == 0 => self.base.wrapping_add(rela.addend),
+                R_RISCV_64 | R_RISCV_JUMP_SLOT => self
+                    .symbol_address(index, library)
+                    .wrapping_add(rela.addend),
+                _ => fail("unsupported relocation type"),
+            };
+            ((self.base + rela.offset) as *mut usize).write(value);
+        }
+    }
+}
+
+unsafe fn relocation_table(addr: usize, size: usize) -> &'static [Elf64Rela] {
+    slice::from_raw_parts(addr as *const Elf64Rela, size / size_of::<Elf64Rela>())
+}
+
+// Library 是被映射到地址空间中的共享库
+struct Library {
+    base: usize,
+    dynamic: usize,
+    phdrs: [Elf64Phdr; MAX_PHDRS],
+    phnum: usize,
+}
+
+impl Library {
+    fn loads(&self) -> impl Iterator<Item = &Elf64Phdr> {
+        self.phdrs[..self.phnum]
+            .iter()
+            .filter(|phdr| phdr.p_type == PT_LOAD)
+    }
+
+    // load 将共享库 path 的 PT_LOAD 段映射到地址空间中。为了处理重定位，所有的段
+    // 先以可读写的方式映射，重定位完成之后再由 protect 设置段的访问权限。
+    fn load(path: &str) -> Self {
+        let fd = open(path, O_RDONLY);
+        if fd < 0 {
+            fail("cannot open the shared library");
+        }
+        let fd = fd as usize;
+        let mut ehdr = [0u8; ELF64_EHDR_SIZE];
+        if pread(fd, &mut ehdr, 0) != ELF64_EHDR_SIZE as isize {
+            fail("cannot read the elf header");
+        }
+        let half = |offset: usize| u16::from_le_bytes([ehdr[offset], ehdr[offset + 1]]);
+        if ehdr[..4] != *b"\x7fELF" || ehdr[4] != 2 || half(16) != ET_DYN || half(18) != EM_RISCV {
+            fail("the shared library is not a RISC-V 64 shared object");
+        }
+        let phoff = u64::from_le_bytes(ehdr[32..40].try_into().unwrap()) as usize;
+        let phnum = half(56) as usize;
+        if half(54) as usize != size_of::<Elf64Phdr>() || phnum > MAX_PHDRS {
+            fail("invalid program headers");
+        }
+        let mut library = Self {
+            base: 0,
+            dynamic: 0,
+            phdrs: [Elf64Phdr::default(); MAX_PHDRS],
+            phnum,
+        };
+        let len = phnum * size_of::<Elf64Phdr>();
+        let buf = unsafe { slice::from_raw_parts_mut(library.phdrs.as_mut_ptr() as *mut u8, len) };
+        if pread(fd, buf, phoff) != len as isize {
+            fail("cannot read the program headers");
+        }
+
+        // 先预留整个镜像的地址范围，然后在其中按照固定地址映射每个段
+        let span = library
+            .loads()
+            .map(|phdr| phdr.p_vaddr + phdr.p_memsz)
+            .max()
+            .unwrap_or_else(|| fail("no loadable segment"));
+        let base = mmap(0, page_ceil(span), PROT_READ, MAP_PRIVATE | MAP_ANONYMOUS);
+        if base < 0 {
+            fail("cannot reserve the address space");
+        }
+        library.base = base as usize;
+        let mut prev_end = library.base;
+        for phdr in library.loads() {
+            let vaddr = library.base + phdr.p_vaddr;
+            let (start, file_end) = (page_floor(vaddr), vaddr + phdr.p_filesz);
+            let mem_end = vaddr + phdr.p_memsz;
+            // 段之间不能共享页，否则后映射的段会覆盖前一个段
+            if phdr.p_filesz > phdr.p_memsz
+                || start < prev_end
+                || vaddr % PAGE_SIZE != phdr.p_offset % PAGE_SIZE
+            {
+                fail("invalid segment layout");
+            }
+            let rw = PROT_READ | PROT_WRITE;
+            let mut mapped_end = start;
+            if phdr.p_filesz > 0 {
+                mapped_end = page_ceil(file_end);
+                let offset = page_floor(phdr.p_offset);
+                let flags = MAP_PRIVATE | MAP_FIXED;
+                if mmap_file(start, mapped_end - start, rw, flags, fd, offset) != start as isize {
+                    fail("cannot map the shared library");
+                }
+            }
+            // .bss 中位于文件映射最后一页的部分需要清零，之后的页使用匿名映射
+            if phdr.p_memsz > phdr.p_filesz {
+                let zero_end = mapped_end.min(mem_end);
+                unsafe {
+                    core::ptr::write_bytes(
+                        file_end as *mut u8,
+                        0,
+                        zero_end.saturating_sub(file_end),
+                    );
+                }
+                let page_end = page_ceil(mem_end);
+                let flags = MAP_PRIVATE | MAP_ANONYMOUS | MAP_FIXED;
+                if page_end > mapped_end
+                    && mmap(mapped_end, page_end - mapped_end, rw, flags) != mapped_end as isize
+                {
+                    fail("cannot map the shared library");
+                }
+            }
+            prev_end = page_ceil(mem_end);
+        }
+        close(fd);
+
+        library.dynamic = library.phdrs[..phnum]
+            .iter()
+            .find(|phdr| phdr.p_type == PT_DYNAMIC)
+            .map(|phdr| library.base + phdr.p_vaddr)
+            .unwrap_or_else(|| fail("the shared library has no PT_DYNAMIC"));
+        library
+    }
+
+    // protect 按照 p_flags 设置每个段的访问权限
+    fn protect(&self) {
+        for phdr in self.loads() {
+            let start = page_floor(self.base + phdr.p_vaddr);
+            let end = page_ceil(self.base + phdr.p_vaddr + phdr.p_memsz);
+            let mut prot = 0;
+            if phdr.p_flags & PF_R != 0 {
+                prot |= PROT_READ;
+            }
+            if phdr.p_flags & PF_W != 0 {
+                prot |= PROT_WRITE;
+            }
+            if phdr.p_flags & PF_X != 0 {
+                prot |= PROT_EXEC;
+            }
+            if mprotect(start, end - start, prot) != 0 {
+                fail("cannot protect the shared library");
+            }
+        }
+    }
+}
+
+// auxv 读取内核放在 envp 之后的 auxv，返回 key 对应的值
+unsafe fn auxv(argc: usize, argv: usize, key: usize) -> Option<usize> {
+    let envp = (argv as *const usize).add(argc + 1);
+    let envc = (0..).find(|&i| *envp.add(i) == 0).unwrap();
+    let mut auxv = envp.add(envc + 1);
+    loop {
+        let (k, v) = (*auxv, *auxv.add(1));
+        if k == AT_NULL {
+            return None;
+        }
+        if k == key {
+            return Some(v);
+        }
+        auxv = auxv.add(2);
+    }
+}
+
+// program 返回程序的加载偏移和 PT_DYNAMIC 的地址，内核已经把程序的 program header
+// 映射在 AT_PHDR 处
+unsafe fn program(phdr: usize, phnum: usize) -> (usize, usize) {
+    let phdrs = slice::from_raw_parts(phdr as *const Elf64Phdr, phnum);
+    let base = phdrs
+        .iter()
+        .find(|phdr| phdr.p_type == PT_PHDR)
+        .map(|ph| phdr - ph.p_vaddr)
+        .unwrap_or_else(|| fail("the program has no PT_PHDR"));
+    let dynamic = phdrs
+        .iter()
+        .find(|phdr| phdr.p_type == PT_DYNAMIC)
+        .map(|phdr| base + phdr.p_vaddr)
+        .unwrap_or_else(|| fail("the program has no PT_DYNAMIC"));
+    (base, dynamic)
+}
+
+#[no_mangle]
+pub extern "C" fn _dl_start(argc: usize, argv: usize) -> ! {
+    let key = |key| unsafe { auxv(argc, argv, key) };
+    // 直接运行 ld 时内核不会传递 AT_BASE
+    if key(AT_BASE).is_none() {
+        fail("ld must be used as the program interpreter");
+    }
+    let (phdr, phnum, entry) = match (key(AT_PHDR), key(AT_PHNUM), key(AT_ENTRY)) {
+        (Some(phdr), Some(phnum), Some(entry)) => (phdr, phnum, entry),
+        _ => fail("missing auxv entries"),
+    };
+    let library = Library::load(LIBRARY);
+    unsafe {
+        let (base, dynamic) = program(phdr, phnum);
+        let shared = DynObject::new(library.base, library.dynamic);
+        shared.relocate(&shared);
+        DynObject::new(base, dynamic).relocate(&shared);
+    }
+    library.protect();
+    let entry: extern "C" fn(usize, usize) -> ! = unsafe { core::mem::transmute(entry) };
+    entry(argc, argv)
+}
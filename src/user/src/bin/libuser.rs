@@ -0,0 +1,46 @@
+// libuser 是 user_lib 的共享库版本，由动态链接器 ld 在运行时加载。动态链接的程序通过
+// C ABI 调用这里导出的函数，修改 user_lib 之后只需要重新链接 libuser。
+#![no_std]
+#![no_main]
+
+use core::slice;
+
+// USER_LIB_VERSION 是共享库的版本号
+#[no_mangle]
+pub static USER_LIB_VERSION: usize = 1;
+
+#[no_mangle]
+pub extern "C" fn user_write(fd: usize, buf: *const u8, len: usize) -> isize {
+    user_lib::write(fd, unsafe { slice::from_raw_parts(buf, len) })
+}
+
+#[no_mangle]
+pub extern "C" fn user_exit(exit_code: i32) -> ! {
+    user_lib::exit(exit_code);
+    unreachable!();
+}
+
+#[no_mangle]
+pub extern "C" fn user_yield() -> isize {
+    user_lib::yield_()
+}
+
+#[no_mangle]
+pub extern "C" fn user_get_time() -> isize {
+    user_lib::get_time()
+}
+
+#[no_mangle]
+pub extern "C" fn user_getpid() -> isize {
+    user_lib::getpid()
+}
+
+#[no_mangle]
+pub extern "C" fn user_fork() -> isize {
+    user_lib::fork()
+}
+
+#[no_mangle]
+pub extern "C" fn user_waitpid(pid: usize, exit_code: *mut i32) -> isize {
+    user_lib::waitpid(pid, unsafe { &mut *exit_code })
+}
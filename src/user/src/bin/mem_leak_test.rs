@@ -0,0 +1,78 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    exit, fork, framestat, memstat, mmap, munmap, waitpid, FrameStat, MemoryStat, MAP_ANONYMOUS,
+    MAP_PRIVATE, PROT_READ, PROT_WRITE,
+};
+
+const PAGE_SIZE: usize = 4096;
+const PAGES: usize = 32;
+const ROUNDS: usize = 8;
+
+// child 检查 mmap 之后 VSZ/RSS/peak 的变化
+fn child() -> i32 {
+    let mut before = MemoryStat::default();
+    assert_eq!(memstat(&mut before), 0);
+
+    let len = PAGES * PAGE_SIZE;
+    let addr = mmap(0, len, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS);
+    assert!(addr > 0);
+    let mut stat = MemoryStat::default();
+    assert_eq!(memstat(&mut stat), 0);
+    // 匿名映射是按需分配的，访问之前不占用页框
+    assert_eq!(stat.vsz, before.vsz + PAGES);
+    assert_eq!(stat.rss, before.rss);
+
+    let buf = unsafe { core::slice::from_raw_parts_mut(addr as usize as *mut u8, len) };
+    for i in 0..PAGES {
+        buf[i * PAGE_SIZE] = i as u8;
+    }
+    assert_eq!(memstat(&mut stat), 0);
+    assert_eq!(stat.rss, before.rss + PAGES);
+    assert!(stat.peak_rss >= stat.rss);
+    assert!(stat.page_table > 0);
+
+    assert_eq!(munmap(addr as usize, len), 0);
+    let peak_rss = stat.peak_rss;
+    assert_eq!(memstat(&mut stat), 0);
+    assert_eq!(stat.vsz, before.vsz);
+    assert_eq!(stat.rss, before.rss);
+    assert_eq!(stat.peak_rss, peak_rss);
+    0
+}
+
+fn run_child() {
+    let pid = fork();
+    if pid == 0 {
+        exit(child());
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+}
+
+#[no_mangle]
+pub fn main(_argc: usize, _argv: &[&str]) -> i32 {
+    // 第一个子进程可能会让内核建立一些之后一直保留的结构 (比如 kernel stack 所在的页表)，
+    // 所以从第二个子进程开始统计
+    run_child();
+
+    let mut before = FrameStat::default();
+    assert_eq!(framestat(&mut before), 0);
+    for _ in 0..ROUNDS {
+        run_child();
+    }
+    let mut after = FrameStat::default();
+    assert_eq!(framestat(&mut after), 0);
+    println!(
+        "frames: total = {}, free before = {}, free after = {}",
+        after.total, before.free, after.free
+    );
+    assert_eq!(before.free, after.free);
+    println!("mem_leak_test passed!");
+    0
+}
@@ -0,0 +1,126 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, exit, fork, ftruncate, memfd_create, mmap_file, mprotect, msync, munmap, open, pread,
+    read, waitpid, write, MAP_PRIVATE, MAP_SHARED, MS_SYNC, O_RDONLY, O_RDWR, PROT_READ,
+    PROT_WRITE,
+};
+
+const PAGE_SIZE: usize = 4096;
+const LEN: usize = 2 * PAGE_SIZE;
+
+const ENOENT: isize = 2;
+const EBADF: isize = 9;
+const EACCES: isize = 13;
+const EINVAL: isize = 22;
+
+fn map(fd: usize, prot: usize, flags: usize) -> &'static mut [u8] {
+    let addr = mmap_file(0, LEN, prot, flags, fd, 0);
+    assert!(addr > 0);
+    unsafe { core::slice::from_raw_parts_mut(addr as usize as *mut u8, LEN) }
+}
+
+// read_byte 绕过映射直接从文件中读取一个字节
+fn read_byte(fd: usize, offset: usize) -> u8 {
+    let mut byte = [0u8; 1];
+    assert_eq!(pread(fd, &mut byte, offset), 1);
+    byte[0]
+}
+
+#[no_mangle]
+pub fn main(_argc: usize, _argv: &[&str]) -> i32 {
+    let fd = memfd_create("mmap_file_test\0", 0);
+    assert!(fd > 2);
+    let fd = fd as usize;
+    assert_eq!(ftruncate(fd, LEN), 0);
+    assert_eq!(mmap_file(0, LEN, PROT_READ, MAP_SHARED, fd, 1), -EINVAL);
+
+    // 共享映射中的修改在 msync 和 munmap 时写回文件
+    let buf = map(fd, PROT_READ | PROT_WRITE, MAP_SHARED);
+    buf[0] = 1;
+    buf[LEN - 1] = 2;
+    assert_eq!(msync(buf.as_ptr() as usize, LEN, MS_SYNC), 0);
+    assert_eq!(read_byte(fd, 0), 1);
+    assert_eq!(read_byte(fd, LEN - 1), 2);
+    buf[PAGE_SIZE] = 3;
+    assert_eq!(munmap(buf.as_ptr() as usize, LEN), 0);
+    assert_eq!(read_byte(fd, PAGE_SIZE), 3);
+    println!("msync/munmap write-back ok.");
+
+    // 子进程退出时共享映射中的修改也会被写回
+    let buf = map(fd, PROT_READ | PROT_WRITE, MAP_SHARED);
+    assert_eq!((buf[0], buf[PAGE_SIZE], buf[LEN - 1]), (1, 3, 2));
+    let pid = fork();
+    if pid == 0 {
+        buf[1] = 4;
+        exit(0);
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    assert_eq!(buf[1], 4);
+    assert_eq!(read_byte(fd, 1), 4);
+    assert_eq!(munmap(buf.as_ptr() as usize, LEN), 0);
+    println!("shared mapping across fork ok.");
+
+    // 私有映射的修改不会写回文件
+    let private = map(fd, PROT_READ | PROT_WRITE, MAP_PRIVATE);
+    assert_eq!(private[0], 1);
+    private[0] = 5;
+    assert_eq!(munmap(private.as_ptr() as usize, LEN), 0);
+    assert_eq!(read_byte(fd, 0), 1);
+    println!("private mapping ok.");
+
+    assert_eq!(ftruncate(fd, PAGE_SIZE), 0);
+    let mut byte = [0u8; 1];
+    assert_eq!(pread(fd, &mut byte, PAGE_SIZE), 0);
+
+    // read/write 从文件位置开始读写并移动文件位置，不会超过文件末尾
+    assert_eq!(write(fd, b"ab"), 2);
+    assert_eq!((read_byte(fd, 0), read_byte(fd, 1)), (b'a', b'b'));
+    let mut pair = [0xffu8; 2];
+    assert_eq!(read(fd, &mut pair), 2);
+    assert_eq!(pair, [read_byte(fd, 2), read_byte(fd, 3)]);
+    let mut rest = [0u8; PAGE_SIZE];
+    assert_eq!(read(fd, &mut rest), (PAGE_SIZE - 4) as isize);
+    assert_eq!(read(fd, &mut rest), 0);
+    assert_eq!(write(fd, b"c"), 0);
+    println!("read/write on memfd ok.");
+
+    assert_eq!(close(fd), 0);
+    assert_eq!(close(fd), -EBADF);
+    assert_eq!(write(fd, b"c"), -EBADF);
+    assert_eq!(read(fd, &mut byte), -EBADF);
+    assert_eq!(mmap_file(0, LEN, PROT_READ, MAP_SHARED, fd, 0), -EBADF);
+
+    // 用户程序是只读文件，只能私有映射或者只读共享映射
+    assert_eq!(open("mmap_file_test\0", O_RDWR), -EACCES);
+    assert_eq!(open("no_such_app\0", O_RDONLY), -ENOENT);
+    let app = open("mmap_file_test\0", O_RDONLY);
+    assert!(app > 2);
+    let app = app as usize;
+    let elf = map(app, PROT_READ, MAP_PRIVATE);
+    assert_eq!(&elf[..4], b"\x7fELF");
+    assert_eq!(
+        mmap_file(0, LEN, PROT_READ | PROT_WRITE, MAP_SHARED, app, 0),
+        -EACCES
+    );
+    let shared = map(app, PROT_READ, MAP_SHARED);
+    assert_eq!(&shared[..4], b"\x7fELF");
+    let mut magic = [0u8; 4];
+    assert_eq!(read(app, &mut magic), 4);
+    assert_eq!(&magic, b"\x7fELF");
+    assert_eq!(write(app, b"c"), -EBADF);
+    assert_eq!(
+        mprotect(shared.as_ptr() as usize, LEN, PROT_READ | PROT_WRITE),
+        -EACCES
+    );
+    assert_eq!(close(app), 0);
+    println!("read-only file mapping ok.");
+    println!("mmap_file_test passed!");
+    0
+}
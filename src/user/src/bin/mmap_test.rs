@@ -0,0 +1,58 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    mmap, mprotect, munmap, MAP_ANONYMOUS, MAP_FIXED, MAP_PRIVATE, PROT_READ, PROT_WRITE,
+};
+
+const PAGE_SIZE: usize = 4096;
+
+#[no_mangle]
+pub fn main(_argc: usize, _argv: &[&str]) -> i32 {
+    let len = PAGE_SIZE * 4;
+    let start = mmap(0, len, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS);
+    assert!(start > 0);
+    let start = start as usize;
+    let buf = unsafe { core::slice::from_raw_parts_mut(start as *mut u8, len) };
+    for (i, byte) in buf.iter_mut().enumerate() {
+        *byte = i as u8;
+    }
+    for (i, byte) in buf.iter().enumerate() {
+        assert_eq!(*byte, i as u8);
+    }
+    println!("mmap {:#x} ok.", start);
+
+    // 非法参数
+    assert!(mmap(start + 1, len, PROT_READ, MAP_PRIVATE | MAP_ANONYMOUS) < 0);
+    assert!(mmap(0, 0, PROT_READ, MAP_PRIVATE | MAP_ANONYMOUS) < 0);
+    assert!(mmap(0, len, 0x8, MAP_PRIVATE | MAP_ANONYMOUS) < 0);
+
+    // 已经被占用的地址会被当作提示，内核会重新选择一个空闲的地址
+    let other = mmap(start, len, PROT_READ, MAP_PRIVATE | MAP_ANONYMOUS);
+    assert!(other > 0 && other as usize != start);
+    assert_eq!(munmap(other as usize, len), 0);
+
+    // 拆分逻辑段：修改中间一页的权限，然后解除中间一页的映射
+    assert_eq!(mprotect(start + PAGE_SIZE, PAGE_SIZE, PROT_READ), 0);
+    assert_eq!(buf[PAGE_SIZE + 1], 1);
+    assert_eq!(munmap(start + PAGE_SIZE * 2, PAGE_SIZE), 0);
+    assert!(mprotect(start, len, PROT_READ) < 0);
+    assert_eq!(buf[PAGE_SIZE * 3], 0);
+
+    // MAP_FIXED 会替换掉已有的映射，新的页被清零
+    let fixed = mmap(
+        start,
+        PAGE_SIZE,
+        PROT_READ | PROT_WRITE,
+        MAP_PRIVATE | MAP_ANONYMOUS | MAP_FIXED,
+    );
+    assert_eq!(fixed as usize, start);
+    assert_eq!(buf[1], 0);
+
+    assert_eq!(munmap(start, len), 0);
+    println!("mmap_test passed!");
+    0
+}
@@ -0,0 +1,75 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    exit, fork, mmap, open, pread, waitpid, MAP_ANONYMOUS, MAP_PRIVATE, O_RDONLY, PROT_READ,
+    PROT_WRITE,
+};
+
+const PAGE_SIZE: usize = 4096;
+// 256 MiB 超过了物理内存和交换区的总和
+const PAGE_COUNT: usize = 0x1_0000;
+// 与内核中 task::OOM_KILLED 保持一致
+const OOM_KILLED: i32 = -4;
+const ENOMEM: isize = 12;
+// 每次 pread 读取的页数
+const CHUNK_PAGES: usize = 16;
+
+fn map_buffer() -> &'static mut [u8] {
+    let len = PAGE_SIZE * PAGE_COUNT;
+    let start = mmap(0, len, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS);
+    assert!(start > 0);
+    unsafe { core::slice::from_raw_parts_mut(start as usize as *mut u8, len) }
+}
+
+// pread_child 通过 pread 让内核写入 lazy 页，页框耗尽时这个进程的 RSS 最大，
+// 系统调用返回 -ENOMEM 而不是 -EFAULT，进程本身不会被杀死
+fn pread_child() -> i32 {
+    let fd = open("oom_test\0", O_RDONLY);
+    assert!(fd >= 0);
+    let buf = map_buffer();
+    for chunk in buf.chunks_mut(PAGE_SIZE * CHUNK_PAGES) {
+        let ret = pread(fd as usize, chunk, 0);
+        if ret == -ENOMEM {
+            return 0;
+        }
+        assert!(ret as usize >= PAGE_SIZE);
+    }
+    panic!("pread should have failed with ENOMEM");
+}
+
+#[no_mangle]
+pub fn main(_argc: usize, _argv: &[&str]) -> i32 {
+    let pid = fork();
+    if pid == 0 {
+        let buf = map_buffer();
+        for page in 0..PAGE_COUNT {
+            buf[page * PAGE_SIZE] = page as u8;
+        }
+        panic!("child should have been killed by the OOM killer");
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, OOM_KILLED);
+
+    let pid = fork();
+    if pid == 0 {
+        exit(pread_child());
+    }
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    println!("pread returned ENOMEM.");
+
+    // 被杀死的进程释放了全部内存，之后仍然可以正常 fork
+    let pid = fork();
+    if pid == 0 {
+        return 0;
+    }
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    println!("oom_test passed!");
+    0
+}
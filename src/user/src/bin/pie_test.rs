@@ -0,0 +1,78 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::ptr::read_volatile;
+use user_lib::{
+    exec, fork, getauxval, personality, waitpid, ADDR_NO_RANDOMIZE, AT_ENTRY, AT_PHDR,
+    PERSONALITY_QUERY,
+};
+
+// pie_test 被链接为 PIE，加载地址在 USER_PIE_BASE 之上
+const USER_PIE_BASE: usize = 0x4000_0000;
+const CHILDREN: usize = 4;
+
+// VALUE_PTR 和 HOOKS 在链接时只记录了相对地址，需要内核在加载时进行重定位
+static VALUE: usize = 42;
+static VALUE_PTR: &usize = &VALUE;
+static HOOKS: [fn() -> usize; 2] = [one, two];
+
+fn one() -> usize {
+    1
+}
+
+fn two() -> usize {
+    2
+}
+
+fn main_addr() -> usize {
+    main as usize
+}
+
+#[no_mangle]
+pub fn main(argc: usize, _argv: &[&str]) -> i32 {
+    if argc > 1 {
+        // 子进程通过退出码返回加载地址的页号低位
+        return ((main_addr() >> 12) & 0xff) as i32;
+    }
+    let base = main_addr();
+    println!("pie_test: main at {:#x}", base);
+    assert!(base >= USER_PIE_BASE);
+    assert!(getauxval(AT_PHDR).unwrap() >= USER_PIE_BASE);
+    assert!(getauxval(AT_ENTRY).unwrap() >= USER_PIE_BASE);
+
+    let ptr = unsafe { read_volatile(&VALUE_PTR) };
+    assert_eq!(ptr as *const usize, &VALUE as *const usize);
+    assert_eq!(*ptr, 42);
+    let hooks = unsafe { read_volatile(&HOOKS) };
+    assert_eq!(hooks[0] as usize, one as usize);
+    assert_eq!(hooks[0]() + hooks[1](), 3);
+    println!("relocated static pointers ok.");
+
+    let mut codes = [0i32; CHILDREN];
+    for code in codes.iter_mut() {
+        let pid = fork();
+        if pid == 0 {
+            exec(
+                "pie_test\0",
+                &["pie_test\0".as_ptr(), "child\0".as_ptr(), core::ptr::null()],
+            );
+            panic!("exec failed");
+        }
+        assert_eq!(waitpid(pid as usize, code), pid);
+    }
+    // 以 norandmaps 启动时所有子进程的加载地址都相同
+    let persona = personality(PERSONALITY_QUERY);
+    assert!(persona >= 0);
+    let randomized = !codes.iter().all(|code| *code == codes[0]);
+    if persona as usize & ADDR_NO_RANDOMIZE != 0 {
+        assert!(!randomized);
+        assert_eq!(codes[0] as usize, (base >> 12) & 0xff);
+    } else {
+        assert!(randomized);
+    }
+    println!("pie_test passed!");
+    0
+}
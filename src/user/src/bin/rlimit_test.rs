@@ -0,0 +1,158 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    exit, fork, getpid, getrlimit, memstat, mmap, prlimit, setrlimit, sleep, waitpid, MemoryStat,
+    RLimit, CPU_LIMIT_EXCEEDED, MAP_ANONYMOUS, MAP_PRIVATE, PROT_READ, PROT_WRITE, RLIMIT_AS,
+    RLIMIT_CPU, RLIMIT_NOFILE, RLIMIT_NPROC, RLIMIT_STACK, RLIM_INFINITY,
+};
+
+const PAGE_SIZE: usize = 4096;
+const MEM_FAULT: i32 = -2;
+
+const EPERM: isize = 1;
+const ESRCH: isize = 3;
+const EAGAIN: isize = 11;
+const ENOMEM: isize = 12;
+const EINVAL: isize = 22;
+
+// 每一层递归占用 1 KiB 的栈空间
+fn recurse(depth: usize) -> usize {
+    let buf = [depth as u8; 1024];
+    let buf = unsafe { core::ptr::read_volatile(&buf) };
+    if depth == 0 {
+        return buf[0] as usize;
+    }
+    recurse(depth - 1) + buf[1023] as usize
+}
+
+// run_child 在子进程中执行 f 并返回子进程的退出码
+fn run_child(f: fn() -> i32) -> i32 {
+    let pid = fork();
+    if pid == 0 {
+        exit(f());
+    }
+    assert!(pid > 0);
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    exit_code
+}
+
+fn set_cur(resource: usize, cur: usize) {
+    let mut limit = RLimit::default();
+    assert_eq!(getrlimit(resource, &mut limit), 0);
+    limit.cur = cur;
+    assert_eq!(setrlimit(resource, &limit), 0);
+}
+
+// as_child 将地址空间限制为当前 VSZ 之后再加 16 页
+fn as_child() -> i32 {
+    let mut stat = MemoryStat::default();
+    assert_eq!(memstat(&mut stat), 0);
+    set_cur(RLIMIT_AS, (stat.vsz + 16) * PAGE_SIZE);
+    let flags = MAP_PRIVATE | MAP_ANONYMOUS;
+    assert_eq!(
+        mmap(0, 32 * PAGE_SIZE, PROT_READ | PROT_WRITE, flags),
+        -ENOMEM
+    );
+    assert!(mmap(0, 8 * PAGE_SIZE, PROT_READ | PROT_WRITE, flags) > 0);
+    0
+}
+
+// nproc_child 中已经有 initproc 等多个进程，RLIMIT_NPROC 为 1 时不能再 fork
+fn nproc_child() -> i32 {
+    set_cur(RLIMIT_NPROC, 1);
+    assert_eq!(fork(), -EAGAIN);
+    0
+}
+
+fn stack_child() -> i32 {
+    set_cur(RLIMIT_STACK, 16 * 1024);
+    recurse(64);
+    unreachable!();
+}
+
+fn cpu_child() -> i32 {
+    set_cur(RLIMIT_CPU, 1);
+    loop {}
+}
+
+fn sleep_child() -> i32 {
+    sleep(100);
+    0
+}
+
+#[no_mangle]
+pub fn main(_argc: usize, _argv: &[&str]) -> i32 {
+    let mut limit = RLimit::default();
+    assert_eq!(getrlimit(RLIMIT_STACK, &mut limit), 0);
+    assert!(limit.cur > 0 && limit.cur <= limit.max);
+    assert_eq!(getrlimit(RLIMIT_AS, &mut limit), 0);
+    assert_eq!(limit.cur, RLIM_INFINITY);
+    assert_eq!(getrlimit(100, &mut limit), -EINVAL);
+
+    // 软限制不能超过硬限制，降低之后的硬限制不能再提高
+    assert_eq!(getrlimit(RLIMIT_NOFILE, &mut limit), 0);
+    let nofile = limit;
+    let bad = RLimit {
+        cur: nofile.max + 1,
+        max: nofile.max,
+    };
+    assert_eq!(setrlimit(RLIMIT_NOFILE, &bad), -EINVAL);
+    let lowered = RLimit {
+        cur: nofile.cur / 2,
+        max: nofile.cur,
+    };
+    assert_eq!(setrlimit(RLIMIT_NOFILE, &lowered), 0);
+    assert_eq!(setrlimit(RLIMIT_NOFILE, &nofile), -EPERM);
+    println!("getrlimit/setrlimit ok.");
+
+    // 子进程继承资源限制，prlimit 可以读写其他进程的资源限制
+    let pid = fork();
+    if pid == 0 {
+        exit(sleep_child());
+    }
+    let mut old = RLimit::default();
+    assert_eq!(
+        prlimit(pid as usize, RLIMIT_NOFILE, None, Some(&mut old)),
+        0
+    );
+    assert_eq!((old.cur, old.max), (lowered.cur, lowered.max));
+    let child_limit = RLimit { cur: 16, max: 32 };
+    assert_eq!(
+        prlimit(pid as usize, RLIMIT_NOFILE, Some(&child_limit), None),
+        0
+    );
+    assert_eq!(
+        prlimit(pid as usize, RLIMIT_NOFILE, None, Some(&mut old)),
+        0
+    );
+    assert_eq!((old.cur, old.max), (16, 32));
+    assert_eq!(prlimit(0, RLIMIT_NOFILE, None, Some(&mut old)), 0);
+    assert_eq!(old.cur, lowered.cur);
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(
+        prlimit(pid as usize, RLIMIT_NOFILE, None, Some(&mut old)),
+        -ESRCH
+    );
+    assert_eq!(
+        prlimit(getpid() as usize, RLIMIT_NOFILE, None, Some(&mut old)),
+        0
+    );
+    println!("prlimit ok.");
+
+    assert_eq!(run_child(as_child), 0);
+    println!("RLIMIT_AS ok.");
+    assert_eq!(run_child(nproc_child), 0);
+    println!("RLIMIT_NPROC ok.");
+    assert_eq!(run_child(stack_child), MEM_FAULT);
+    println!("RLIMIT_STACK ok.");
+    assert_eq!(run_child(cpu_child), CPU_LIMIT_EXCEEDED);
+    println!("RLIMIT_CPU ok.");
+    println!("rlimit_test passed!");
+    0
+}
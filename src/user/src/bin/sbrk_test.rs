@@ -0,0 +1,50 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+#[macro_use]
+extern crate user_lib;
+
+use alloc::vec::Vec;
+use user_lib::{brk, mprotect, munmap, sbrk, PROT_READ};
+
+const PAGE_SIZE: usize = 4096;
+const EINVAL: isize = 22;
+
+#[no_mangle]
+pub fn main(_argc: usize, _argv: &[&str]) -> i32 {
+    let origin_brk = sbrk(0);
+    assert!(origin_brk > 0);
+
+    // 手动增长 heap 并写入数据
+    assert_eq!(sbrk((PAGE_SIZE * 2) as isize), origin_brk);
+    let buf = unsafe { core::slice::from_raw_parts_mut(origin_brk as *mut u8, PAGE_SIZE * 2) };
+    buf.fill(0x5a);
+    assert!(buf.iter().all(|&b| b == 0x5a));
+
+    // 收缩 heap，brk 不能低于程序镜像的结束地址
+    assert_eq!(
+        sbrk(-((PAGE_SIZE * 2) as isize)),
+        origin_brk + (PAGE_SIZE * 2) as isize
+    );
+    assert_eq!(brk(1), origin_brk);
+    println!("sbrk ok.");
+
+    // heap 只能通过 brk 调整，munmap 和 mprotect 不能拆分或者移除它
+    let heap = origin_brk as usize;
+    assert_eq!(munmap(heap - PAGE_SIZE, PAGE_SIZE * 2), -EINVAL);
+    assert_eq!(sbrk(PAGE_SIZE as isize), origin_brk);
+    assert_eq!(mprotect(heap, PAGE_SIZE, PROT_READ), -EINVAL);
+    assert_eq!(sbrk(-(PAGE_SIZE as isize)), origin_brk + PAGE_SIZE as isize);
+    println!("heap is protected from munmap/mprotect.");
+
+    // 分配超过原来 16 KiB 静态 heap 的内存
+    let mut v: Vec<usize> = Vec::new();
+    for i in 0..(64 * 1024) {
+        v.push(i);
+    }
+    assert!(v.iter().enumerate().all(|(i, &x)| i == x));
+    println!("sbrk_test passed!");
+    0
+}
@@ -0,0 +1,101 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    fork, framestat, mprotect, shmat, shmctl, shmdt, shmget, waitpid, FrameStat, IPC_CREAT,
+    IPC_EXCL, IPC_PRIVATE, IPC_RMID, PROT_READ, PROT_WRITE, SHM_RDONLY,
+};
+
+const PAGE_SIZE: usize = 4096;
+const SHM_KEY: usize = 0x5348;
+const SHM_KEY_RMID: usize = 0x5349;
+const EACCES: isize = 13;
+const EINVAL: isize = 22;
+
+fn free_frames() -> usize {
+    let mut stat = FrameStat::default();
+    assert_eq!(framestat(&mut stat), 0);
+    stat.free
+}
+
+#[no_mangle]
+pub fn main(_argc: usize, _argv: &[&str]) -> i32 {
+    let len = PAGE_SIZE * 2;
+    let shmid = shmget(SHM_KEY, len, IPC_CREAT);
+    assert!(shmid > 0);
+    assert_eq!(shmget(SHM_KEY, len, 0), shmid);
+    assert!(shmget(SHM_KEY, len, IPC_CREAT | IPC_EXCL) < 0);
+    assert!(shmget(SHM_KEY, len * 2, 0) < 0);
+
+    // 没有被映射过的共享内存段在 IPC_RMID 时立即被释放
+    let private = shmget(IPC_PRIVATE, len, IPC_CREAT);
+    assert!(private > 0 && private != shmid);
+    let free = free_frames();
+    assert_eq!(shmctl(private as usize, IPC_RMID), 0);
+    assert!(free_frames() >= free + len / PAGE_SIZE);
+    assert!(shmat(private as usize, 0, 0) < 0);
+    assert_eq!(shmctl(private as usize, IPC_RMID), -EINVAL);
+
+    // 第一次 attach 失败时共享内存段不能被销毁
+    assert!(shmat(shmid as usize, 1, 0) < 0);
+    assert_eq!(shmget(SHM_KEY, len, 0), shmid);
+
+    let addr = shmat(shmid as usize, 0, 0);
+    assert!(addr > 0);
+    let buf = unsafe { core::slice::from_raw_parts_mut(addr as usize as *mut u8, len) };
+    buf[0] = 1;
+
+    // fork 之后父子进程仍然共享同一个共享内存段，子进程通过另一个映射写入
+    let pid = fork();
+    if pid == 0 {
+        let other = shmat(shmid as usize, 0, 0);
+        assert!(other > 0 && other != addr);
+        let other_buf = unsafe { core::slice::from_raw_parts_mut(other as usize as *mut u8, len) };
+        assert_eq!(other_buf[0], 1);
+        other_buf[len - 1] = 42;
+        assert_eq!(buf[len - 1], 42);
+        buf[0] = 2;
+        assert_eq!(shmdt(other as usize), 0);
+        return 0;
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    assert_eq!(buf[0], 2);
+    assert_eq!(buf[len - 1], 42);
+    println!("shared memory between processes ok.");
+
+    let readonly = shmat(shmid as usize, 0, SHM_RDONLY);
+    assert!(readonly > 0);
+    assert_eq!(unsafe { *(readonly as usize as *const u8) }, 2);
+    // 以只读方式映射的共享内存不能通过 mprotect 变为可写
+    assert_eq!(
+        mprotect(readonly as usize, len, PROT_READ | PROT_WRITE),
+        -EACCES
+    );
+    assert_eq!(mprotect(readonly as usize, len, PROT_READ), 0);
+    assert_eq!(shmdt(readonly as usize), 0);
+    assert!(shmdt(readonly as usize) < 0);
+
+    // 最后一个映射被解除之后共享内存段被释放
+    assert_eq!(shmdt(addr as usize), 0);
+    assert!(shmget(SHM_KEY, len, 0) < 0);
+
+    // IPC_RMID 之后不能再找到共享内存段，但是已经存在的映射仍然可以使用
+    let shmid = shmget(SHM_KEY_RMID, len, IPC_CREAT);
+    assert!(shmid > 0);
+    let addr = shmat(shmid as usize, 0, 0);
+    assert!(addr > 0);
+    assert_eq!(shmctl(shmid as usize, IPC_RMID), 0);
+    assert!(shmget(SHM_KEY_RMID, len, 0) < 0);
+    assert!(shmat(shmid as usize, 0, 0) < 0);
+    let buf = unsafe { core::slice::from_raw_parts_mut(addr as usize as *mut u8, len) };
+    buf[len - 1] = 7;
+    assert_eq!(buf[len - 1], 7);
+    assert_eq!(shmdt(addr as usize), 0);
+    println!("shm_test passed!");
+    0
+}
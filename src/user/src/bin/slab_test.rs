@@ -0,0 +1,82 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exit, fork, slabstat, sleep, waitpid, SlabStat};
+
+const MAX_STATS: usize = 64;
+const CHILDREN: usize = 16;
+
+// 每个进程都有自己的 TaskControlBlock、页表和逻辑段，它们分别从这些专用的缓存中分配
+const TYPED_CACHES: [&str; 3] = ["task", "page_table", "map_area"];
+
+// snapshot 返回统计信息的项数，并检查每一项
+fn snapshot(stats: &mut [SlabStat; MAX_STATS]) -> usize {
+    let len = slabstat(stats);
+    assert!(len > 0 && len as usize <= MAX_STATS);
+    let len = len as usize;
+    for stat in &stats[..len] {
+        assert!(!stat.name().is_empty());
+        assert!(stat.size > 0 && stat.slabs > 0);
+        assert!(stat.inuse <= stat.objects);
+    }
+    len
+}
+
+// inuse 返回名字为 name 的缓存中已经分配出去的对象个数
+fn inuse(stats: &[SlabStat], name: &str) -> usize {
+    stats
+        .iter()
+        .filter(|stat| stat.name() == name)
+        .map(|stat| stat.inuse)
+        .sum()
+}
+
+#[no_mangle]
+pub fn main(_argc: usize, _argv: &[&str]) -> i32 {
+    let mut before = [SlabStat::default(); MAX_STATS];
+    let before_len = snapshot(&mut before);
+
+    let mut pids = [0isize; CHILDREN];
+    for pid in pids.iter_mut() {
+        *pid = fork();
+        if *pid == 0 {
+            sleep(100);
+            exit(0);
+        }
+        assert!(*pid > 0);
+    }
+    let mut busy = [SlabStat::default(); MAX_STATS];
+    let busy_len = snapshot(&mut busy);
+    println!("slab caches with {} children:", CHILDREN);
+    for stat in &busy[..busy_len] {
+        println!(
+            "  {:<10} size {:>4}: slabs {:>3}, objects {:>4}, inuse {:>4}",
+            stat.name(),
+            stat.size,
+            stat.slabs,
+            stat.objects,
+            stat.inuse
+        );
+    }
+    // 每个子进程的 TaskControlBlock 都在 task 缓存中
+    assert!(inuse(&busy[..busy_len], "task") >= inuse(&before[..before_len], "task") + CHILDREN);
+    for name in TYPED_CACHES {
+        assert!(inuse(&busy[..busy_len], name) > inuse(&before[..before_len], name));
+    }
+
+    for &pid in pids.iter() {
+        let mut exit_code = 0;
+        assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+        assert_eq!(exit_code, 0);
+    }
+    let mut after = [SlabStat::default(); MAX_STATS];
+    let after_len = snapshot(&mut after);
+    for name in TYPED_CACHES {
+        assert!(inuse(&after[..after_len], name) < inuse(&busy[..busy_len], name));
+    }
+    println!("slab_test passed!");
+    0
+}
@@ -0,0 +1,41 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{fork, waitpid};
+
+const MEM_FAULT: i32 = -2;
+
+// 每一层递归占用 1 KiB 的栈空间
+fn recurse(depth: usize) -> usize {
+    let buf = [depth as u8; 1024];
+    let buf = unsafe { core::ptr::read_volatile(&buf) };
+    if depth == 0 {
+        return buf[0] as usize;
+    }
+    recurse(depth - 1) + buf[1023] as usize
+}
+
+#[no_mangle]
+pub fn main(_argc: usize, _argv: &[&str]) -> i32 {
+    // 1 MiB 远远超过初始的 8 KiB，user stack 会在 page fault 时自动增长
+    let depth = 1024;
+    let expected: usize = (0..=depth).map(|i| i as u8 as usize).sum();
+    assert_eq!(recurse(depth), expected);
+    println!("deep recursion ok.");
+
+    // 无限递归最终会超过 user stack 的最大长度，子进程被内核杀死
+    let pid = fork();
+    if pid == 0 {
+        recurse(usize::MAX);
+        unreachable!();
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, MEM_FAULT);
+    println!("stack overflow detected.");
+    println!("stack_test passed!");
+    0
+}
@@ -0,0 +1,50 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{fork, mmap, munmap, waitpid, MAP_ANONYMOUS, MAP_PRIVATE, PROT_READ, PROT_WRITE};
+
+const PAGE_SIZE: usize = 4096;
+// 使用 `make run MEMORY=16M` 启动时，8 MiB 超过了可用的物理页框，一部分页会被换出
+const PAGE_COUNT: usize = 2048;
+
+fn check(buf: &[u8]) {
+    for page in 0..PAGE_COUNT {
+        let offset = page * PAGE_SIZE;
+        assert_eq!(buf[offset], page as u8);
+        assert_eq!(buf[offset + PAGE_SIZE - 1], (page >> 8) as u8);
+    }
+}
+
+#[no_mangle]
+pub fn main(_argc: usize, _argv: &[&str]) -> i32 {
+    let len = PAGE_SIZE * PAGE_COUNT;
+    let start = mmap(0, len, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS);
+    assert!(start > 0);
+    let buf = unsafe { core::slice::from_raw_parts_mut(start as usize as *mut u8, len) };
+    for page in 0..PAGE_COUNT {
+        let offset = page * PAGE_SIZE;
+        buf[offset] = page as u8;
+        buf[offset + PAGE_SIZE - 1] = (page >> 8) as u8;
+    }
+    check(buf);
+    println!("swap in/out ok.");
+
+    // 子进程和父进程共享被换出的页
+    let pid = fork();
+    if pid == 0 {
+        check(buf);
+        return 0;
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    check(buf);
+    println!("swap after fork ok.");
+
+    assert_eq!(munmap(start as usize, len), 0);
+    println!("swap_test passed!");
+    0
+}
@@ -0,0 +1,37 @@
+#![no_std]
+#![no_main]
+#![feature(thread_local)]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::arch::asm;
+
+#[thread_local]
+static mut COUNTER: usize = 42;
+#[thread_local]
+static mut ZEROED: [usize; 16] = [0; 16];
+
+#[no_mangle]
+pub fn main(_argc: usize, _argv: &[&str]) -> i32 {
+    let tp: usize;
+    unsafe {
+        asm!("mv {}, tp", out(reg) tp);
+    }
+    println!("tp = {:#x}", tp);
+    assert!(tp != 0);
+    unsafe {
+        // .tdata 中的变量带有初始值，.tbss 中的变量被清零
+        assert_eq!(COUNTER, 42);
+        assert!(ZEROED.iter().all(|x| *x == 0));
+        for _ in 0..100 {
+            COUNTER += 1;
+            // 让出 CPU，tp 在切换进程之后仍然保持不变
+            user_lib::yield_();
+        }
+        ZEROED[15] = COUNTER;
+        assert_eq!(ZEROED[15], 142);
+    }
+    println!("tls_test passed!");
+    0
+}
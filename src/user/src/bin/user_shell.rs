@@ -13,11 +13,41 @@ const DL: u8 = 0x7fu8;
 const BS: u8 = 0x08u8;
 
 use alloc::string::String;
+use alloc::vec::Vec;
 use user_lib::console::getchar;
-use user_lib::{exec, fork, waitpid};
+use user_lib::{env_iter, exec, fork, setenv, unsetenv, waitpid};
+
+// run_builtin 执行 shell 内置命令，返回 false 表示不是内置命令
+// - export：列出全部环境变量
+// - export KEY=VALUE ...：设置环境变量，之后启动的程序都会继承它们
+// - unset KEY ...：删除环境变量
+fn run_builtin(words: &[&str]) -> bool {
+    match words[0] {
+        "export" if words.len() == 1 => {
+            for (key, value) in env_iter() {
+                println!("{}={}", key, value);
+            }
+        }
+        "export" => {
+            for word in &words[1..] {
+                match word.split_once('=') {
+                    Some((key, value)) if setenv(key, value) => {}
+                    _ => println!("export: invalid argument {}", word),
+                }
+            }
+        }
+        "unset" => {
+            for key in &words[1..] {
+                unsetenv(key);
+            }
+        }
+        _ => return false,
+    }
+    true
+}
 
 #[no_mangle]
-pub fn main() -> i32 {
+pub fn main(_argc: usize, _argv: &[&str]) -> i32 {
     println!("[user_shell] Hello, welcome to the user shell!");
     let mut line: String = String::new();
     print!(">> ");
@@ -26,12 +56,24 @@ pub fn main() -> i32 {
         match c {
             LF | CR => {
                 println!("");
-                if !line.is_empty() {
-                    line.push('\0');
+                let words: Vec<&str> = line.split_whitespace().collect();
+                if !words.is_empty() && !run_builtin(&words) {
+                    // 按照空白字符拆分参数，argv[0] 就是程序名
+                    let args: Vec<String> = words
+                        .iter()
+                        .map(|&arg| {
+                            let mut arg = String::from(arg);
+                            arg.push('\0');
+                            arg
+                        })
+                        .collect();
+                    let mut args_addr: Vec<*const u8> =
+                        args.iter().map(|arg| arg.as_ptr()).collect();
+                    args_addr.push(core::ptr::null());
                     let pid = fork();
                     if pid == 0 {
                         // child process
-                        if exec(line.as_str()) == -1 {
+                        if exec(args[0].as_str(), args_addr.as_slice()) < 0 {
                             println!("Error when executing!");
                             return -4;
                         }
@@ -42,8 +84,8 @@ pub fn main() -> i32 {
                         assert_eq!(pid, exit_pid);
                         println!("[user_shell] Process {} exited with code {}", pid, exit_code);
                     }
-                    line.clear();
                 }
+                line.clear();
                 print!(">> ");
             }
             BS | DL => {
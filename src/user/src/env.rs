@@ -0,0 +1,77 @@
+// 环境变量保存在用户地址空间中，fork 时随着地址空间一起被复制，
+// exec 时通过 envp 传递给新的程序。
+use alloc::{string::String, vec::Vec};
+use core::cell::UnsafeCell;
+
+// 用户程序只有一个线程，所以不需要加锁
+struct Environ(UnsafeCell<Vec<String>>);
+
+unsafe impl Sync for Environ {}
+
+// ENVIRON 中的每一项都是 KEY=VALUE 的形式
+static ENVIRON: Environ = Environ(UnsafeCell::new(Vec::new()));
+
+fn environ() -> &'static mut Vec<String> {
+    unsafe { &mut *ENVIRON.0.get() }
+}
+
+// init 由 _start 调用，使用内核传递的 envp 初始化环境变量
+pub(crate) fn init(envp: &[&str]) {
+    environ().extend(envp.iter().map(|&env| String::from(env)));
+}
+
+fn position(key: &str) -> Option<usize> {
+    environ()
+        .iter()
+        .position(|env| env.split_once('=').map_or(false, |(k, _)| k == key))
+}
+
+pub fn getenv(key: &str) -> Option<String> {
+    let idx = position(key)?;
+    environ()[idx]
+        .split_once('=')
+        .map(|(_, value)| String::from(value))
+}
+
+// setenv 设置环境变量 key 的值，key 为空或者包含 '=' 时返回 false
+pub fn setenv(key: &str, value: &str) -> bool {
+    if key.is_empty() || key.contains('=') || key.contains('\0') || value.contains('\0') {
+        return false;
+    }
+    let mut env = String::from(key);
+    env.push('=');
+    env.push_str(value);
+    match position(key) {
+        Some(idx) => environ()[idx] = env,
+        None => environ().push(env),
+    }
+    true
+}
+
+pub fn unsetenv(key: &str) {
+    if let Some(idx) = position(key) {
+        environ().remove(idx);
+    }
+}
+
+// env_iter 返回当前全部环境变量 (key, value) 的快照
+pub fn env_iter() -> impl Iterator<Item = (String, String)> {
+    environ()
+        .iter()
+        .filter_map(|env| env.split_once('='))
+        .map(|(key, value)| (String::from(key), String::from(value)))
+        .collect::<Vec<_>>()
+        .into_iter()
+}
+
+// envp 返回以 '\0' 结尾的环境变量字符串，exec 使用它们构造 envp 数组
+pub(crate) fn envp() -> Vec<String> {
+    environ()
+        .iter()
+        .map(|env| {
+            let mut env = env.clone();
+            env.push('\0');
+            env
+        })
+        .collect()
+}
@@ -0,0 +1,55 @@
+use super::sbrk;
+use buddy_system_allocator::{Heap, LockedHeap};
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    ptr::NonNull,
+};
+
+const PAGE_SIZE: usize = 4096;
+// 每次向内核申请内存的最小长度
+const HEAP_GROW_SIZE: usize = PAGE_SIZE * 4;
+
+// UserHeap 在 buddy allocator 分配失败时通过 sbrk 向内核申请更多的内存，
+// 而不是直接调用 handle_alloc_error。
+pub struct UserHeap(LockedHeap);
+
+impl UserHeap {
+    pub const fn empty() -> Self {
+        Self(LockedHeap::empty())
+    }
+}
+
+// grow 通过 sbrk 扩大 heap，新申请的内存至少可以满足 layout 的大小和对齐要求。
+// 返回 false 表示内核拒绝了本次申请。
+fn grow(heap: &mut Heap, layout: &Layout) -> bool {
+    // buddy allocator 需要一块按照自身大小对齐的内存，申请两倍的长度可以保证
+    // 新的内存中一定存在这样一块内存。
+    let size = layout.size().max(layout.align()).next_power_of_two() * 2;
+    let size = (size.max(HEAP_GROW_SIZE) + PAGE_SIZE - 1) & !(PAGE_SIZE - 1);
+    let start = sbrk(size as isize);
+    if start < 0 {
+        return false;
+    }
+    unsafe {
+        heap.add_to_heap(start as usize, start as usize + size);
+    }
+    true
+}
+
+unsafe impl GlobalAlloc for UserHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let mut heap = self.0.lock();
+        loop {
+            if let Ok(ptr) = heap.alloc(layout) {
+                return ptr.as_ptr();
+            }
+            if !grow(&mut heap, &layout) {
+                return core::ptr::null_mut();
+            }
+        }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.0.lock().dealloc(NonNull::new_unchecked(ptr), layout)
+    }
+}
@@ -5,38 +5,90 @@
 
 #[macro_use]
 pub mod console;
+mod env;
+mod heap_allocator;
 mod lang_items;
 mod syscall;
 
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+// heap 初始为空，分配失败时通过 sbrk 向内核申请内存
 #[global_allocator]
-static HEAP: LockedHeap = LockedHeap::empty();
+static HEAP: UserHeap = UserHeap::empty();
 
 #[alloc_error_handler]
 pub fn handle_alloc_error(layout: core::alloc::Layout) -> ! {
     panic!("Heap allocation error, layout = {:?}", layout);
 }
 
-const USER_HEAP_SIZE: usize = 16384;
-static mut HEAP_SPACE: [u8; USER_HEAP_SIZE] = [0; USER_HEAP_SIZE];
+// auxv 在用户栈上的地址，由 _start 设置
+static AUXV: AtomicUsize = AtomicUsize::new(0);
+
+pub const AT_NULL: usize = 0;
+pub const AT_PHDR: usize = 3;
+pub const AT_PHENT: usize = 4;
+pub const AT_PHNUM: usize = 5;
+pub const AT_PAGESZ: usize = 6;
+pub const AT_BASE: usize = 7;
+pub const AT_ENTRY: usize = 9;
 
+// 内核将 argc 和 argv 的地址分别放在 a0 和 a1 中，argv、envp 和 auxv 在用户栈上依次排列
 #[no_mangle]
 #[link_section = ".text.entry"]
-pub extern "C" fn _start() -> ! {
-    unsafe {
-        HEAP.lock()
-            .init(HEAP_SPACE.as_ptr() as usize, USER_HEAP_SIZE);
-    }
-    exit(main());
+pub extern "C" fn _start(argc: usize, argv: usize) -> ! {
+    let argv_ptrs = unsafe { core::slice::from_raw_parts(argv as *const usize, argc) };
+    let args: Vec<&'static str> = argv_ptrs.iter().map(|&ptr| unsafe { c_str(ptr) }).collect();
+    // envp 紧跟在 argv 结尾的空指针之后，auxv 紧跟在 envp 结尾的空指针之后
+    let envp = argv + (argc + 1) * core::mem::size_of::<usize>();
+    let envc = (0usize..)
+        .find(|&i| unsafe { *(envp as *const usize).add(i) } == 0)
+        .unwrap();
+    let envp_ptrs = unsafe { core::slice::from_raw_parts(envp as *const usize, envc) };
+    let envs: Vec<&'static str> = envp_ptrs.iter().map(|&ptr| unsafe { c_str(ptr) }).collect();
+    env::init(envs.as_slice());
+    AUXV.store(
+        envp + (envc + 1) * core::mem::size_of::<usize>(),
+        Ordering::Relaxed,
+    );
+    exit(main(argc, args.as_slice()));
     panic!("unreachable after sys_exit!");
 }
 
+// c_str 将以 '\0' 结尾的字符串转换为 &str
+unsafe fn c_str(ptr: usize) -> &'static str {
+    let len = (0usize..)
+        .find(|&i| *((ptr + i) as *const u8) == 0)
+        .unwrap();
+    core::str::from_utf8(core::slice::from_raw_parts(ptr as *const u8, len)).unwrap()
+}
+
+// 每个程序都将 main 定义为 fn main(argc: usize, argv: &[&str]) -> i32，_start 按照这个签名调用它
 #[linkage = "weak"]
 #[no_mangle]
-fn main() -> i32 {
+fn main(_argc: usize, _argv: &[&str]) -> i32 {
     panic!("Cannot find main!");
 }
 
-use buddy_system_allocator::LockedHeap;
+// getauxval 返回 auxv 中 key 对应的值，不存在时返回 None
+pub fn getauxval(key: usize) -> Option<usize> {
+    let mut auxv = AUXV.load(Ordering::Relaxed) as *const usize;
+    loop {
+        let (k, v) = unsafe { (*auxv, *auxv.add(1)) };
+        if k == AT_NULL {
+            return None;
+        }
+        if k == key {
+            return Some(v);
+        }
+        auxv = unsafe { auxv.add(2) };
+    }
+}
+
+pub use env::{env_iter, getenv, setenv, unsetenv};
+use heap_allocator::UserHeap;
 use syscall::*;
 
 const WAITPID_ANY_PID: isize = -1;
@@ -44,6 +96,104 @@ const WAITPID_ANY_PID: isize = -1;
 pub const WAITPID_NO_CHILDREN_RUNNING: isize = -1;
 pub const WAITPID_CHILDREN_RUNNING: isize = -2;
 
+pub const PROT_NONE: usize = 0;
+pub const PROT_READ: usize = 1 << 0;
+pub const PROT_WRITE: usize = 1 << 1;
+pub const PROT_EXEC: usize = 1 << 2;
+
+pub const MAP_SHARED: usize = 0x01;
+pub const MAP_PRIVATE: usize = 0x02;
+pub const MAP_FIXED: usize = 0x10;
+pub const MAP_ANONYMOUS: usize = 0x20;
+
+pub const MS_ASYNC: usize = 1;
+pub const MS_SYNC: usize = 4;
+
+pub const O_RDONLY: usize = 0;
+pub const O_RDWR: usize = 2;
+
+// personality 的查询参数，以及表示地址空间没有随机化的标志
+pub const PERSONALITY_QUERY: usize = 0xffff_ffff;
+pub const ADDR_NO_RANDOMIZE: usize = 0x0040000;
+
+pub const IPC_PRIVATE: usize = 0;
+pub const IPC_CREAT: usize = 0o1000;
+pub const IPC_EXCL: usize = 0o2000;
+pub const IPC_RMID: usize = 0;
+
+pub const SHM_RDONLY: usize = 0o10000;
+pub const SHM_RND: usize = 0o20000;
+
+pub const RLIMIT_CPU: usize = 0;
+pub const RLIMIT_STACK: usize = 3;
+pub const RLIMIT_NPROC: usize = 6;
+pub const RLIMIT_NOFILE: usize = 7;
+pub const RLIMIT_AS: usize = 9;
+pub const RLIM_INFINITY: usize = usize::MAX;
+
+// CPU 时间超过 RLIMIT_CPU 被内核杀死的进程的退出码
+pub const CPU_LIMIT_EXCEEDED: i32 = -5;
+
+// RLimit 是一种资源的软限制 (cur) 和硬限制 (max)
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct RLimit {
+    pub cur: usize,
+    pub max: usize,
+}
+
+// MemoryStat 是当前进程地址空间的内存使用情况，单位都是页
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct MemoryStat {
+    pub vsz: usize,
+    pub rss: usize,
+    pub swap: usize,
+    pub page_table: usize,
+    pub peak_rss: usize,
+}
+
+// FrameStat 是全局的物理页框使用情况，单位是页
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct FrameStat {
+    pub total: usize,
+    pub free: usize,
+}
+
+// HeapStat 是内核堆的使用情况，单位是字节，grown 是从页框分配器扩展出来的部分
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct HeapStat {
+    pub total: usize,
+    pub grown: usize,
+    pub allocated: usize,
+    pub requested: usize,
+}
+
+// SlabStat 是内核中一个 slab 缓存的一个大小分类的使用情况，name 是缓存的名字，size 是对象
+// 大小，objects 是全部 slab 中对象的个数，inuse 是已经分配出去的个数
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct SlabStat {
+    pub name: [u8; 16],
+    pub size: usize,
+    pub slabs: usize,
+    pub objects: usize,
+    pub inuse: usize,
+}
+
+impl SlabStat {
+    pub fn name(&self) -> &str {
+        let len = self
+            .name
+            .iter()
+            .position(|&b| b == 0)
+            .unwrap_or(self.name.len());
+        core::str::from_utf8(&self.name[..len]).unwrap()
+    }
+}
+
 pub fn write(fd: usize, buf: &[u8]) -> isize {
     sys_write(fd, buf)
 }
@@ -64,12 +214,22 @@ pub fn getpid() -> isize {
     sys_getpid()
 }
 
+pub fn personality(persona: usize) -> isize {
+    sys_personality(persona)
+}
+
 pub fn fork() -> isize {
     sys_fork()
 }
 
-pub fn exec(path: &str) -> isize {
-    sys_exec(path)
+// exec 执行 path 对应的程序，path 和 args 中的字符串都需要以 '\0' 结尾，
+// args 以空指针结尾，当前的环境变量会被传递给新的程序。
+// 成功时不会返回，程序不存在或者不是合法的 elf 文件时返回负数。
+pub fn exec(path: &str, args: &[*const u8]) -> isize {
+    let envs = env::envp();
+    let mut envp: Vec<*const u8> = envs.iter().map(|env| env.as_ptr()).collect();
+    envp.push(core::ptr::null());
+    sys_exec(path, args, envp.as_slice())
 }
 
 // wait for all children to exit
@@ -98,6 +258,29 @@ pub fn waitpid(pid: usize, exit_code: &mut i32) -> isize {
     }
 }
 
+pub fn getrlimit(resource: usize, rlim: &mut RLimit) -> isize {
+    sys_getrlimit(resource, rlim as *mut _)
+}
+
+pub fn setrlimit(resource: usize, rlim: &RLimit) -> isize {
+    sys_setrlimit(resource, rlim as *const _)
+}
+
+// prlimit 读取并修改 pid 对应进程的资源限制，pid 为 0 表示当前进程
+pub fn prlimit(
+    pid: usize,
+    resource: usize,
+    new_limit: Option<&RLimit>,
+    old_limit: Option<&mut RLimit>,
+) -> isize {
+    sys_prlimit(
+        pid,
+        resource,
+        new_limit.map_or(core::ptr::null(), |limit| limit as *const _),
+        old_limit.map_or(core::ptr::null_mut(), |limit| limit as *mut _),
+    )
+}
+
 pub fn sleep(duration: usize) {
     let start = get_time();
     while get_time() - start < duration as isize {
@@ -108,3 +291,110 @@ pub fn sleep(duration: usize) {
 pub fn read(fd: usize, buf: &mut [u8]) -> isize {
     sys_read(fd, buf)
 }
+
+// mmap 创建匿名映射，成功时返回映射的起始地址，失败时返回负数错误码
+pub fn mmap(start: usize, len: usize, prot: usize, flags: usize) -> isize {
+    sys_mmap(start, len, prot, flags, usize::MAX, 0)
+}
+
+// mmap_file 映射 fd 从 offset 开始的内容，offset 需要页对齐
+pub fn mmap_file(
+    start: usize,
+    len: usize,
+    prot: usize,
+    flags: usize,
+    fd: usize,
+    offset: usize,
+) -> isize {
+    sys_mmap(start, len, prot, flags, fd, offset)
+}
+
+// msync 将共享文件映射中被修改过的页写回文件
+pub fn msync(start: usize, len: usize, flags: usize) -> isize {
+    sys_msync(start, len, flags)
+}
+
+// open 以只读方式打开用户程序，path 需要以 '\0' 结尾，成功时返回文件描述符
+pub fn open(path: &str, flags: usize) -> isize {
+    sys_open(path, flags)
+}
+
+pub fn close(fd: usize) -> isize {
+    sys_close(fd)
+}
+
+// pread 从文件的 offset 处读取数据，返回读取的字节数
+pub fn pread(fd: usize, buf: &mut [u8], offset: usize) -> isize {
+    sys_pread(fd, buf, offset)
+}
+
+pub fn ftruncate(fd: usize, len: usize) -> isize {
+    sys_ftruncate(fd, len)
+}
+
+// memfd_create 创建一个长度为 0 的内存文件，name 需要以 '\0' 结尾
+pub fn memfd_create(name: &str, flags: usize) -> isize {
+    sys_memfd_create(name, flags)
+}
+
+pub fn munmap(start: usize, len: usize) -> isize {
+    sys_munmap(start, len)
+}
+
+pub fn mprotect(start: usize, len: usize, prot: usize) -> isize {
+    sys_mprotect(start, len, prot)
+}
+
+// brk 将 heap 的结束地址设置为 addr，返回新的结束地址，失败时返回当前的结束地址
+pub fn brk(addr: usize) -> isize {
+    sys_brk(addr)
+}
+
+// shmget 返回 key 对应的共享内存段的 shmid，失败时返回负数错误码
+pub fn shmget(key: usize, size: usize, shmflg: usize) -> isize {
+    sys_shmget(key, size, shmflg)
+}
+
+// shmat 映射共享内存段，shmaddr 为 0 时由内核选择地址，成功时返回映射的起始地址
+pub fn shmat(shmid: usize, shmaddr: usize, shmflg: usize) -> isize {
+    sys_shmat(shmid, shmaddr, shmflg)
+}
+
+pub fn shmdt(shmaddr: usize) -> isize {
+    sys_shmdt(shmaddr)
+}
+
+// shmctl 目前只支持 IPC_RMID，删除共享内存段之后已经存在的映射仍然可以使用
+pub fn shmctl(shmid: usize, cmd: usize) -> isize {
+    sys_shmctl(shmid, cmd)
+}
+
+// sbrk 将 heap 增长 (或者收缩) increment 个字节，成功时返回原来的结束地址，失败时返回 -1
+pub fn sbrk(increment: isize) -> isize {
+    let old_brk = sys_brk(0);
+    if increment == 0 {
+        return old_brk;
+    }
+    let new_brk = old_brk + increment;
+    if new_brk < 0 || sys_brk(new_brk as usize) != new_brk {
+        return -1;
+    }
+    old_brk
+}
+
+pub fn memstat(stat: &mut MemoryStat) -> isize {
+    sys_memstat(stat as *mut _)
+}
+
+pub fn framestat(stat: &mut FrameStat) -> isize {
+    sys_framestat(stat as *mut _)
+}
+
+pub fn heapstat(stat: &mut HeapStat) -> isize {
+    sys_heapstat(stat as *mut _)
+}
+
+// slabstat 将内核 slab 缓存的使用情况写入 stats，返回内核中 slab 缓存的个数
+pub fn slabstat(stats: &mut [SlabStat]) -> isize {
+    sys_slabstat(stats.as_mut_ptr(), stats.len())
+}
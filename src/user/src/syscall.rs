@@ -1,14 +1,38 @@
 use core::arch::asm;
 
+use crate::{FrameStat, HeapStat, MemoryStat, RLimit, SlabStat};
+
+const SYSCALL_FTRUNCATE: usize = 46;
+const SYSCALL_OPEN: usize = 56;
+const SYSCALL_CLOSE: usize = 57;
 const SYSCALL_READ: usize = 63;
 const SYSCALL_WRITE: usize = 64;
+const SYSCALL_PREAD: usize = 67;
+const SYSCALL_PERSONALITY: usize = 92;
 const SYSCALL_EXIT: usize = 93;
 const SYSCALL_YIELD: usize = 124;
+const SYSCALL_GETRLIMIT: usize = 163;
+const SYSCALL_SETRLIMIT: usize = 164;
 const SYSCALL_GET_TIME: usize = 169;
 const SYSCALL_GETPID: usize = 172;
+const SYSCALL_SHMGET: usize = 194;
+const SYSCALL_SHMCTL: usize = 195;
+const SYSCALL_SHMAT: usize = 196;
+const SYSCALL_SHMDT: usize = 197;
+const SYSCALL_BRK: usize = 214;
+const SYSCALL_MUNMAP: usize = 215;
 const SYSCALL_FORK: usize = 220;
 const SYSCALL_EXEC: usize = 221;
+const SYSCALL_MMAP: usize = 222;
+const SYSCALL_MPROTECT: usize = 226;
+const SYSCALL_MSYNC: usize = 227;
 const SYSCALL_WAITPID: usize = 260;
+const SYSCALL_PRLIMIT: usize = 261;
+const SYSCALL_MEMFD_CREATE: usize = 279;
+const SYSCALL_MEMSTAT: usize = 500;
+const SYSCALL_FRAMESTAT: usize = 501;
+const SYSCALL_HEAPSTAT: usize = 502;
+const SYSCALL_SLABSTAT: usize = 503;
 
 fn syscall(id: usize, args: [usize; 3]) -> isize {
     let mut ret: isize;
@@ -27,6 +51,24 @@ fn syscall(id: usize, args: [usize; 3]) -> isize {
     ret
 }
 
+// syscall6 用于参数多于 3 个的系统调用，比如 mmap
+fn syscall6(id: usize, args: [usize; 6]) -> isize {
+    let mut ret: isize;
+    unsafe {
+        asm!(
+            "ecall",
+            inlateout("x10") args[0] => ret,
+            in("x11") args[1],
+            in("x12") args[2],
+            in("x13") args[3],
+            in("x14") args[4],
+            in("x15") args[5],
+            in("x17") id
+        );
+    }
+    ret
+}
+
 pub fn sys_write(fd: usize, buffer: &[u8]) -> isize {
     syscall(SYSCALL_WRITE, [fd, buffer.as_ptr() as usize, buffer.len()])
 }
@@ -47,21 +89,134 @@ pub fn sys_getpid() -> isize {
     syscall(SYSCALL_GETPID, [0, 0, 0])
 }
 
+pub fn sys_personality(persona: usize) -> isize {
+    syscall(SYSCALL_PERSONALITY, [persona, 0, 0])
+}
+
 pub fn sys_fork() -> isize {
     syscall(SYSCALL_FORK, [0, 0, 0])
 }
 
-pub fn sys_exec(path: &str) -> isize {
-    syscall(SYSCALL_EXEC, [path.as_ptr() as usize, 0, 0])
+pub fn sys_exec(path: &str, args: &[*const u8], envp: &[*const u8]) -> isize {
+    syscall(
+        SYSCALL_EXEC,
+        [
+            path.as_ptr() as usize,
+            args.as_ptr() as usize,
+            envp.as_ptr() as usize,
+        ],
+    )
 }
 
 pub fn sys_waitpid(pid: isize, exit_code: *mut i32) -> isize {
     syscall(SYSCALL_WAITPID, [pid as usize, exit_code as usize, 0])
 }
 
+pub fn sys_getrlimit(resource: usize, rlim: *mut RLimit) -> isize {
+    syscall(SYSCALL_GETRLIMIT, [resource, rlim as usize, 0])
+}
+
+pub fn sys_setrlimit(resource: usize, rlim: *const RLimit) -> isize {
+    syscall(SYSCALL_SETRLIMIT, [resource, rlim as usize, 0])
+}
+
+pub fn sys_prlimit(
+    pid: usize,
+    resource: usize,
+    new_limit: *const RLimit,
+    old_limit: *mut RLimit,
+) -> isize {
+    syscall6(
+        SYSCALL_PRLIMIT,
+        [pid, resource, new_limit as usize, old_limit as usize, 0, 0],
+    )
+}
+
 pub fn sys_read(fd: usize, buffer: &mut [u8]) -> isize {
     syscall(
         SYSCALL_READ,
         [fd, buffer.as_mut_ptr() as usize, buffer.len()],
     )
 }
+
+pub fn sys_mmap(
+    start: usize,
+    len: usize,
+    prot: usize,
+    flags: usize,
+    fd: usize,
+    offset: usize,
+) -> isize {
+    syscall6(SYSCALL_MMAP, [start, len, prot, flags, fd, offset])
+}
+
+pub fn sys_msync(start: usize, len: usize, flags: usize) -> isize {
+    syscall(SYSCALL_MSYNC, [start, len, flags])
+}
+
+pub fn sys_open(path: &str, flags: usize) -> isize {
+    syscall(SYSCALL_OPEN, [path.as_ptr() as usize, flags, 0])
+}
+
+pub fn sys_close(fd: usize) -> isize {
+    syscall(SYSCALL_CLOSE, [fd, 0, 0])
+}
+
+pub fn sys_pread(fd: usize, buffer: &mut [u8], offset: usize) -> isize {
+    syscall6(
+        SYSCALL_PREAD,
+        [fd, buffer.as_mut_ptr() as usize, buffer.len(), offset, 0, 0],
+    )
+}
+
+pub fn sys_ftruncate(fd: usize, len: usize) -> isize {
+    syscall(SYSCALL_FTRUNCATE, [fd, len, 0])
+}
+
+pub fn sys_memfd_create(name: &str, flags: usize) -> isize {
+    syscall(SYSCALL_MEMFD_CREATE, [name.as_ptr() as usize, flags, 0])
+}
+
+pub fn sys_munmap(start: usize, len: usize) -> isize {
+    syscall(SYSCALL_MUNMAP, [start, len, 0])
+}
+
+pub fn sys_mprotect(start: usize, len: usize, prot: usize) -> isize {
+    syscall(SYSCALL_MPROTECT, [start, len, prot])
+}
+
+pub fn sys_brk(addr: usize) -> isize {
+    syscall(SYSCALL_BRK, [addr, 0, 0])
+}
+
+pub fn sys_shmget(key: usize, size: usize, shmflg: usize) -> isize {
+    syscall(SYSCALL_SHMGET, [key, size, shmflg])
+}
+
+pub fn sys_shmctl(shmid: usize, cmd: usize) -> isize {
+    syscall(SYSCALL_SHMCTL, [shmid, cmd, 0])
+}
+
+pub fn sys_shmat(shmid: usize, shmaddr: usize, shmflg: usize) -> isize {
+    syscall(SYSCALL_SHMAT, [shmid, shmaddr, shmflg])
+}
+
+pub fn sys_shmdt(shmaddr: usize) -> isize {
+    syscall(SYSCALL_SHMDT, [shmaddr, 0, 0])
+}
+
+pub fn sys_memstat(stat: *mut MemoryStat) -> isize {
+    syscall(SYSCALL_MEMSTAT, [stat as usize, 0, 0])
+}
+
+pub fn sys_framestat(stat: *mut FrameStat) -> isize {
+    syscall(SYSCALL_FRAMESTAT, [stat as usize, 0, 0])
+}
+
+pub fn sys_heapstat(stat: *mut HeapStat) -> isize {
+    syscall(SYSCALL_HEAPSTAT, [stat as usize, 0, 0])
+}
+
+pub fn sys_slabstat(stats: *mut SlabStat, len: usize) -> isize {
+    syscall(SYSCALL_SLABSTAT, [stats as usize, len, 0])
+}